@@ -50,6 +50,7 @@ impl std::fmt::Display for IndividualMarkdown<'_> {
                                 TestResultType::CorrectNonTerminated { .. } => {
                                     "Correct<sup>*</sup>"
                                 }
+                                TestResultType::ProbablyCorrect { .. } => "Correct<sup>?</sup>",
                                 TestResultType::Mismatch { .. } => "Mismatch",
                                 TestResultType::TimeOut => "Time out",
                                 TestResultType::Error { .. } => "Error",
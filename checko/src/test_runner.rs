@@ -174,6 +174,7 @@ pub struct TestRunResultsSection {
 pub enum TestResultType {
     CorrectTerminated,
     CorrectNonTerminated { iterations: u64 },
+    ProbablyCorrect { reason: String },
     Mismatch { reason: String },
     TimeOut,
     Error { description: String },
@@ -192,7 +193,9 @@ impl TestResultType {
     pub fn is_correct(&self) -> bool {
         matches!(
             self,
-            TestResultType::CorrectTerminated | TestResultType::CorrectNonTerminated { .. }
+            TestResultType::CorrectTerminated
+                | TestResultType::CorrectNonTerminated { .. }
+                | TestResultType::ProbablyCorrect { .. }
         )
     }
 }
@@ -214,13 +217,19 @@ impl GroupResults<'_> {
 
         for key in config.envs.keys() {
             match key {
-                // NOTE: Skip graph
-                Analysis::Graph => {}
+                Analysis::Graph => results.push(&env::GraphEnv).await,
                 Analysis::Parse => results.push(&env::ParseEnv).await,
                 Analysis::Interpreter => results.push(&env::InterpreterEnv).await,
                 Analysis::ProgramVerification => results.push(&env::ProgramVerificationEnv).await,
                 Analysis::Sign => results.push(&env::SignEnv).await,
+                Analysis::Interval => results.push(&env::IntervalEnv).await,
+                Analysis::LiveVariables => results.push(&env::LiveVariablesEnv).await,
+                Analysis::ReachingDefinitions => results.push(&env::ReachingDefinitionsEnv).await,
+                Analysis::AvailableExpressions => results.push(&env::AvailableExpressionsEnv).await,
+                Analysis::VeryBusyExpressions => results.push(&env::VeryBusyExpressionsEnv).await,
+                Analysis::Worklist => results.push(&env::WorklistEnv).await,
                 Analysis::Security => results.push(&env::SecurityEnv).await,
+                Analysis::ProofOutline => results.push(&env::ProofOutlineEnv).await,
             }
         }
 
@@ -255,6 +264,9 @@ async fn generate_test_results<E: Environment>(
                     ValidationResult::CorrectNonTerminated { iterations } => {
                         TestResultType::CorrectNonTerminated { iterations }
                     }
+                    ValidationResult::ProbablyCorrect { reason } => {
+                        TestResultType::ProbablyCorrect { reason }
+                    }
                     ValidationResult::Mismatch { reason } => TestResultType::Mismatch { reason },
                     ValidationResult::TimeOut => TestResultType::TimeOut,
                 },
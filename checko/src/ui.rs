@@ -180,6 +180,14 @@ impl Row {
                                     TestResultType::CorrectNonTerminated { .. } => {
                                         ("Correct*".to_string(), Color::Green)
                                     }
+                                    TestResultType::ProbablyCorrect { reason } => (
+                                        if show {
+                                            format!("Correct?: {reason}")
+                                        } else {
+                                            "Correct?".to_string()
+                                        },
+                                        Color::Green,
+                                    ),
                                     TestResultType::Mismatch { reason } => (
                                         if show {
                                             format!("Mismatch: {reason}")
@@ -1,20 +1,66 @@
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 
-use crate::pg::{Edge, Node, ProgramGraph};
+use crate::pg::{Action, Edge, Node, ProgramGraph};
 
 pub enum Direction {
     Forward,
     Backward,
 }
 
+/// The nodes at which [`MonotoneFramework::widen`] is used instead of the
+/// least upper bound
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WideningPoints {
+    /// Never widen, which only terminates for domains of finite height
+    Nowhere,
+    /// Widen at the targets of back edges, see [`ProgramGraph::loop_heads`]
+    LoopHeads,
+    /// Widen at every node
+    Everywhere,
+}
+
 pub trait MonotoneFramework {
     type Domain: Lattice + Serialize + for<'a> Deserialize<'a>;
+    /// The effect of a single edge. Basic blocks can be handled by
+    /// [`transfer`](Self::transfer), which goes through their edges.
     fn semantic(&self, _pg: &ProgramGraph, e: &Edge, prev: &Self::Domain) -> Self::Domain;
     fn direction() -> Direction;
     fn initial(&self, pg: &ProgramGraph) -> Self::Domain;
     fn debug(&self, _item: &Self::Domain) {}
+
+    /// Domains of infinite height must widen at least at loop heads for the
+    /// analysis to terminate
+    fn widening_points() -> WideningPoints {
+        WideningPoints::Nowhere
+    }
+    /// An upper bound of `prev` and `next` which ensures that every ascending
+    /// chain of widenings stabilizes
+    fn widen(&self, prev: &Self::Domain, next: &Self::Domain) -> Self::Domain {
+        prev.lub(next)
+    }
+    /// A value between `next` and `prev`, where `next` is below `prev`, used to
+    /// recover precision lost by widening. Every descending chain of
+    /// narrowings must stabilize.
+    fn narrow(&self, prev: &Self::Domain, _next: &Self::Domain) -> Self::Domain {
+        prev.clone()
+    }
+
+    /// Applies the semantic function along `e`. The edges of a basic block are
+    /// gone through one at a time in the direction of the analysis, such that
+    /// the facts do not depend on whether the graph is compressed.
+    fn transfer(&self, pg: &ProgramGraph, e: &Edge, prev: &Self::Domain) -> Self::Domain {
+        let Action::Block(edges) = e.action() else {
+            return self.semantic(pg, e, prev);
+        };
+        let step = |fact: Self::Domain, e: &Edge| self.transfer(pg, e, &fact);
+        match Self::direction() {
+            Direction::Forward => edges.iter().fold(prev.clone(), step),
+            Direction::Backward => edges.iter().rev().fold(prev.clone(), step),
+        }
+    }
 }
 
 pub trait Lattice: Sized + Clone {
@@ -27,14 +73,16 @@ pub trait Lattice: Sized + Clone {
 }
 
 pub trait Worklist {
-    fn empty() -> Self;
+    /// An empty worklist. `order` lists every node of the program graph in the
+    /// order in which the analysis should preferably visit them.
+    fn empty(order: &[Node]) -> Self;
     fn insert(&mut self, n: Node);
-    fn extract(&mut self, pg: &ProgramGraph) -> Option<Node>;
+    fn extract(&mut self) -> Option<Node>;
 }
 
 pub struct FiFo(VecDeque<Node>);
 impl Worklist for FiFo {
-    fn empty() -> Self {
+    fn empty(_order: &[Node]) -> Self {
         FiFo(Default::default())
     }
 
@@ -42,14 +90,14 @@ impl Worklist for FiFo {
         self.0.push_back(n)
     }
 
-    fn extract(&mut self, _pg: &ProgramGraph) -> Option<Node> {
+    fn extract(&mut self) -> Option<Node> {
         self.0.pop_front()
     }
 }
 
 pub struct LiFo(Vec<Node>);
 impl Worklist for LiFo {
-    fn empty() -> Self {
+    fn empty(_order: &[Node]) -> Self {
         LiFo(Default::default())
     }
 
@@ -57,37 +105,113 @@ impl Worklist for LiFo {
         self.0.push(n);
     }
 
-    fn extract(&mut self, _pg: &ProgramGraph) -> Option<Node> {
+    fn extract(&mut self) -> Option<Node> {
         self.0.pop()
     }
 }
 
-// pub struct RoundRobin(VecDeque<Node>, HashSet<Node>);
-// impl Worklist for RoundRobin {
-//     fn empty() -> Self {
-//         RoundRobin(Default::default(), Default::default())
-//     }
-
-//     fn insert(&mut self, n: Node) {
-//         let RoundRobin(v, p) = self;
-//         if !v.contains(&n) {
-//             p.insert(n);
-//         }
-//     }
-
-//     fn extract(&mut self, pg: &ProgramGraph) -> Option<Node> {
-//         let RoundRobin(v, p) = self;
-//         match (v.is_empty(), p.is_empty()) {
-//             (true, true) => return None,
-//             (true, false) => {
-//                 *v = pg.reverse_post_order().filter(|n| p.contains(n)).collect();
-//                 p.clear();
-//                 v.pop_back()
-//             }
-//             _ => v.pop_back(),
-//         }
-//     }
-// }
+/// Processes the pending nodes in rounds, visiting the nodes of each round in
+/// reverse post-order, or its reverse for backward analyses. Nodes inserted
+/// during a round are deferred to the next one, unless they are still to be
+/// visited in the current round.
+pub struct RoundRobin {
+    order: Vec<Node>,
+    round: VecDeque<Node>,
+    pending: HashSet<Node>,
+}
+impl Worklist for RoundRobin {
+    fn empty(order: &[Node]) -> Self {
+        RoundRobin {
+            order: order.to_vec(),
+            round: Default::default(),
+            pending: Default::default(),
+        }
+    }
+
+    fn insert(&mut self, n: Node) {
+        if !self.round.contains(&n) {
+            self.pending.insert(n);
+        }
+    }
+
+    fn extract(&mut self) -> Option<Node> {
+        if self.round.is_empty() {
+            if self.pending.is_empty() {
+                return None;
+            }
+            self.round = self
+                .order
+                .iter()
+                .filter(|n| self.pending.contains(n))
+                .copied()
+                .collect();
+            self.pending.clear();
+        }
+        self.round.pop_front()
+    }
+}
+
+/// Always extracts the pending node which comes first in reverse post-order,
+/// or last for backward analyses
+pub struct ReversePostOrder {
+    rank: HashMap<Node, usize>,
+    queue: BTreeSet<(usize, Node)>,
+}
+impl Worklist for ReversePostOrder {
+    fn empty(order: &[Node]) -> Self {
+        ReversePostOrder {
+            rank: order.iter().enumerate().map(|(r, &n)| (n, r)).collect(),
+            queue: Default::default(),
+        }
+    }
+
+    fn insert(&mut self, n: Node) {
+        self.queue.insert((self.rank[&n], n));
+    }
+
+    fn extract(&mut self) -> Option<Node> {
+        self.queue.pop_first().map(|(_, n)| n)
+    }
+}
+
+/// A choice of [`Worklist`] to use with [`mono_analysis`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "Case")]
+pub enum WorklistStrategy {
+    FiFo,
+    LiFo,
+    RoundRobin,
+    ReversePostOrder,
+}
+
+impl std::fmt::Display for WorklistStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WorklistStrategy::FiFo => write!(f, "FIFO"),
+            WorklistStrategy::LiFo => write!(f, "LIFO"),
+            WorklistStrategy::RoundRobin => write!(f, "Round robin"),
+            WorklistStrategy::ReversePostOrder => write!(f, "Reverse post-order"),
+        }
+    }
+}
+
+impl WorklistStrategy {
+    pub const ALL: [WorklistStrategy; 4] = [
+        WorklistStrategy::FiFo,
+        WorklistStrategy::LiFo,
+        WorklistStrategy::RoundRobin,
+        WorklistStrategy::ReversePostOrder,
+    ];
+
+    pub fn analyze<A: MonotoneFramework>(self, a: A, pg: &ProgramGraph) -> AnalysisResults<A> {
+        match self {
+            WorklistStrategy::FiFo => mono_analysis::<A, FiFo>(a, pg),
+            WorklistStrategy::LiFo => mono_analysis::<A, LiFo>(a, pg),
+            WorklistStrategy::RoundRobin => mono_analysis::<A, RoundRobin>(a, pg),
+            WorklistStrategy::ReversePostOrder => mono_analysis::<A, ReversePostOrder>(a, pg),
+        }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AnalysisResults<A: MonotoneFramework> {
@@ -95,51 +219,119 @@ pub struct AnalysisResults<A: MonotoneFramework> {
     pub semantic_calls: usize,
 }
 
+/// The number of times [`MonotoneFramework::transfer`] applies the semantic
+/// function along `e`
+fn semantic_calls(e: &Edge) -> usize {
+    match e.action() {
+        Action::Block(edges) => edges.iter().map(semantic_calls).sum(),
+        _ => 1,
+    }
+}
+
+type Adjacent = fn(&ProgramGraph, Node) -> &[Edge];
+type Endpoint = fn(&Edge) -> Node;
+
 pub fn mono_analysis<A: MonotoneFramework, W: Worklist>(
     a: A,
     pg: &ProgramGraph,
 ) -> AnalysisResults<A> {
-    let mut worklist = W::empty();
-
-    let bot = A::Domain::bottom();
+    // NOTE: Reverse post-order visits a node after its predecessors, which is
+    // the order in which facts flow in a forward analysis. Backward analyses
+    // go through it in reverse.
+    let mut order = pg.reverse_post_order();
+    if let Direction::Backward = A::direction() {
+        order.reverse();
+    }
+    let mut worklist = W::empty(&order);
 
-    let mut facts: HashMap<Node, A::Domain> = HashMap::default();
-    for &n in pg.nodes() {
-        facts.insert(n, bot.clone());
+    let mut facts: HashMap<Node, A::Domain> = HashMap::with_capacity(pg.nodes().len());
+    // NOTE: Nodes are inserted in order, such that the result is the same
+    // across runs, even when widening makes it depend on the iteration order
+    for &n in pg.nodes().iter().sorted() {
+        facts.insert(n, A::Domain::bottom());
         worklist.insert(n);
     }
 
-    let initial = a.initial(pg);
     let initial_node = match A::direction() {
         Direction::Forward => Node::Start,
         Direction::Backward => Node::End,
     };
-    facts.insert(initial_node, initial);
+    facts.insert(initial_node, a.initial(pg));
 
-    let mut calls = 0;
+    let widening_points: HashSet<Node> = match A::widening_points() {
+        WideningPoints::Nowhere => HashSet::default(),
+        WideningPoints::LoopHeads => pg.loop_heads(),
+        WideningPoints::Everywhere => pg.nodes().clone(),
+    };
 
-    while let Some(n) = worklist.extract(pg) {
-        for e in pg.edges() {
-            let (from, to) = match A::direction() {
-                Direction::Forward => (e.from(), e.to()),
-                Direction::Backward => (e.to(), e.from()),
-            };
-            if n != from {
-                continue;
-            }
+    // The edges along which facts flow out of and into a node, and the node at
+    // the other end of such edges
+    let (outgoing, incoming, next, prev): (Adjacent, Adjacent, Endpoint, Endpoint) =
+        match A::direction() {
+            Direction::Forward => (
+                ProgramGraph::outgoing,
+                ProgramGraph::incoming,
+                Edge::to,
+                Edge::from,
+            ),
+            Direction::Backward => (
+                ProgramGraph::incoming,
+                ProgramGraph::outgoing,
+                Edge::from,
+                Edge::to,
+            ),
+        };
+
+    let mut calls = 0;
 
-            let constraint = a.semantic(pg, e, &facts[&from]);
-            calls += 1;
+    while let Some(n) = worklist.extract() {
+        for e in outgoing(pg, n) {
+            let to = next(e);
+            calls += semantic_calls(e);
+            let constraint = a.transfer(pg, e, &facts[&n]);
 
             let target = facts.get_mut(&to).unwrap();
 
             if !target.contains(&constraint) {
-                target.lub_extend(&constraint);
+                if widening_points.contains(&to) {
+                    *target = a.widen(target, &constraint);
+                } else {
+                    target.lub_extend(&constraint);
+                }
                 worklist.insert(to);
             }
         }
     }
 
+    // NOTE: The facts are now a post-fixpoint. If widening was used they can
+    // be improved by iterating downwards, narrowing at the widening points.
+    if !widening_points.is_empty() {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &n in pg.nodes().iter().sorted() {
+                let mut joined = if n == initial_node {
+                    a.initial(pg)
+                } else {
+                    A::Domain::bottom()
+                };
+                for e in incoming(pg, n) {
+                    calls += semantic_calls(e);
+                    joined.lub_extend(&a.transfer(pg, e, &facts[&prev(e)]));
+                }
+
+                let target = facts.get_mut(&n).unwrap();
+                if widening_points.contains(&n) {
+                    joined = a.narrow(target, &joined);
+                }
+                if !joined.contains(target) {
+                    *target = joined;
+                    changed = true;
+                }
+            }
+        }
+    }
+
     AnalysisResults {
         facts,
         semantic_calls: calls,
@@ -216,6 +408,146 @@ where
     }
 }
 
+impl<K, V> Lattice for BTreeMap<K, V>
+where
+    K: Ord + Clone,
+    V: Lattice + Clone,
+{
+    fn bottom() -> Self {
+        BTreeMap::default()
+    }
+
+    fn lub_extend(&mut self, other: &Self) {
+        for (k, b) in other {
+            if let Some(a) = self.get_mut(k) {
+                a.lub_extend(b);
+            } else {
+                self.insert(k.clone(), b.clone());
+            }
+        }
+    }
+
+    fn lub(&self, other: &Self) -> Self {
+        let mut result = self.clone();
+        result.lub_extend(other);
+        result
+    }
+
+    fn contains(&self, other: &Self) -> bool {
+        other.iter().all(|(k, a)| {
+            if let Some(b) = self.get(k) {
+                b.contains(a)
+            } else {
+                false
+            }
+        })
+    }
+}
+
+/// Lifts a lattice with a new bottom element `None`
+impl<T> Lattice for Option<T>
+where
+    T: Lattice,
+{
+    fn bottom() -> Self {
+        None
+    }
+
+    fn lub_extend(&mut self, other: &Self) {
+        match (self.as_mut(), other) {
+            (Some(a), Some(b)) => a.lub_extend(b),
+            (None, Some(b)) => *self = Some(b.clone()),
+            (_, None) => {}
+        }
+    }
+
+    fn lub(&self, other: &Self) -> Self {
+        match (self, other) {
+            (Some(a), Some(b)) => Some(a.lub(b)),
+            (Some(a), None) | (None, Some(a)) => Some(a.clone()),
+            (None, None) => None,
+        }
+    }
+
+    fn contains(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Some(a), Some(b)) => a.contains(b),
+            (_, None) => true,
+            (None, Some(_)) => false,
+        }
+    }
+}
+
+/// A set ordered by reverse inclusion, such that the least upper bound is the
+/// intersection. This is the domain of must analyses, whose bottom element is
+/// the set of everything.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "Case")]
+pub enum MustSet<T: std::hash::Hash + Eq> {
+    Everything,
+    Only { elements: HashSet<T> },
+}
+
+impl<T: std::hash::Hash + Eq> MustSet<T> {
+    pub fn empty() -> Self {
+        MustSet::Only {
+            elements: HashSet::default(),
+        }
+    }
+    /// The elements of the set, where `universe` is used for
+    /// [`MustSet::Everything`]
+    pub fn into_elements(self, universe: impl IntoIterator<Item = T>) -> HashSet<T> {
+        match self {
+            MustSet::Everything => universe.into_iter().collect(),
+            MustSet::Only { elements } => elements,
+        }
+    }
+    pub fn map(self, f: impl FnOnce(HashSet<T>) -> HashSet<T>) -> Self {
+        match self {
+            MustSet::Everything => MustSet::Everything,
+            MustSet::Only { elements } => MustSet::Only {
+                elements: f(elements),
+            },
+        }
+    }
+}
+
+impl<T> Lattice for MustSet<T>
+where
+    T: std::hash::Hash + Eq + Clone,
+{
+    fn bottom() -> Self {
+        MustSet::Everything
+    }
+
+    fn lub_extend(&mut self, other: &Self) {
+        match (self, other) {
+            (_, MustSet::Everything) => {}
+            (this @ MustSet::Everything, other) => *this = other.clone(),
+            (MustSet::Only { elements: a }, MustSet::Only { elements: b }) => {
+                a.retain(|x| b.contains(x))
+            }
+        }
+    }
+
+    fn lub(&self, other: &Self) -> Self {
+        match (self, other) {
+            (MustSet::Everything, x) | (x, MustSet::Everything) => x.clone(),
+            (MustSet::Only { elements: a }, MustSet::Only { elements: b }) => MustSet::Only {
+                elements: a.intersection(b).cloned().collect(),
+            },
+        }
+    }
+
+    fn contains(&self, other: &Self) -> bool {
+        match (self, other) {
+            (_, MustSet::Everything) => true,
+            (MustSet::Everything, _) => false,
+            (MustSet::Only { elements: a }, MustSet::Only { elements: b }) => a.is_subset(b),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum NodeOrder<'a> {
     First,
@@ -228,10 +560,10 @@ impl<'a> NodeOrder<'a> {
     pub fn parse(n: &'a str) -> Self {
         match n {
             _ if n.contains('▷') => NodeOrder::First,
-            "qS" => NodeOrder::First,
+            "qS" | "qStart" => NodeOrder::First,
 
             _ if n.contains('◀') => NodeOrder::Last,
-            "qF" => NodeOrder::Last,
+            "qF" | "qFinal" => NodeOrder::Last,
 
             _ if n.contains(|c: char| c.is_numeric()) => NodeOrder::Middle(
                 n.chars()
@@ -244,3 +576,134 @@ impl<'a> NodeOrder<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        bit_vector::{LiveVariables, ReachingDefinitions},
+        interval::Interval,
+        parse::parse_commands,
+        pg::{Determinism, Edge, Node, ProgramGraph},
+    };
+
+    use super::{
+        mono_analysis, Direction, FiFo, Lattice, MonotoneFramework, WideningPoints,
+        WorklistStrategy,
+    };
+
+    /// The number of edges taken from the start, which has infinite height in
+    /// the presence of loops. It widens at every node if `EVERYWHERE` is set,
+    /// and at loop heads otherwise, and never narrows.
+    struct Steps<const EVERYWHERE: bool>;
+
+    impl<const EVERYWHERE: bool> MonotoneFramework for Steps<EVERYWHERE> {
+        type Domain = Interval;
+
+        fn semantic(&self, _pg: &ProgramGraph, _e: &Edge, prev: &Interval) -> Interval {
+            match *prev {
+                Interval::Empty => Interval::Empty,
+                Interval::Range { lower, upper } => Interval::Range {
+                    lower: lower.map(|l| l + 1),
+                    upper: upper.map(|u| u + 1),
+                },
+            }
+        }
+
+        fn direction() -> Direction {
+            Direction::Forward
+        }
+
+        fn initial(&self, _pg: &ProgramGraph) -> Interval {
+            Interval::constant(0)
+        }
+
+        fn widening_points() -> WideningPoints {
+            if EVERYWHERE {
+                WideningPoints::Everywhere
+            } else {
+                WideningPoints::LoopHeads
+            }
+        }
+
+        fn widen(&self, prev: &Interval, next: &Interval) -> Interval {
+            prev.widen(*next)
+        }
+    }
+
+    #[test]
+    fn every_worklist_reaches_the_same_fixpoint() -> miette::Result<()> {
+        let cmds = parse_commands(
+            "i := 0; do i < 10 -> if i > 5 -> x := i [] true -> y := x fi; i := i + 1 od",
+        )?;
+        let pg = ProgramGraph::new(Determinism::NonDeterministic, &cmds);
+
+        let order = pg.reverse_post_order();
+        assert_eq!(order.len(), pg.nodes().len());
+        assert_eq!(order[0], crate::pg::Node::Start);
+
+        let results = WorklistStrategy::ALL.map(|w| w.analyze(ReachingDefinitions, &pg));
+        for r in &results {
+            assert_eq!(r.facts, results[0].facts);
+            assert!(r.semantic_calls >= pg.edges().len());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn backward_analyses_visit_nodes_in_reverse() -> miette::Result<()> {
+        let cmds = parse_commands("x := 1; if x > 0 -> y := x [] true -> y := 2 fi; z := y")?;
+        let pg = ProgramGraph::new(Determinism::NonDeterministic, &cmds);
+
+        // NOTE: Without loops every node is visited only once all of the nodes
+        // it depends on are done, so each edge is used exactly once
+        for w in [
+            WorklistStrategy::RoundRobin,
+            WorklistStrategy::ReversePostOrder,
+        ] {
+            let results = w.analyze(LiveVariables, &pg);
+            assert_eq!(results.semantic_calls, pg.edges().len(), "{w}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn widening_at_loop_heads_terminates_on_infinite_height() -> miette::Result<()> {
+        let cmds = parse_commands("i := 0; do i < 10 -> i := i + 1 od")?;
+        let pg = ProgramGraph::new(Determinism::NonDeterministic, &cmds);
+
+        let facts = mono_analysis::<_, FiFo>(Steps::<false>, &pg).facts;
+        let at_least = |n| Interval::Range {
+            lower: Some(n),
+            upper: None,
+        };
+        for head in pg.loop_heads() {
+            assert_eq!(facts[&head], at_least(1));
+        }
+        assert_eq!(facts[&Node::End], at_least(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn widening_everywhere_loses_precision_outside_loops() -> miette::Result<()> {
+        let cmds = parse_commands("if true -> skip [] true -> x := 1; x := 2 fi")?;
+        let pg = ProgramGraph::new(Determinism::NonDeterministic, &cmds);
+        assert!(pg.loop_heads().is_empty());
+
+        // NOTE: Without loops there is nothing to widen at loop heads, so the
+        // two branches are joined exactly
+        let loop_heads = mono_analysis::<_, FiFo>(Steps::<false>, &pg).facts;
+        let everywhere = mono_analysis::<_, FiFo>(Steps::<true>, &pg).facts;
+        let exact = Interval::Range {
+            lower: Some(2),
+            upper: Some(3),
+        };
+        assert_eq!(loop_heads[&Node::End], exact);
+        assert_ne!(everywhere[&Node::End], exact);
+        assert!(everywhere[&Node::End].contains(&exact));
+
+        Ok(())
+    }
+}
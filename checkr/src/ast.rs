@@ -1,8 +1,13 @@
-use std::{collections::HashSet, str::FromStr};
+use std::{
+    collections::{BTreeSet, HashSet},
+    str::FromStr,
+};
 
 use itertools::Either;
 use serde::{Deserialize, Serialize};
 
+use crate::parse::SourceSpan;
+
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Target<Idx = ()> {
     Variable(Variable),
@@ -80,6 +85,25 @@ impl<'de> serde::Deserialize<'de> for Target {
     }
 }
 
+/// Expressions are serialized in their concrete syntax
+impl serde::Serialize for AExpr {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+impl<'de> serde::Deserialize<'de> for AExpr {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let src = String::deserialize(deserializer)?;
+        crate::parse::parse_aexpr(&src).map_err(serde::de::Error::custom)
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 #[serde(transparent)]
 pub struct Variable(pub String);
@@ -138,16 +162,31 @@ impl From<Array> for Target<()> {
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Commands(pub Vec<Command>);
 
+/// A command together with the location in the source it was parsed from.
+///
+/// The span is ignored when comparing and hashing commands, such that the same
+/// program is considered equal regardless of how it was formatted. Commands
+/// which were not parsed, for example generated ones, have no span.
+#[derive(Debug, Clone)]
+pub struct Command {
+    pub kind: CommandKind,
+    pub span: Option<SourceSpan>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub enum Command {
+pub enum CommandKind {
     Assignment(Target<Box<AExpr>>, AExpr),
     Skip,
     If(Vec<Guard>),
     Loop(Vec<Guard>),
-    /// **Extension**
-    EnrichedLoop(Predicate, Vec<Guard>),
+    /// **Extension**: A loop with an invariant, and optionally a variant which
+    /// decreases in every iteration while staying non-negative
+    EnrichedLoop(Predicate, Option<AExpr>, Vec<Guard>),
     /// **Extension**
     Annotated(Predicate, Commands, Predicate),
+    /// **Extension**: An intermediate assertion of a proof outline, which
+    /// must hold right before the command
+    Assertion(Predicate, Box<Command>),
     /// **Extension**
     Break,
     /// **Extension**
@@ -159,8 +198,17 @@ pub struct Guard(pub BExpr, pub Commands);
 
 pub type Int = i64;
 
+/// An arithmetic expression together with the location in the source it was
+/// parsed from. Like for [`Command`], the span is ignored when comparing and
+/// hashing expressions.
+#[derive(Debug, Clone)]
+pub struct AExpr {
+    pub kind: AExprKind,
+    pub span: Option<SourceSpan>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub enum AExpr {
+pub enum AExprKind {
     Number(Int),
     Reference(Target<Box<AExpr>>),
     Binary(Box<AExpr>, AOp, Box<AExpr>),
@@ -190,8 +238,17 @@ pub enum Function {
     Fib(Box<AExpr>),
 }
 
+/// A boolean expression together with the location in the source it was
+/// parsed from. Like for [`Command`], the span is ignored when comparing and
+/// hashing expressions.
+#[derive(Debug, Clone)]
+pub struct BExpr {
+    pub kind: BExprKind,
+    pub span: Option<SourceSpan>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub enum BExpr {
+pub enum BExprKind {
     Bool(bool),
     Rel(AExpr, RelOp, AExpr),
     Logic(Box<BExpr>, LogicOp, Box<BExpr>),
@@ -227,24 +284,70 @@ pub enum LogicOp {
     Implies,
 }
 
+/// Implements comparison and hashing of a node with a span by its `kind`
+/// alone, and conversion from a `kind` without a span
+macro_rules! ignore_span {
+    ($t:ident, $kind:ident) => {
+        impl PartialEq for $t {
+            fn eq(&self, other: &Self) -> bool {
+                self.kind == other.kind
+            }
+        }
+        impl Eq for $t {}
+        impl PartialOrd for $t {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for $t {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                self.kind.cmp(&other.kind)
+            }
+        }
+        impl std::hash::Hash for $t {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                self.kind.hash(state);
+            }
+        }
+        impl From<$kind> for $t {
+            fn from(kind: $kind) -> Self {
+                $t { kind, span: None }
+            }
+        }
+    };
+}
+ignore_span!(Command, CommandKind);
+ignore_span!(AExpr, AExprKind);
+ignore_span!(BExpr, BExprKind);
+
 impl Commands {
     pub fn fv(&self) -> HashSet<Target> {
         self.0.iter().flat_map(|c| c.fv()).collect()
     }
+    /// The span covering all of the commands, if any of them were parsed
+    pub fn span(&self) -> Option<SourceSpan> {
+        let mut spans = self.0.iter().map(|c| c.span);
+        let first = spans.find_map(|s| s)?;
+        Some(SourceSpan::union(first, spans))
+    }
 }
 impl Command {
+    pub fn new(kind: CommandKind, span: Option<SourceSpan>) -> Command {
+        Command { kind, span }
+    }
     pub fn fv(&self) -> HashSet<Target> {
-        match self {
-            Command::Assignment(x, a) => x.fv().union(&a.fv()).cloned().collect(),
-            Command::Skip => HashSet::default(),
-            Command::If(c) => guards_fv(c),
-            Command::Loop(c) => guards_fv(c),
+        match &self.kind {
+            CommandKind::Assignment(x, a) => x.fv().union(&a.fv()).cloned().collect(),
+            CommandKind::Skip => HashSet::default(),
+            CommandKind::If(c) => guards_fv(c),
+            CommandKind::Loop(c) => guards_fv(c),
             // TODO: Maybe the pred should also be looked at?
-            Command::EnrichedLoop(_, c) => guards_fv(c),
+            CommandKind::EnrichedLoop(_, _, c) => guards_fv(c),
             // TODO: Maybe the pred should also be looked at?
-            Command::Annotated(_, c, _) => c.fv(),
-            Command::Break => HashSet::default(),
-            Command::Continue => HashSet::default(),
+            CommandKind::Annotated(_, c, _) => c.fv(),
+            CommandKind::Assertion(_, c) => c.fv(),
+            CommandKind::Break => HashSet::default(),
+            CommandKind::Continue => HashSet::default(),
         }
     }
 }
@@ -269,16 +372,30 @@ impl Target<Box<AExpr>> {
     }
 }
 impl AExpr {
+    pub fn new(kind: AExprKind, span: Option<SourceSpan>) -> AExpr {
+        AExpr { kind, span }
+    }
     pub fn binary(lhs: Self, op: AOp, rhs: Self) -> Self {
-        Self::Binary(Box::new(lhs), op, Box::new(rhs))
+        AExprKind::Binary(Box::new(lhs), op, Box::new(rhs)).into()
     }
     pub fn fv(&self) -> HashSet<Target> {
-        match self {
-            AExpr::Number(_) => Default::default(),
-            AExpr::Reference(v) => v.fv(),
-            AExpr::Binary(l, _, r) => l.fv().union(&r.fv()).cloned().collect(),
-            AExpr::Minus(x) => x.fv(),
-            AExpr::Function(f) => f.fv(),
+        match &self.kind {
+            AExprKind::Number(_) => Default::default(),
+            AExprKind::Reference(v) => v.fv(),
+            AExprKind::Binary(l, _, r) => l.fv().union(&r.fv()).cloned().collect(),
+            AExprKind::Minus(x) => x.fv(),
+            AExprKind::Function(f) => f.fv(),
+        }
+    }
+    /// The numbers written in the expression
+    pub fn constants(&self) -> BTreeSet<Int> {
+        match &self.kind {
+            AExprKind::Number(n) => [*n].into_iter().collect(),
+            AExprKind::Reference(Target::Variable(_)) => Default::default(),
+            AExprKind::Reference(Target::Array(_, idx)) => idx.constants(),
+            AExprKind::Binary(l, _, r) => l.constants().union(&r.constants()).copied().collect(),
+            AExprKind::Minus(x) => x.constants(),
+            AExprKind::Function(f) => f.exprs().flat_map(|x| x.constants()).collect(),
         }
     }
 }
@@ -311,23 +428,35 @@ impl Function {
     }
 }
 impl BExpr {
+    pub fn new(kind: BExprKind, span: Option<SourceSpan>) -> BExpr {
+        BExpr { kind, span }
+    }
     pub fn logic(lhs: Self, op: LogicOp, rhs: Self) -> Self {
-        Self::Logic(Box::new(lhs), op, Box::new(rhs))
+        BExprKind::Logic(Box::new(lhs), op, Box::new(rhs)).into()
     }
     pub fn rel(lhs: AExpr, op: RelOp, rhs: AExpr) -> Self {
-        Self::Rel(lhs, op, rhs)
+        BExprKind::Rel(lhs, op, rhs).into()
     }
     pub fn fv(&self) -> HashSet<Target> {
-        match self {
-            BExpr::Bool(_) => Default::default(),
-            BExpr::Rel(l, _, r) => l.fv().union(&r.fv()).cloned().collect(),
-            BExpr::Logic(l, _, r) => l.fv().union(&r.fv()).cloned().collect(),
-            BExpr::Not(x) => x.fv(),
-            BExpr::Quantified(_, x, b) => {
+        match &self.kind {
+            BExprKind::Bool(_) => Default::default(),
+            BExprKind::Rel(l, _, r) => l.fv().union(&r.fv()).cloned().collect(),
+            BExprKind::Logic(l, _, r) => l.fv().union(&r.fv()).cloned().collect(),
+            BExprKind::Not(x) => x.fv(),
+            BExprKind::Quantified(_, x, b) => {
                 let mut fv = b.fv();
                 fv.remove(x);
                 fv
             }
         }
     }
+    /// The numbers written in the expression
+    pub fn constants(&self) -> BTreeSet<Int> {
+        match &self.kind {
+            BExprKind::Bool(_) => Default::default(),
+            BExprKind::Rel(l, _, r) => l.constants().union(&r.constants()).copied().collect(),
+            BExprKind::Logic(l, _, r) => l.constants().union(&r.constants()).copied().collect(),
+            BExprKind::Not(x) | BExprKind::Quantified(_, _, x) => x.constants(),
+        }
+    }
 }
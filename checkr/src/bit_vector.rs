@@ -0,0 +1,291 @@
+//! The classic bit-vector analyses: live variables, reaching definitions,
+//! available expressions and very busy expressions.
+//!
+//! Each of them is given by the facts it kills and generates along an edge,
+//! see for example Chapter 2 of Principles of Program Analysis.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::{Direction, MonotoneFramework, MustSet},
+    ast::{AExpr, AExprKind, BExpr, BExprKind, Target},
+    pg::{Action, Edge, Node, ProgramGraph},
+};
+
+/// The variables and arrays which may be used before they are assigned
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct LiveVariables;
+
+/// The assignments which may have produced the current value of each variable
+/// and array
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ReachingDefinitions;
+
+/// The expressions which have definitely been computed, and not since been
+/// invalidated by an assignment
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AvailableExpressions;
+
+/// The expressions which will definitely be computed before any of their free
+/// variables are assigned
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct VeryBusyExpressions;
+
+/// An assignment to `target` along the edge from `from` to `to`. The initial
+/// value of every target is represented by a definition without a `from` node
+/// and with the start node as `to`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Definition {
+    pub target: Target,
+    pub from: Option<Node>,
+    pub to: Node,
+}
+
+/// The variables and arrays assigned by the action
+fn assigned(action: &Action) -> HashSet<Target> {
+    match action {
+        Action::Assignment(t, _) => [t.clone().unit()].into_iter().collect(),
+        Action::Skip | Action::Condition(_) => HashSet::default(),
+        Action::Block(edges) => edges.iter().flat_map(|e| assigned(e.action())).collect(),
+    }
+}
+
+/// Removes the expressions referring to the assigned variables and arrays
+fn kill_expressions(action: &Action, exprs: &mut HashSet<AExpr>) {
+    let assigned = assigned(action);
+    exprs.retain(|e| e.fv().is_disjoint(&assigned));
+}
+
+impl MonotoneFramework for LiveVariables {
+    type Domain = HashSet<Target>;
+
+    fn semantic(&self, pg: &ProgramGraph, e: &Edge, prev: &Self::Domain) -> Self::Domain {
+        let mut live = prev.clone();
+        match e.action() {
+            Action::Assignment(Target::Variable(x), a) => {
+                live.remove(&Target::Variable(x.clone()));
+                live.extend(a.fv());
+            }
+            // NOTE: Only a single element is assigned, so the array is not
+            // killed
+            Action::Assignment(Target::Array(_, idx), a) => {
+                live.extend(idx.fv());
+                live.extend(a.fv());
+            }
+            Action::Skip => {}
+            Action::Condition(b) => live.extend(b.fv()),
+            Action::Block(_) => return self.transfer(pg, e, prev),
+        }
+        live
+    }
+
+    fn direction() -> Direction {
+        Direction::Backward
+    }
+
+    fn initial(&self, _pg: &ProgramGraph) -> Self::Domain {
+        HashSet::default()
+    }
+}
+
+impl MonotoneFramework for ReachingDefinitions {
+    type Domain = HashSet<Definition>;
+
+    fn semantic(&self, pg: &ProgramGraph, e: &Edge, prev: &Self::Domain) -> Self::Domain {
+        let mut reaching = prev.clone();
+        match e.action() {
+            Action::Assignment(t, _) => {
+                let target = t.clone().unit();
+                // NOTE: Assigning a single element of an array leaves the
+                // definitions of the remaining elements intact
+                if let Target::Variable(_) = target {
+                    reaching.retain(|d| d.target != target);
+                }
+                reaching.insert(Definition {
+                    target,
+                    from: Some(e.from()),
+                    to: e.to(),
+                });
+            }
+            Action::Skip | Action::Condition(_) => {}
+            Action::Block(_) => return self.transfer(pg, e, prev),
+        }
+        reaching
+    }
+
+    fn direction() -> Direction {
+        Direction::Forward
+    }
+
+    fn initial(&self, pg: &ProgramGraph) -> Self::Domain {
+        pg.fv()
+            .into_iter()
+            .map(|target| Definition {
+                target,
+                from: None,
+                to: Node::Start,
+            })
+            .collect()
+    }
+}
+
+impl MonotoneFramework for AvailableExpressions {
+    type Domain = MustSet<AExpr>;
+
+    fn semantic(&self, _pg: &ProgramGraph, e: &Edge, prev: &Self::Domain) -> Self::Domain {
+        prev.clone().map(|mut available| {
+            available.extend(e.action().expressions());
+            kill_expressions(e.action(), &mut available);
+            available
+        })
+    }
+
+    fn direction() -> Direction {
+        Direction::Forward
+    }
+
+    fn initial(&self, _pg: &ProgramGraph) -> Self::Domain {
+        MustSet::empty()
+    }
+}
+
+impl MonotoneFramework for VeryBusyExpressions {
+    type Domain = MustSet<AExpr>;
+
+    fn semantic(&self, _pg: &ProgramGraph, e: &Edge, prev: &Self::Domain) -> Self::Domain {
+        prev.clone().map(|mut busy| {
+            kill_expressions(e.action(), &mut busy);
+            busy.extend(e.action().expressions());
+            busy
+        })
+    }
+
+    fn direction() -> Direction {
+        Direction::Backward
+    }
+
+    fn initial(&self, _pg: &ProgramGraph) -> Self::Domain {
+        MustSet::empty()
+    }
+}
+
+impl ProgramGraph {
+    /// The non-trivial arithmetic expressions occurring in the program graph
+    pub fn expressions(&self) -> HashSet<AExpr> {
+        self.edges()
+            .iter()
+            .flat_map(|e| e.action().expressions())
+            .collect()
+    }
+}
+
+impl Action {
+    /// The non-trivial arithmetic expressions evaluated by the action
+    pub fn expressions(&self) -> HashSet<AExpr> {
+        let mut exprs = HashSet::new();
+        match self {
+            Action::Assignment(t, a) => {
+                if let Target::Array(_, idx) = t {
+                    idx.collect_expressions(&mut exprs);
+                }
+                a.collect_expressions(&mut exprs);
+            }
+            Action::Skip => {}
+            Action::Condition(b) => b.collect_expressions(&mut exprs),
+            Action::Block(edges) => {
+                for e in edges {
+                    exprs.extend(e.action().expressions());
+                }
+            }
+        }
+        exprs
+    }
+}
+
+impl AExpr {
+    /// Collects the subexpressions of `self` which are neither numbers nor
+    /// references
+    fn collect_expressions(&self, exprs: &mut HashSet<AExpr>) {
+        match &self.kind {
+            AExprKind::Number(_) | AExprKind::Reference(Target::Variable(_)) => {}
+            AExprKind::Reference(Target::Array(_, idx)) => idx.collect_expressions(exprs),
+            AExprKind::Binary(l, _, r) => {
+                l.collect_expressions(exprs);
+                r.collect_expressions(exprs);
+                exprs.insert(self.clone());
+            }
+            AExprKind::Minus(x) => {
+                x.collect_expressions(exprs);
+                exprs.insert(self.clone());
+            }
+            AExprKind::Function(f) => {
+                for x in f.exprs() {
+                    x.collect_expressions(exprs);
+                }
+                exprs.insert(self.clone());
+            }
+        }
+    }
+}
+
+impl BExpr {
+    fn collect_expressions(&self, exprs: &mut HashSet<AExpr>) {
+        match &self.kind {
+            BExprKind::Bool(_) => {}
+            BExprKind::Rel(l, _, r) => {
+                l.collect_expressions(exprs);
+                r.collect_expressions(exprs);
+            }
+            BExprKind::Logic(l, _, r) => {
+                l.collect_expressions(exprs);
+                r.collect_expressions(exprs);
+            }
+            BExprKind::Not(b) => b.collect_expressions(exprs),
+            // NOTE: Expressions under a quantifier are not evaluated
+            BExprKind::Quantified(_, _, _) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        analysis::{mono_analysis, FiFo},
+        ast::{Target, Variable},
+        parse::{parse_aexpr, parse_commands},
+        pg::{Determinism, Node, ProgramGraph},
+    };
+
+    use super::{AvailableExpressions, LiveVariables};
+
+    #[test]
+    fn live_variables_flow_backwards() -> miette::Result<()> {
+        let cmds = parse_commands("x := y; y := 1; z := x")?;
+        let pg = ProgramGraph::new(Determinism::Deterministic, &cmds);
+        let facts = mono_analysis::<_, FiFo>(LiveVariables, &pg).facts;
+
+        let var = |x: &str| Target::Variable(Variable(x.to_string()));
+        assert_eq!(facts[&Node::Start], [var("y")].into_iter().collect());
+        assert!(facts[&Node::End].is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn assignments_kill_available_expressions() -> miette::Result<()> {
+        let cmds = parse_commands("if x > 0 -> y := x + 1 [] true -> y := x + 1; x := 0 fi")?;
+        let pg = ProgramGraph::new(Determinism::NonDeterministic, &cmds);
+        let facts = mono_analysis::<_, FiFo>(AvailableExpressions, &pg).facts;
+
+        assert!(facts[&Node::End]
+            .clone()
+            .into_elements(pg.expressions())
+            .is_empty());
+        let x_plus_1 = parse_aexpr("x + 1")?;
+        assert!(pg.expressions().contains(&x_plus_1));
+
+        Ok(())
+    }
+}
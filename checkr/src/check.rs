@@ -0,0 +1,223 @@
+//! Static semantic checks of GCL programs.
+//!
+//! The grammar accepts some programs which none of the analysis can give a
+//! meaning to, for example ones using a name both as a variable and as an
+//! array. [`check`] finds these problems up front, such that they can be
+//! reported against the source instead of surfacing as panics deep inside an
+//! analysis.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::{
+    ast::{Command, CommandKind, Commands, Guard, Target},
+    parse::SourceSpan,
+};
+
+#[derive(Debug, Error, Diagnostic, Clone, PartialEq, Eq)]
+pub enum CheckError {
+    #[error("`{name}` is used both as a variable and as an array")]
+    #[diagnostic(help("A name must consistently refer to either a variable or an array"))]
+    VariableAndArray {
+        name: String,
+        #[label("`{name}` is used as a different kind here")]
+        span: Option<SourceSpan>,
+    },
+    #[error("the logical variable `{name}` is used outside of a predicate")]
+    #[diagnostic(help("Names starting with `_` may only appear in predicates"))]
+    LogicalOutsidePredicate {
+        name: String,
+        #[label("`{name}` is used here")]
+        span: Option<SourceSpan>,
+    },
+    #[error("`break` outside of a loop")]
+    BreakOutsideLoop {
+        #[label("This `break` is not inside a `do`")]
+        span: Option<SourceSpan>,
+    },
+    #[error("`continue` outside of a loop")]
+    ContinueOutsideLoop {
+        #[label("This `continue` is not inside a `do`")]
+        span: Option<SourceSpan>,
+    },
+    #[error("annotated blocks cannot be nested")]
+    NestedAnnotation {
+        #[label("This block is inside another annotated block")]
+        span: Option<SourceSpan>,
+    },
+    #[error("loop invariant outside of an annotated block")]
+    InvariantOutsideAnnotation {
+        #[label("This loop is not inside an annotated block")]
+        span: Option<SourceSpan>,
+    },
+    #[error("assertion outside of an annotated block")]
+    AssertionOutsideAnnotation {
+        #[label("This assertion is not inside an annotated block")]
+        span: Option<SourceSpan>,
+    },
+}
+
+impl CheckError {
+    pub fn span(&self) -> Option<SourceSpan> {
+        match self {
+            CheckError::VariableAndArray { span, .. }
+            | CheckError::LogicalOutsidePredicate { span, .. }
+            | CheckError::BreakOutsideLoop { span }
+            | CheckError::ContinueOutsideLoop { span }
+            | CheckError::NestedAnnotation { span }
+            | CheckError::InvariantOutsideAnnotation { span }
+            | CheckError::AssertionOutsideAnnotation { span } => *span,
+        }
+    }
+}
+
+/// Checks that the program is well-formed, returning every problem found in
+/// the order they appear in the program.
+pub fn check(cmds: &Commands) -> Vec<CheckError> {
+    let mut checker = Checker::default();
+    checker.commands(cmds);
+    checker.errors
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NameKind {
+    Variable,
+    Array,
+}
+
+#[derive(Debug, Default)]
+struct Checker {
+    /// The kind of each name at its first use
+    kinds: HashMap<String, NameKind>,
+    /// Names already reported as inconsistent, such that they are only
+    /// reported once
+    inconsistent: HashSet<String>,
+    loop_depth: u32,
+    in_annotation: bool,
+    errors: Vec<CheckError>,
+}
+
+impl Checker {
+    fn commands(&mut self, cmds: &Commands) {
+        for c in &cmds.0 {
+            self.command(c);
+        }
+    }
+    fn command(&mut self, c: &Command) {
+        let span = c.span;
+        match &c.kind {
+            CommandKind::Assignment(x, a) => {
+                self.names(span, false, x.fv().into_iter().chain(a.fv()))
+            }
+            CommandKind::Skip => {}
+            CommandKind::If(guards) => self.guards(span, guards),
+            CommandKind::Loop(guards) => self.loop_guards(span, guards),
+            CommandKind::EnrichedLoop(inv, variant, guards) => {
+                if !self.in_annotation {
+                    self.errors
+                        .push(CheckError::InvariantOutsideAnnotation { span });
+                }
+                self.names(
+                    span,
+                    true,
+                    inv.fv()
+                        .into_iter()
+                        .chain(variant.iter().flat_map(|v| v.fv())),
+                );
+                self.loop_guards(span, guards);
+            }
+            CommandKind::Annotated(p, body, q) => {
+                if self.in_annotation {
+                    self.errors.push(CheckError::NestedAnnotation { span });
+                }
+                self.names(span, true, p.fv().into_iter().chain(q.fv()));
+                let outer = std::mem::replace(&mut self.in_annotation, true);
+                self.commands(body);
+                self.in_annotation = outer;
+            }
+            CommandKind::Assertion(p, c) => {
+                if !self.in_annotation {
+                    self.errors
+                        .push(CheckError::AssertionOutsideAnnotation { span });
+                }
+                self.names(span, true, p.fv());
+                self.command(c);
+            }
+            CommandKind::Break => {
+                if self.loop_depth == 0 {
+                    self.errors.push(CheckError::BreakOutsideLoop { span });
+                }
+            }
+            CommandKind::Continue => {
+                if self.loop_depth == 0 {
+                    self.errors.push(CheckError::ContinueOutsideLoop { span });
+                }
+            }
+        }
+    }
+    fn loop_guards(&mut self, span: Option<SourceSpan>, guards: &[Guard]) {
+        self.loop_depth += 1;
+        self.guards(span, guards);
+        self.loop_depth -= 1;
+    }
+    fn guards(&mut self, span: Option<SourceSpan>, guards: &[Guard]) {
+        for Guard(b, c) in guards {
+            self.names(span, false, b.fv());
+            self.commands(c);
+        }
+    }
+    /// Checks the names used by the command at `span`
+    fn names(
+        &mut self,
+        span: Option<SourceSpan>,
+        in_predicate: bool,
+        names: impl IntoIterator<Item = Target>,
+    ) {
+        // NOTE: The names are sorted to report errors in a deterministic order
+        for t in names.into_iter().collect::<BTreeSet<_>>() {
+            let name = t.name().to_string();
+            let kind = match t {
+                Target::Variable(_) => NameKind::Variable,
+                Target::Array(_, _) => NameKind::Array,
+            };
+
+            if !in_predicate && t.is_logical() {
+                self.errors.push(CheckError::LogicalOutsidePredicate {
+                    name: name.clone(),
+                    span,
+                });
+            }
+
+            let first = *self.kinds.entry(name.clone()).or_insert(kind);
+            if first != kind && self.inconsistent.insert(name.clone()) {
+                self.errors
+                    .push(CheckError::VariableAndArray { name, span });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, CheckError};
+
+    #[test]
+    fn reports_every_problem() -> miette::Result<()> {
+        let src = "a := 1; a[0] := 2; break; do true -> continue od";
+        let errors = check(&crate::parse::parse_commands(src)?);
+
+        assert!(matches!(
+            errors.as_slice(),
+            [
+                CheckError::VariableAndArray { name, .. },
+                CheckError::BreakOutsideLoop { .. },
+            ] if name == "a"
+        ));
+        let span = errors[1].span().unwrap();
+        assert_eq!(&src[span.offset()..span.end()], "break");
+
+        Ok(())
+    }
+}
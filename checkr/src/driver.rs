@@ -4,7 +4,6 @@ use std::{
 };
 
 use tokio::process::Command;
-use tracing::error;
 
 use crate::{
     ast::Commands,
@@ -1,9 +1,15 @@
-use egg::{define_language, rewrite as rw, FromOpError, Id, RecExpr, RecExprParseError};
+use std::time::Duration;
 
-use crate::ast::{AExpr, AOp, Array, BExpr, Function, LogicOp, Target, Variable};
+use egg::{
+    define_language, rewrite as rw, AstSize, Extractor, FromOpError, Id, Language, RecExpr,
+    RecExprParseError,
+};
 
-type Runner = egg::Runner<Gcl, ()>;
-type Rewrite = egg::Rewrite<Gcl, ()>;
+use crate::ast::{AExpr, AExprKind, AOp, BExpr, BExprKind, Function, Target, Variable};
+
+type EGraph = egg::EGraph<Gcl, Definedness>;
+type Runner = egg::Runner<Gcl, Definedness>;
+type Rewrite = egg::Rewrite<Gcl, Definedness>;
 
 define_language! {
     pub enum Gcl {
@@ -17,7 +23,8 @@ define_language! {
         "^" = Pow([Id; 2]),
         Number(crate::ast::Int),
         Variable(Variable),
-        Array(Array, Id),
+        // - Arrays, where the first operand is the name of the array
+        "select" = Select([Id; 2]),
         // Functions
         "division" = Division([Id; 2]),
         "min" = Min([Id; 2]),
@@ -48,46 +55,109 @@ define_language! {
     }
 }
 
+/// Tracks whether the expressions of an e-class are defined in every memory.
+/// Division, powers, reads of arrays, `fac` and `fib` can fail, and rewrites
+/// which drop a subexpression require it to be defined, such that, for
+/// example, `(x / 0) * 0` is not rewritten to `0`.
+#[derive(Debug, Default)]
+pub struct Definedness;
+
+impl egg::Analysis<Gcl> for Definedness {
+    type Data = bool;
+
+    fn make(egraph: &EGraph, enode: &Gcl) -> Self::Data {
+        match enode {
+            Gcl::Division(_) | Gcl::Pow(_) | Gcl::Select(_) | Gcl::Fac(_) | Gcl::Fib(_) => false,
+            _ => enode.all(|id| egraph[id].data),
+        }
+    }
+
+    fn merge(&mut self, a: &mut Self::Data, b: Self::Data) -> egg::DidMerge {
+        egg::merge_max(a, b)
+    }
+}
+
+fn is_defined(var: &str) -> impl Fn(&mut EGraph, Id, &egg::Subst) -> bool {
+    let var: egg::Var = var.parse().unwrap();
+    move |egraph, _, subst| egraph[subst[var]].data
+}
+
+/// Rewrites which hold for all integers, ignoring overflow. Rules which drop
+/// a subexpression are guarded by [`Definedness`], and there are deliberately
+/// no rules for division, as most identities for division of rationals do
+/// not hold for integer division.
 fn make_rules() -> Vec<Rewrite> {
-    vec![
+    let mut rules = vec![
         rw!("comm-add";  "(+ ?a ?b)"        => "(+ ?b ?a)"),
         rw!("comm-mul";  "(* ?a ?b)"        => "(* ?b ?a)"),
         rw!("assoc-add"; "(+ ?a (+ ?b ?c))" => "(+ (+ ?a ?b) ?c)"),
         rw!("assoc-mul"; "(* ?a (* ?b ?c))" => "(* (* ?a ?b) ?c)"),
-        // rw!("sub-canon"; "(- ?a ?b)" => "(+ ?a (* -1 ?b))"),
-        // rw!("canon-sub"; "(+ ?a (* -1 ?b))"   => "(- ?a ?b)"),
-        // rw!("zero-add"; "(+ ?a 0)" => "?a"),
-        // rw!("zero-mul"; "(* ?a 0)" => "0"),
-        // rw!("one-mul";  "(* ?a 1)" => "?a"),
-        // rw!("add-zero"; "?a" => "(+ ?a 0)"),
-        // rw!("mul-one";  "?a" => "(* ?a 1)"),
-        // rw!("cancel-sub"; "(- ?a ?a)" => "0"),
-        // rw!("distribute"; "(* ?a (+ ?b ?c))"        => "(+ (* ?a ?b) (* ?a ?c))"),
-        // rw!("factor"    ; "(+ (* ?a ?b) (* ?a ?c))" => "(* ?a (+ ?b ?c))"),
-        // rw!("pow-mul"; "(* (^ ?a ?b) (^ ?a ?c))" => "(^ ?a (+ ?b ?c))"),
-        // rw!("pow0"; "(^ ?x 0)" => "1"
-        //     if is_not_zero("?x")),
-        // rw!("pow1"; "(^ ?x 1)" => "?x"),
-        // rw!("pow2"; "(^ ?x 2)" => "(* ?x ?x)"),
-        // rw!("pow-recip"; "(^ ?x -1)" => "(/ 1 ?x)"
-        //     if is_not_zero("?x")),
-        // rw!("recip-mul-div"; "(* ?x (/ 1 ?x))" => "1" if is_not_zero("?x")),
-        // rw!("desugar-ne"; "(!= ?a ?b)" => "(! (= ?a ?b))"),
-        // rw!("desugar-lq"; "(<= ?a ?b)" => "(| (= ?a ?b) (< ?a ?b))"),
-        // rw!("desugar-gq"; "(>= ?a ?b)" => "(! (< ?a ?b))"),
-        // rw!("desugar-ge"; "(> ?a ?b)" => "(! (<= ?a ?b))"),
-        // rw!("desugar-and-land"; "(&& ?a ?b)" => "(& ?a ?b)"),
-        // rw!("desugar-or-lor"; "(|| ?a ?b)" => "(| ?a ?b)"),
-        // rw!("desugar-imp"; "(==> ?a ?b)" => "(| (! ?a) ?b)"),
-        rw!("comm-or";  "(|| ?a ?b)"        => "(|| ?b ?a)"),
+        rw!("zero-add"; "(+ ?a 0)" => "?a"),
+        rw!("zero-sub"; "(- ?a 0)" => "?a"),
+        rw!("one-mul";  "(* ?a 1)" => "?a"),
+        rw!("zero-mul"; "(* ?a 0)" => "0" if is_defined("?a")),
+        rw!("cancel-sub"; "(- ?a ?a)" => "0" if is_defined("?a")),
+        rw!("neg-neg"; "(- 0 (- 0 ?a))" => "?a"),
+        rw!("sub-neg"; "(- ?a (- 0 ?b))" => "(+ ?a ?b)"),
+        rw!("add-neg"; "(+ ?a (- 0 ?b))" => "(- ?a ?b)"),
+        rw!("distribute-add"; "(* ?a (+ ?b ?c))" => "(+ (* ?a ?b) (* ?a ?c))"),
+        rw!("distribute-sub"; "(* ?a (- ?b ?c))" => "(- (* ?a ?b) (* ?a ?c))"),
+        rw!("factor-add"; "(+ (* ?a ?b) (* ?a ?c))" => "(* ?a (+ ?b ?c))"),
+        rw!("factor-sub"; "(- (* ?a ?b) (* ?a ?c))" => "(* ?a (- ?b ?c))"),
+        rw!("pow0"; "(^ ?a 0)" => "1" if is_defined("?a")),
+        rw!("pow1"; "(^ ?a 1)" => "?a"),
+        rw!("pow2"; "(^ ?a 2)" => "(* ?a ?a)"),
+        rw!("comm-min"; "(min ?a ?b)" => "(min ?b ?a)"),
+        rw!("comm-max"; "(max ?a ?b)" => "(max ?b ?a)"),
+        rw!("comm-eq"; "(= ?a ?b)"  => "(= ?b ?a)"),
+        rw!("comm-ne"; "(!= ?a ?b)" => "(!= ?b ?a)"),
+        rw!("desugar-ne"; "(!= ?a ?b)" => "(! (= ?a ?b))"),
+        rw!("desugar-gt"; "(> ?a ?b)"  => "(< ?b ?a)"),
+        rw!("desugar-ge"; "(>= ?a ?b)" => "(<= ?b ?a)"),
+        rw!("desugar-le"; "(<= ?a ?b)" => "(! (< ?b ?a))"),
+        rw!("desugar-imp"; "(==> ?a ?b)" => "(| (! ?a) ?b)"),
+        rw!("double-neg"; "(! (! ?a))" => "?a"),
+        rw!("not-true";  "(! true)"  => "false"),
+        rw!("not-false"; "(! false)" => "true"),
+        // NOTE: The short-circuiting operators are only commutative when both
+        // operands are defined, which follows from the rules below turning
+        // them into the strict ones and back
         rw!("comm-lor";  "(| ?a ?b)"        => "(| ?b ?a)"),
         rw!("comm-and";  "(& ?a ?b)"        => "(& ?b ?a)"),
-        rw!("comm-land";  "(&& ?a ?b)"        => "(&& ?b ?a)"),
         rw!("assoc-or"; "(|| ?a (|| ?b ?c))" => "(|| (|| ?a ?b) ?c)"),
         rw!("assoc-lor"; "(| ?a (| ?b ?c))" => "(| (| ?a ?b) ?c)"),
         rw!("assoc-and"; "(&& ?a (&& ?b ?c))" => "(&& (&& ?a ?b) ?c)"),
         rw!("assoc-land"; "(& ?a (& ?b ?c))" => "(& (& ?a ?b) ?c)"),
-    ]
+        rw!("idem-and"; "(& ?a ?a)" => "?a"),
+        rw!("idem-or";  "(| ?a ?a)" => "?a"),
+        rw!("true-and";   "(&& true ?a)" => "?a"),
+        rw!("true-land";  "(& true ?a)"  => "?a"),
+        rw!("false-or";   "(|| false ?a)" => "?a"),
+        rw!("false-lor";  "(| false ?a)"  => "?a"),
+        rw!("false-and";  "(&& false ?a)" => "false"),
+        rw!("false-land"; "(& false ?a)"  => "false" if is_defined("?a")),
+        rw!("true-or";    "(|| true ?a)"  => "true"),
+        rw!("true-lor";   "(| true ?a)"   => "true" if is_defined("?a")),
+        // NOTE: The short-circuiting operators only differ from the strict
+        // ones when the right operand is undefined
+        rw!("strict-and";  "(&& ?a ?b)" => "(& ?a ?b)"  if is_defined("?b")),
+        rw!("lazy-and";    "(& ?a ?b)"  => "(&& ?a ?b)" if is_defined("?b")),
+        rw!("strict-or";   "(|| ?a ?b)" => "(| ?a ?b)"  if is_defined("?b")),
+        rw!("lazy-or";     "(| ?a ?b)"  => "(|| ?a ?b)" if is_defined("?b")),
+        rw!("distribute-and"; "(& ?a (| ?b ?c))" => "(| (& ?a ?b) (& ?a ?c))"),
+        rw!("distribute-or";  "(| ?a (& ?b ?c))" => "(& (| ?a ?b) (| ?a ?c))"),
+    ];
+    rules.extend(
+        [
+            rw!("de-morgan-and";  "(! (&& ?a ?b))" <=> "(|| (! ?a) (! ?b))"),
+            rw!("de-morgan-land"; "(! (& ?a ?b))"  <=> "(| (! ?a) (! ?b))"),
+            rw!("de-morgan-or";   "(! (|| ?a ?b))" <=> "(&& (! ?a) (! ?b))"),
+            rw!("de-morgan-lor";  "(! (| ?a ?b))"  <=> "(& (! ?a) (! ?b))"),
+        ]
+        .into_iter()
+        .flatten(),
+    );
+    rules
 }
 
 pub trait IntoEgg {
@@ -102,18 +172,18 @@ pub trait IntoEgg {
 
 impl IntoEgg for AExpr {
     fn egg(&self) -> String {
-        match self {
-            AExpr::Number(n) => format!("{n}"),
-            AExpr::Reference(t) => match t {
+        match &self.kind {
+            AExprKind::Number(n) => format!("{n}"),
+            AExprKind::Reference(t) => match t {
                 Target::Variable(v) => format!("{v}"),
-                Target::Array(arr, idx) => format!("({arr} {})", idx.egg()),
+                Target::Array(arr, idx) => format!("(select {arr} {})", idx.egg()),
             },
-            AExpr::Binary(lhs, AOp::Divide, rhs) => {
+            AExprKind::Binary(lhs, AOp::Divide, rhs) => {
                 format!("(division {} {})", lhs.egg(), rhs.egg())
             }
-            AExpr::Binary(lhs, op, rhs) => format!("({op} {} {})", lhs.egg(), rhs.egg()),
-            AExpr::Minus(e) => format!("(- 0 {})", e.egg()),
-            AExpr::Function(fun) => fun.egg(),
+            AExprKind::Binary(lhs, op, rhs) => format!("({op} {} {})", lhs.egg(), rhs.egg()),
+            AExprKind::Minus(e) => format!("(- 0 {})", e.egg()),
+            AExprKind::Function(fun) => fun.egg(),
         }
     }
 }
@@ -136,17 +206,19 @@ impl IntoEgg for Function {
 
 impl IntoEgg for BExpr {
     fn egg(&self) -> String {
-        match self {
-            BExpr::Bool(b) => format!("{b}"),
-            BExpr::Rel(l, op, r) => format!("({op} {} {})", l.egg(), r.egg()),
-            BExpr::Logic(l, op, r) => format!("({op} {} {})", l.egg(), r.egg()),
-            BExpr::Not(b) => format!("(! {})", b.egg()),
-            BExpr::Quantified(q, x, b) => format!("({q} {x} {})", b.egg()),
+        match &self.kind {
+            BExprKind::Bool(b) => format!("{b}"),
+            BExprKind::Rel(l, op, r) => format!("({op} {} {})", l.egg(), r.egg()),
+            BExprKind::Logic(l, op, r) => format!("({op} {} {})", l.egg(), r.egg()),
+            BExprKind::Not(b) => format!("(! {})", b.egg()),
+            BExprKind::Quantified(q, x, b) => format!("({q} {x} {})", b.egg()),
         }
     }
 }
 
 impl BExpr {
+    /// Simplifies the predicate and names the quantified variables `_f0`,
+    /// `_f1`, ... in the order they appear
     pub fn renumber_quantifiers(&self) -> BExpr {
         // NOTE: We do two passes, otherwise expressions like these wouldn't be equal:
         //   exists _f0 :: exists _f1 :: _f0 = _f1
@@ -154,64 +226,76 @@ impl BExpr {
         //
         // By constructing identifiers with invalid names, we are sure that
         // we don't interfere with anything already defined.
-        self.renumber_quantifiers_inner("not a valid ident", &mut 0)
+        self.simplify()
+            .renumber_quantifiers_inner("not a valid ident", &mut 0)
             .renumber_quantifiers_inner("f", &mut 0)
     }
     fn renumber_quantifiers_inner(&self, f: &str, count: &mut u64) -> BExpr {
-        match self
-            .semantics(&Default::default())
-            .map(BExpr::Bool)
-            .unwrap_or_else(|_| self.clone())
-        {
-            BExpr::Bool(b) => BExpr::Bool(b),
-            BExpr::Rel(l, op, r) => BExpr::Rel(l.simplify(), op, r.simplify()),
-            BExpr::Logic(l, op, r) => {
+        match &self.kind {
+            BExprKind::Bool(_) | BExprKind::Rel(_, _, _) => self.clone(),
+            BExprKind::Logic(l, op, r) => {
                 let l = l.renumber_quantifiers_inner(f, count);
                 let r = r.renumber_quantifiers_inner(f, count);
-
-                match (l, op, r) {
-                    (BExpr::Bool(true), LogicOp::And, x) | (x, LogicOp::And, BExpr::Bool(true)) => {
-                        x
-                    }
-                    (BExpr::Bool(false), LogicOp::And, _)
-                    | (_, LogicOp::And, BExpr::Bool(false)) => BExpr::Bool(false),
-                    (BExpr::Bool(false), LogicOp::Or, x) | (x, LogicOp::Or, BExpr::Bool(false)) => {
-                        x
-                    }
-                    (BExpr::Bool(true), LogicOp::Or, _) | (_, LogicOp::Or, BExpr::Bool(true)) => {
-                        BExpr::Bool(true)
-                    }
-                    (l, op, r) => BExpr::logic(l, op, r),
-                }
+                BExpr::logic(l, *op, r)
             }
-            BExpr::Not(x) => {
-                let x = x.renumber_quantifiers_inner(f, count);
-                match x {
-                    BExpr::Bool(b) => BExpr::Bool(!b),
-                    x => BExpr::Not(Box::new(x)),
-                }
+            BExprKind::Not(x) => {
+                BExprKind::Not(Box::new(x.renumber_quantifiers_inner(f, count))).into()
             }
-            BExpr::Quantified(q, t, e) => {
+            BExprKind::Quantified(q, t, e) => {
                 let x = Target::Variable(Variable(format!("_{f}{count}")));
                 *count += 1;
-                BExpr::Quantified(
-                    q,
+                BExprKind::Quantified(
+                    *q,
                     x.clone().unit(),
                     Box::new(
-                        e.subst_var(&t, &AExpr::Reference(x))
+                        e.subst_var(t, &AExprKind::Reference(x).into())
                             .renumber_quantifiers_inner(f, count),
                     ),
                 )
+                .into()
             }
         }
     }
 }
 
+/// Decides equivalence of expressions up to the rewrites of [`make_rules`].
+/// All expressions must be registered before calling [`EquivChecker::run`].
 pub struct EquivChecker {
     rules: Vec<Rewrite>,
     runner: Runner,
 }
 impl EquivChecker {
+    /// Records the rewrites applied, which is needed for
+    /// [`EquivChecker::explain`] and [`EquivChecker::rewrite_path`]. This
+    /// makes rewriting considerably slower, and must be enabled before any
+    /// expression is registered.
+    pub fn with_explanations(self) -> Self {
+        Self {
+            runner: self.runner.with_explanations_enabled(),
+            ..self
+        }
+    }
+    /// Stops the rewriting once the e-graph has this many nodes
+    pub fn with_node_limit(self, limit: usize) -> Self {
+        Self {
+            runner: self.runner.with_node_limit(limit),
+            ..self
+        }
+    }
+    /// Stops the rewriting after this many iterations of applying every rule
+    pub fn with_iter_limit(self, limit: usize) -> Self {
+        Self {
+            runner: self.runner.with_iter_limit(limit),
+            ..self
+        }
+    }
+    /// Stops the rewriting after this much time has passed
+    pub fn with_time_limit(self, limit: Duration) -> Self {
+        Self {
+            runner: self.runner.with_time_limit(limit),
+            ..self
+        }
+    }
     pub fn register(&mut self, ex: &impl IntoEgg) -> RecExpr<Gcl> {
         let expr = ex.rec_expr().unwrap();
         self.runner.egraph.add_expr(&expr);
@@ -223,13 +307,42 @@ impl EquivChecker {
     pub fn are_equivalent(&self, x: &RecExpr<Gcl>, y: &RecExpr<Gcl>) -> bool {
         !self.runner.egraph.equivs(x, y).is_empty()
     }
+    /// The rewrites showing that `x` and `y` are equivalent, one step per
+    /// line, or `None` if they are not. Requires
+    /// [explanations](Self::with_explanations).
+    pub fn explain(&mut self, x: &RecExpr<Gcl>, y: &RecExpr<Gcl>) -> Option<String> {
+        if !self.are_equivalent(x, y) {
+            return None;
+        }
+        Some(self.runner.explain_equivalence(x, y).get_flat_string())
+    }
+    /// The smallest expression `x` was rewritten to
+    pub fn simplest(&self, x: &RecExpr<Gcl>) -> RecExpr<Gcl> {
+        let id = self
+            .runner
+            .egraph
+            .lookup_expr(x)
+            .expect("expression should be registered");
+        Extractor::new(&self.runner.egraph, AstSize).find_best(id).1
+    }
+    /// The rewrites from `x` to the [smallest expression](Self::simplest) it
+    /// was rewritten to, one step per line. Requires
+    /// [explanations](Self::with_explanations).
+    pub fn rewrite_path(&mut self, x: &RecExpr<Gcl>) -> String {
+        let simplest = self.simplest(x);
+        self.runner
+            .explain_equivalence(x, &simplest)
+            .get_flat_string()
+    }
 }
 
 impl Default for EquivChecker {
     fn default() -> Self {
         EquivChecker {
             rules: make_rules(),
-            runner: Runner::default(), //.with_explanations_enabled(),
+            runner: Runner::default()
+                .with_node_limit(3_000)
+                .with_time_limit(Duration::from_secs(1)),
         }
     }
 }
@@ -238,25 +351,25 @@ impl Default for EquivChecker {
 fn egg_quantifiers() -> color_eyre::Result<()> {
     use crate::ast::Quantifier;
 
-    color_eyre::install()?;
-
     let mut checker = EquivChecker::default();
 
-    let forall = BExpr::Quantified(
+    let forall: BExpr = BExprKind::Quantified(
         Quantifier::Forall,
         Target::Variable("x".parse().unwrap()),
-        Box::new(BExpr::Bool(true)),
-    );
+        Box::new(BExprKind::Bool(true).into()),
+    )
+    .into();
     let forall_expr = checker.register(&forall);
     assert_eq!(forall_expr.to_string(), "(forall x true)");
     let forall_re: RecExpr<Gcl> = forall_expr.to_string().parse()?;
     assert_eq!(forall_expr, forall_re);
 
-    let exists = BExpr::Quantified(
+    let exists: BExpr = BExprKind::Quantified(
         Quantifier::Exists,
         Target::Variable("x".parse().unwrap()),
-        Box::new(BExpr::Bool(true)),
-    );
+        Box::new(BExprKind::Bool(true).into()),
+    )
+    .into();
     let exists_expr = checker.register(&exists);
     assert_eq!(exists_expr.to_string(), "(exists x true)");
     let exists_re: RecExpr<Gcl> = exists_expr.to_string().parse()?;
@@ -273,18 +386,75 @@ fn egg_quantifiers() -> color_eyre::Result<()> {
 fn egg_arrays() -> color_eyre::Result<()> {
     use pretty_assertions::assert_eq;
 
-    color_eyre::install()?;
-
     let mut checker = EquivChecker::default();
-    let a = AExpr::Reference(Target::Array(
-        Array("a".to_string()),
-        Box::new(AExpr::Number(0)),
-    ));
+    let a: AExpr = AExprKind::Reference(Target::Array(
+        crate::ast::Array("a".to_string()),
+        Box::new(AExprKind::Number(0).into()),
+    ))
+    .into();
     a.rec_expr().unwrap();
     let a_expr = checker.register(&a);
-    assert_eq!(a_expr.to_string(), "(a 0)");
+    assert_eq!(a_expr.to_string(), "(select a 0)");
     let a_re: RecExpr<Gcl> = a_expr.to_string().parse()?;
     assert_eq!(a_expr, a_re);
 
     Ok(())
 }
+
+#[test]
+fn egg_sound_rules() -> miette::Result<()> {
+    use crate::parse::{parse_aexpr, parse_predicate};
+
+    fn check<T: IntoEgg>(
+        parse: impl Fn(&str) -> Result<T, crate::parse::ParseError>,
+        pairs: &[(&str, &str, bool)],
+    ) -> miette::Result<()> {
+        for &(a, b, expected) in pairs {
+            let mut checker = EquivChecker::default().with_explanations();
+            let x = checker.register(&parse(a)?);
+            let y = checker.register(&parse(b)?);
+            checker.run();
+            assert_eq!(checker.are_equivalent(&x, &y), expected, "`{a}` and `{b}`");
+            assert_eq!(checker.explain(&x, &y).is_some(), expected);
+        }
+        Ok(())
+    }
+
+    check(
+        parse_aexpr,
+        &[
+            ("x + 0", "x", true),
+            ("x * 0", "0", true),
+            ("x - x", "0", true),
+            ("2 * (x + y)", "y * 2 + x * 2", true),
+            ("-(-x)", "x", true),
+            ("x ^ 2", "x * x", true),
+            ("(x / 0) * 0", "0", false),
+            ("(x / 2) * 2", "x", false),
+            ("A[i] - A[i]", "0", false),
+        ],
+    )?;
+    check(
+        parse_predicate,
+        &[
+            ("a <= b", "!(a > b)", true),
+            ("x != y", "!(y = x)", true),
+            ("a > 0 ==> b > 0", "!(a > 0) || b > 0", true),
+            ("!(a > 0 & b > 0)", "!(a > 0) | !(b > 0)", true),
+            ("!!(x >= y)", "y <= x", true),
+            ("x > 0 && y > 1", "x > 0 & y > 1", true),
+            ("x > 0 && y / x > 1", "x > 0 & y / x > 1", false),
+            ("false & x / 0 = 1", "false", false),
+            ("x = 0 ==> 1 / x > 0", "!(x = 0) || 1 / x > 0", false),
+        ],
+    )?;
+
+    let mut checker = EquivChecker::default().with_explanations();
+    let x = checker.register(&parse_predicate("!(x + 0 > y)")?);
+    checker.run();
+    let simplest = checker.simplest(&x);
+    assert!(["(<= x y)", "(! (< y x))"].contains(&simplest.to_string().as_str()));
+    assert!(checker.rewrite_path(&x).contains("zero-add"));
+
+    Ok(())
+}
@@ -0,0 +1,335 @@
+//! Environments for the analyses in [`crate::bit_vector`].
+//!
+//! The facts at each node are given as sets of strings, except for reaching
+//! definitions, which are given as triples. Expressions are compared by their
+//! syntax tree, so any parenthesization is accepted.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use indexmap::IndexMap;
+use itertools::Itertools;
+
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::{mono_analysis, FiFo, NodeOrder},
+    ast::{AExpr, Commands},
+    bit_vector::{
+        AvailableExpressions, Definition, LiveVariables, ReachingDefinitions, VeryBusyExpressions,
+    },
+    generation::Generate,
+    parse::parse_aexpr,
+    pg::{Determinism, Node, ProgramGraph},
+};
+
+use super::{
+    check_program, Analysis, EnvError, Environment, Markdown, ToMarkdown, ValidationResult,
+};
+
+#[derive(Debug)]
+pub struct LiveVariablesEnv;
+#[derive(Debug)]
+pub struct ReachingDefinitionsEnv;
+#[derive(Debug)]
+pub struct AvailableExpressionsEnv;
+#[derive(Debug)]
+pub struct VeryBusyExpressionsEnv;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BitVectorAnalysisInput {
+    pub determinism: Determinism,
+}
+
+impl Generate for BitVectorAnalysisInput {
+    type Context = Commands;
+
+    fn gen<R: rand::Rng>(_cx: &mut Self::Context, rng: &mut R) -> Self {
+        BitVectorAnalysisInput {
+            determinism: [Determinism::Deterministic, Determinism::NonDeterministic]
+                .choose(rng)
+                .copied()
+                .unwrap(),
+        }
+    }
+}
+
+impl ToMarkdown for BitVectorAnalysisInput {
+    fn to_markdown(&self) -> Markdown {
+        let mut table = comfy_table::Table::new();
+        table
+            .load_preset(comfy_table::presets::ASCII_MARKDOWN)
+            .set_header(["Input"]);
+
+        table.add_row([
+            "Determinism:",
+            match self.determinism {
+                Determinism::Deterministic => "**✓**",
+                Determinism::NonDeterministic => "**✕**",
+            },
+        ]);
+
+        format!("{table}").into()
+    }
+}
+
+/// A definition as given in [`ReachingDefinitionsEnv`] outputs. The initial
+/// definition of a variable or array has no `from` node.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct ReachingDefinition {
+    pub target: String,
+    pub from: Option<String>,
+    pub to: String,
+}
+
+impl From<Definition> for ReachingDefinition {
+    fn from(d: Definition) -> Self {
+        ReachingDefinition {
+            target: d.target.to_string(),
+            from: d.from.map(|n| n.to_string()),
+            to: d.to.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for ReachingDefinition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let from = self.from.as_deref().unwrap_or("?");
+        write!(f, "({}, {from}, {})", self.target, self.to)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BitVectorAnalysisOutput<T: Ord> {
+    pub initial_node: String,
+    pub final_node: String,
+    pub nodes: IndexMap<String, BTreeSet<T>>,
+}
+
+impl<T: Ord> BitVectorAnalysisOutput<T> {
+    fn new<F>(facts: HashMap<Node, F>, f: impl Fn(F) -> BTreeSet<T>) -> Self {
+        BitVectorAnalysisOutput {
+            initial_node: Node::Start.to_string(),
+            final_node: Node::End.to_string(),
+            nodes: facts
+                .into_iter()
+                .sorted_by_key(|(n, _)| *n)
+                .map(|(n, fact)| (n.to_string(), f(fact)))
+                .collect(),
+        }
+    }
+}
+
+impl<T: Ord + std::fmt::Display> ToMarkdown for BitVectorAnalysisOutput<T> {
+    fn to_markdown(&self) -> Markdown {
+        let mut table = comfy_table::Table::new();
+        table
+            .load_preset(comfy_table::presets::ASCII_MARKDOWN)
+            .set_header(["Node", "Facts"]);
+
+        for (n, facts) in self
+            .nodes
+            .iter()
+            .sorted_by_key(|(n, _)| NodeOrder::parse(n))
+        {
+            table.add_row([
+                n.to_string(),
+                format!(
+                    "{{{}}}",
+                    facts.iter().map(|x| format!("`{x}`")).format(", ")
+                ),
+            ]);
+        }
+
+        format!("{table}").into()
+    }
+}
+
+/// Compares the facts at every node, after bringing them on a canonical form
+/// with `normalize`
+fn validate_nodes<T, N>(
+    reference: &BitVectorAnalysisOutput<T>,
+    output: &BitVectorAnalysisOutput<T>,
+    normalize: impl Fn(&T) -> Result<N, String>,
+) -> ValidationResult
+where
+    T: Ord + std::fmt::Display,
+    N: Ord,
+{
+    let normalize_all = |facts: &BTreeSet<T>| -> Result<BTreeMap<N, String>, String> {
+        facts
+            .iter()
+            .map(|x| Ok((normalize(x)?, x.to_string())))
+            .collect()
+    };
+
+    for (n, expected) in &reference.nodes {
+        let Some(actual) = output.nodes.get(n) else {
+            return ValidationResult::Mismatch {
+                reason: format!("No facts were given for node {n}"),
+            };
+        };
+        let (expected, actual) = match (normalize_all(expected), normalize_all(actual)) {
+            (Ok(expected), Ok(actual)) => (expected, actual),
+            (Err(reason), _) | (_, Err(reason)) => return ValidationResult::Mismatch { reason },
+        };
+        if expected.keys().ne(actual.keys()) {
+            let missing = expected
+                .iter()
+                .filter(|(k, _)| !actual.contains_key(k))
+                .map(|(_, x)| format!("`{x}`"))
+                .format(", ");
+            let extra = actual
+                .iter()
+                .filter(|(k, _)| !expected.contains_key(k))
+                .map(|(_, x)| format!("`{x}`"))
+                .format(", ");
+            return ValidationResult::Mismatch {
+                reason: format!(
+                    "At node {n} the facts {{{missing}}} were missing and {{{extra}}} were not expected"
+                ),
+            };
+        }
+    }
+
+    if let Some(n) = output
+        .nodes
+        .keys()
+        .find(|n| !reference.nodes.contains_key(*n))
+    {
+        return ValidationResult::Mismatch {
+            reason: format!("Node {n} does not exist in the program graph"),
+        };
+    }
+
+    ValidationResult::CorrectTerminated
+}
+
+fn parse_expression(src: &str) -> Result<AExpr, String> {
+    parse_aexpr(src).map_err(|_| format!("`{src}` is not an arithmetic expression"))
+}
+
+impl Environment for LiveVariablesEnv {
+    type Input = BitVectorAnalysisInput;
+
+    type Output = BitVectorAnalysisOutput<String>;
+
+    const ANALYSIS: Analysis = Analysis::LiveVariables;
+
+    fn run(&self, cmds: &Commands, input: &Self::Input) -> Result<Self::Output, EnvError> {
+        check_program(cmds)?;
+
+        let pg = ProgramGraph::new(input.determinism, cmds);
+        let facts = mono_analysis::<_, FiFo>(LiveVariables, &pg).facts;
+
+        Ok(BitVectorAnalysisOutput::new(facts, |live| {
+            live.into_iter().map(|t| t.to_string()).collect()
+        }))
+    }
+
+    fn validate(
+        &self,
+        cmds: &Commands,
+        input: &Self::Input,
+        output: &Self::Output,
+    ) -> Result<ValidationResult, EnvError> {
+        let reference = self.run(cmds, input)?;
+        Ok(validate_nodes(&reference, output, |x| Ok(x.clone())))
+    }
+}
+
+impl Environment for ReachingDefinitionsEnv {
+    type Input = BitVectorAnalysisInput;
+
+    type Output = BitVectorAnalysisOutput<ReachingDefinition>;
+
+    const ANALYSIS: Analysis = Analysis::ReachingDefinitions;
+
+    fn run(&self, cmds: &Commands, input: &Self::Input) -> Result<Self::Output, EnvError> {
+        check_program(cmds)?;
+
+        let pg = ProgramGraph::new(input.determinism, cmds);
+        let facts = mono_analysis::<_, FiFo>(ReachingDefinitions, &pg).facts;
+
+        Ok(BitVectorAnalysisOutput::new(facts, |reaching| {
+            reaching.into_iter().map(ReachingDefinition::from).collect()
+        }))
+    }
+
+    fn validate(
+        &self,
+        cmds: &Commands,
+        input: &Self::Input,
+        output: &Self::Output,
+    ) -> Result<ValidationResult, EnvError> {
+        let reference = self.run(cmds, input)?;
+        Ok(validate_nodes(&reference, output, |x| Ok(x.clone())))
+    }
+}
+
+impl Environment for AvailableExpressionsEnv {
+    type Input = BitVectorAnalysisInput;
+
+    type Output = BitVectorAnalysisOutput<String>;
+
+    const ANALYSIS: Analysis = Analysis::AvailableExpressions;
+
+    fn run(&self, cmds: &Commands, input: &Self::Input) -> Result<Self::Output, EnvError> {
+        check_program(cmds)?;
+
+        let pg = ProgramGraph::new(input.determinism, cmds);
+        let universe = pg.expressions();
+        let facts = mono_analysis::<_, FiFo>(AvailableExpressions, &pg).facts;
+
+        Ok(BitVectorAnalysisOutput::new(facts, |available| {
+            available
+                .into_elements(universe.iter().cloned())
+                .into_iter()
+                .map(|e| e.to_string())
+                .collect()
+        }))
+    }
+
+    fn validate(
+        &self,
+        cmds: &Commands,
+        input: &Self::Input,
+        output: &Self::Output,
+    ) -> Result<ValidationResult, EnvError> {
+        let reference = self.run(cmds, input)?;
+        Ok(validate_nodes(&reference, output, |x| parse_expression(x)))
+    }
+}
+
+impl Environment for VeryBusyExpressionsEnv {
+    type Input = BitVectorAnalysisInput;
+
+    type Output = BitVectorAnalysisOutput<String>;
+
+    const ANALYSIS: Analysis = Analysis::VeryBusyExpressions;
+
+    fn run(&self, cmds: &Commands, input: &Self::Input) -> Result<Self::Output, EnvError> {
+        check_program(cmds)?;
+
+        let pg = ProgramGraph::new(input.determinism, cmds);
+        let universe = pg.expressions();
+        let facts = mono_analysis::<_, FiFo>(VeryBusyExpressions, &pg).facts;
+
+        Ok(BitVectorAnalysisOutput::new(facts, |busy| {
+            busy.into_elements(universe.iter().cloned())
+                .into_iter()
+                .map(|e| e.to_string())
+                .collect()
+        }))
+    }
+
+    fn validate(
+        &self,
+        cmds: &Commands,
+        input: &Self::Input,
+        output: &Self::Output,
+    ) -> Result<ValidationResult, EnvError> {
+        let reference = self.run(cmds, input)?;
+        Ok(validate_nodes(&reference, output, |x| parse_expression(x)))
+    }
+}
@@ -1,17 +1,24 @@
-use std::collections::HashMap;
+use std::collections::{hash_map::Entry, HashMap, HashSet, VecDeque};
 
-use graphviz_rust::dot_structures::{Attribute, Id};
-use itertools::Itertools;
+use graphviz_rust::dot_structures::{self, Id};
+use itertools::{chain, Itertools};
+use petgraph::{
+    graph::{EdgeIndex, NodeIndex},
+    visit::EdgeRef,
+};
 use serde::{Deserialize, Serialize};
-use tracing::debug;
 
 use crate::{
+    analysis::NodeOrder,
     ast::Commands,
     generation::Generate,
-    pg::{Determinism, ProgramGraph},
+    parse,
+    pg::{Action, Determinism, GraphFormat, JsonGraph, ProgramGraph},
 };
 
-use super::{Analysis, EnvError, Environment, Markdown, ToMarkdown};
+use super::{
+    check_program, Analysis, EnvError, Environment, Markdown, ToMarkdown, ValidationResult,
+};
 
 #[derive(Debug)]
 pub struct GraphEnv;
@@ -19,11 +26,18 @@ pub struct GraphEnv;
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct GraphEnvInput {
     pub determinism: Determinism,
+    /// The format the graph is expected in. Only DOT and JSON graphs can be
+    /// validated.
+    #[serde(default)]
+    pub format: GraphFormat,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct GraphEnvOutput {
-    pub dot: String,
+    #[serde(default)]
+    pub format: GraphFormat,
+    #[serde(alias = "dot")]
+    pub graph: String,
 }
 
 impl Generate for GraphEnvInput {
@@ -33,18 +47,28 @@ impl Generate for GraphEnvInput {
         Self {
             // TODO
             determinism: Determinism::Deterministic,
+            format: GraphFormat::Dot,
         }
     }
 }
 
 impl ToMarkdown for GraphEnvInput {
     fn to_markdown(&self) -> Markdown {
-        format!("**Determinism:** {:?}", self.determinism).into()
+        format!(
+            "**Determinism:** {:?}\n\n**Format:** {}",
+            self.determinism, self.format
+        )
+        .into()
     }
 }
 impl ToMarkdown for GraphEnvOutput {
     fn to_markdown(&self) -> Markdown {
-        format!("\n\n```dot\n{}\n```\n\n", self.dot).into()
+        format!(
+            "\n\n```{}\n{}\n```\n\n",
+            self.format.code_block_language(),
+            self.graph
+        )
+        .into()
     }
 }
 
@@ -60,8 +84,13 @@ impl Environment for GraphEnv {
         cmds: &crate::ast::Commands,
         input: &Self::Input,
     ) -> Result<Self::Output, EnvError> {
+        check_program(cmds)?;
+
         let pg = ProgramGraph::new(input.determinism, cmds);
-        Ok(GraphEnvOutput { dot: pg.dot() })
+        Ok(GraphEnvOutput {
+            format: input.format,
+            graph: pg.export(input.format),
+        })
     }
 
     fn validate(
@@ -69,180 +98,495 @@ impl Environment for GraphEnv {
         cmds: &crate::ast::Commands,
         input: &Self::Input,
         output: &Self::Output,
-    ) -> Result<super::ValidationResult, EnvError> {
-        let reference = self.run(cmds, input)?;
-
-        let (a_data, _a_node_mapping, a_graph) = dot_to_petgraph(&reference.dot);
-        let (b_data, _b_node_mapping, b_graph) = dot_to_petgraph(&output.dot);
-
-        eprintln!("{a_graph:?}");
-        eprintln!("{b_graph:?}");
-
-        let a_graph = &a_graph;
-        let b_graph = &b_graph;
-        // let mut binding_1 = |_a: &String, _b: &String| a == b;
-        let mut binding_1 = |_a: &String, _b: &String| true;
-        // let mut binding_2 = |_a: &String, _b: &String| a == b;
-        let mut binding_2 = |_a: &String, _b: &String| true;
-        let res = petgraph::algo::isomorphism::subgraph_isomorphisms_iter(
-            &a_graph,
-            &b_graph,
-            &mut binding_1,
-            &mut binding_2,
-        )
-        .unwrap();
+    ) -> Result<ValidationResult, EnvError> {
+        let pg = ProgramGraph::new(input.determinism, cmds);
 
-        let res = res.collect_vec();
+        let parsed = match input.format {
+            GraphFormat::Dot => dot_to_petgraph(&output.graph),
+            GraphFormat::Json => json_to_petgraph(&output.graph),
+            GraphFormat::Mermaid | GraphFormat::GraphML => {
+                return Err(EnvError::InvalidInputForProgram {
+                    input: super::Input::from_concrete::<Self>(input),
+                    message: format!("graphs in the {} format cannot be validated", input.format),
+                })
+            }
+        };
+        let ParsedGraph {
+            node_mapping,
+            graph,
+            endpoints,
+        } = match parsed {
+            Ok(res) => res,
+            Err(err) => {
+                return Ok(ValidationResult::Mismatch {
+                    reason: format!("could not read the produced graph: {err}"),
+                })
+            }
+        };
+        let given = graph.map(
+            |_, n| n.clone(),
+            |_, label| parse::parse_action(label).map_err(|_| label),
+        );
+        if let Some(Err(label)) = given.edge_weights().find(|action| action.is_err()) {
+            return Ok(ValidationResult::Mismatch {
+                reason: format!("could not parse the edge label {label:?} as an action"),
+            });
+        }
+        let given = given.map(
+            |_, n| n.clone(),
+            |_, action| action.clone().expect("all labels were parsed"),
+        );
 
-        for matching in res {
-            for (a_idx, b_idx) in matching.iter().copied().enumerate() {
-                let a = &a_data[&a_graph.raw_nodes()[a_idx].weight];
-                let b = &b_data[&b_graph.raw_nodes()[b_idx].weight];
-                eprintln!("{a:?} == {b:?}");
+        let Some((given_start, given_end)) = endpoints else {
+            return Ok(ValidationResult::Mismatch {
+                reason: "could not determine the initial and final nodes of the graph. \
+                         Label them `q▷` and `q◀` respectively"
+                    .to_string(),
+            });
+        };
+        let given_start = node_mapping[&given_start];
+        let given_end = node_mapping[&given_end];
 
-                // a_graph.find_edge(a, b)
+        // NOTE: Actions are compared after being printed and parsed again, such
+        // that they agree with what was parsed from the labels
+        let (reference, reference_mapping, _) = pg.as_petgraph();
+        let reference = reference.map(
+            |_, n| *n,
+            |_, a| {
+                let label = a.to_string();
+                parse::parse_action(&label).map_err(|err| format!("{label:?}: {err}"))
+            },
+        );
+        if let Some(Err(err)) = reference.edge_weights().find(|a| a.is_err()) {
+            return Ok(ValidationResult::Mismatch {
+                reason: format!("could not parse the reference action {err}"),
+            });
+        }
+        let reference = reference.map(
+            |_, n| *n,
+            |_, action| action.clone().expect("all actions were parsed"),
+        );
 
-                let a_edges = a_graph
-                    .edges_directed(
-                        petgraph::graph::NodeIndex::new(a_idx),
-                        petgraph::Direction::Outgoing,
-                    )
-                    .map(|e| e.weight())
-                    .collect_vec();
-                let b_edges = b_graph
-                    .edges_directed(
-                        petgraph::graph::NodeIndex::new(b_idx),
-                        petgraph::Direction::Outgoing,
-                    )
-                    .map(|e| e.weight())
-                    .collect_vec();
-                eprintln!("{a_edges:?} == {b_edges:?}");
-            }
+        // NOTE: Program graphs never contain parallel edges, which the
+        // isomorphism check below cannot handle
+        let has_parallel_edges = given
+            .raw_edges()
+            .iter()
+            .map(|e| (e.source(), e.target()))
+            .duplicates()
+            .next()
+            .is_some();
+
+        let is_isomorphic = !has_parallel_edges
+            && petgraph::algo::is_isomorphic_matching(
+                &reference,
+                &given,
+                |a, b| match a {
+                    crate::pg::Node::Start => b == &given[given_start],
+                    crate::pg::Node::End => b == &given[given_end],
+                    crate::pg::Node::Node(_) => b != &given[given_start] && b != &given[given_end],
+                },
+                |a, b| a == b,
+            );
+
+        if is_isomorphic {
+            return Ok(ValidationResult::CorrectTerminated);
         }
 
-        // println!(
-        //     "{}",
-        //     petgraph::dot::Dot::with_config(a_graph, &[petgraph::dot::Config::EdgeNoLabel])
-        // );
-        // println!(
-        //     "{}",
-        //     petgraph::dot::Dot::with_config(b_graph, &[petgraph::dot::Config::EdgeNoLabel])
-        // );
+        let (missing, unexpected) = unmatched_edges(
+            &reference,
+            &given,
+            [
+                (reference_mapping[&crate::pg::Node::Start], given_start),
+                (reference_mapping[&crate::pg::Node::End], given_end),
+            ],
+        );
+
+        let format_edges = |edges: Vec<String>| {
+            if edges.is_empty() {
+                "none".to_string()
+            } else {
+                edges.iter().format(", ").to_string()
+            }
+        };
+
+        Ok(ValidationResult::Mismatch {
+            reason: format!(
+                "the graph is not isomorphic to the reference. \
+                 Edges missing from the given graph: {}. \
+                 Edges not in the reference: {}",
+                format_edges(
+                    missing
+                        .into_iter()
+                        .map(|e| {
+                            let (a, b) = reference.edge_endpoints(e).unwrap();
+                            format!("{} -> {} [{}]", reference[a], reference[b], reference[e])
+                        })
+                        .collect()
+                ),
+                format_edges(
+                    unexpected
+                        .into_iter()
+                        .map(|e| {
+                            let (a, b) = given.edge_endpoints(e).unwrap();
+                            format!("{} -> {} [{}]", given[a], given[b], given[e])
+                        })
+                        .collect()
+                ),
+            ),
+        })
+    }
+}
+
+/// Greedily walks both graphs from the given pairs of corresponding nodes,
+/// matching outgoing edges with equal actions. Returns the edges of the
+/// reference and of the given graph respectively, which could not be matched.
+fn unmatched_edges<N, M>(
+    reference: &petgraph::Graph<N, Action>,
+    given: &petgraph::Graph<M, Action>,
+    anchors: [(NodeIndex, NodeIndex); 2],
+) -> (Vec<EdgeIndex>, Vec<EdgeIndex>) {
+    let mut mapping: HashMap<NodeIndex, NodeIndex> = anchors.into_iter().collect();
+    let mut mapped: HashSet<NodeIndex> = mapping.values().copied().collect();
+    let mut used_reference = HashSet::new();
+    let mut used_given = HashSet::new();
 
-        todo!("MADE IT TO THE END!");
+    let mut queue: VecDeque<_> = anchors.into_iter().collect();
+    while let Some((r, g)) = queue.pop_front() {
+        for r_edge in reference.edges(r) {
+            let r_target = r_edge.target();
+            let matching = given.edges(g).find(|g_edge| {
+                !used_given.contains(&g_edge.id())
+                    && g_edge.weight() == r_edge.weight()
+                    && match mapping.get(&r_target) {
+                        Some(g_target) => *g_target == g_edge.target(),
+                        None => !mapped.contains(&g_edge.target()),
+                    }
+            });
+            if let Some(g_edge) = matching {
+                used_reference.insert(r_edge.id());
+                used_given.insert(g_edge.id());
+                if let Entry::Vacant(entry) = mapping.entry(r_target) {
+                    entry.insert(g_edge.target());
+                    mapped.insert(g_edge.target());
+                    queue.push_back((r_target, g_edge.target()));
+                }
+            }
+        }
     }
+
+    (
+        reference
+            .edge_indices()
+            .filter(|e| !used_reference.contains(e))
+            .collect(),
+        given
+            .edge_indices()
+            .filter(|e| !used_given.contains(e))
+            .collect(),
+    )
+}
+
+fn find_endpoint<'a>(
+    nodes: &'a HashMap<String, Node>,
+    order: NodeOrder<'static>,
+) -> Option<&'a String> {
+    let by_name = nodes
+        .iter()
+        .filter(|(n, node)| {
+            NodeOrder::parse(n) == order
+                || node.label.as_deref().map(NodeOrder::parse) == Some(order)
+        })
+        .map(|(n, _)| n)
+        .exactly_one()
+        .ok();
+
+    // NOTE: If the nodes are not named in a recognizable way, fall back to
+    // the structure of the graph
+    by_name.or_else(|| {
+        nodes
+            .iter()
+            .filter(|(_, node)| match order {
+                NodeOrder::First => node.ingoing.is_empty(),
+                _ => node.outgoing.is_empty(),
+            })
+            .map(|(n, _)| n)
+            .exactly_one()
+            .ok()
+    })
 }
 
 #[derive(Debug, Default)]
 struct Node {
-    attributes: Vec<Attribute>,
+    label: Option<String>,
     outgoing: Vec<String>,
     ingoing: Vec<String>,
 }
-fn dot_to_petgraph(
-    dot: &str,
-) -> (
-    HashMap<String, Node>,
-    HashMap<String, petgraph::graph::NodeIndex>,
-    petgraph::Graph<String, String>,
-) {
+
+/// A graph given by the user, with nodes identified by their names and edges
+/// labelled with the unparsed actions
+struct ParsedGraph {
+    node_mapping: HashMap<String, NodeIndex>,
+    graph: petgraph::Graph<String, String>,
+    /// The initial and final node, if they could be determined
+    endpoints: Option<(String, String)>,
+}
+
+fn json_to_petgraph(json: &str) -> Result<ParsedGraph, String> {
+    let json_graph: JsonGraph = serde_json::from_str(json).map_err(|err| err.to_string())?;
+
+    let mut node_mapping = HashMap::<String, NodeIndex>::new();
+    let mut graph = petgraph::Graph::<String, String>::new();
+
+    let mut node_index = |graph: &mut petgraph::Graph<String, String>, n: &str| {
+        *node_mapping
+            .entry(n.to_string())
+            .or_insert_with_key(|k| graph.add_node(k.to_string()))
+    };
+
+    node_index(&mut graph, &json_graph.initial_node);
+    node_index(&mut graph, &json_graph.final_node);
+    for e in &json_graph.edges {
+        let a = node_index(&mut graph, &e.from);
+        let b = node_index(&mut graph, &e.to);
+        graph.add_edge(a, b, e.action.clone());
+    }
+
+    let endpoints = (json_graph.initial_node != json_graph.final_node)
+        .then_some((json_graph.initial_node, json_graph.final_node));
+
+    Ok(ParsedGraph {
+        node_mapping,
+        graph,
+        endpoints,
+    })
+}
+
+fn dot_to_petgraph(dot: &str) -> Result<ParsedGraph, String> {
     let mut nodes = HashMap::<String, Node>::new();
-    let mut node_mapping = HashMap::<String, petgraph::graph::NodeIndex>::new();
+    let mut node_mapping = HashMap::<String, NodeIndex>::new();
     let mut graph = petgraph::Graph::<String, String>::new();
 
-    let parsed = graphviz_rust::parse(dot).unwrap();
-
-    match parsed {
-        graphviz_rust::dot_structures::Graph::Graph { .. } => todo!(),
-        graphviz_rust::dot_structures::Graph::DiGraph { stmts, .. } => {
-            for stmt in stmts {
-                match stmt {
-                    graphviz_rust::dot_structures::Stmt::Node(n) => {
-                        node_mapping
-                            .entry(n.id.0.to_string())
-                            .or_insert_with_key(|k| graph.add_node(k.to_string()));
-
-                        nodes
-                            .entry(n.id.0.to_string())
-                            .or_default()
-                            .attributes
-                            .extend_from_slice(&n.attributes);
+    let stmts = match graphviz_rust::parse(dot)? {
+        dot_structures::Graph::Graph { .. } => {
+            return Err("expected a directed graph (`digraph`), found `graph`".to_string())
+        }
+        dot_structures::Graph::DiGraph { stmts, .. } => stmts,
+    };
+
+    let mut node_index = |graph: &mut petgraph::Graph<String, String>, n: &str| {
+        *node_mapping
+            .entry(n.to_string())
+            .or_insert_with_key(|k| graph.add_node(k.to_string()))
+    };
+
+    // NOTE: Subgraphs only group nodes and edges visually, so their
+    // statements are flattened into the outer graph
+    let mut stack = stmts;
+    stack.reverse();
+    while let Some(stmt) = stack.pop() {
+        match stmt {
+            dot_structures::Stmt::Node(n) => {
+                let id = id_to_string(&n.id.0);
+                node_index(&mut graph, &id);
+                let node = nodes.entry(id).or_default();
+                if let Some(label) = find_label(&n.attributes) {
+                    node.label = Some(label);
+                }
+            }
+            dot_structures::Stmt::Subgraph(sub) => stack.extend(sub.stmts.into_iter().rev()),
+            // NOTE: Attributes such as `rankdir` do not change the structure
+            dot_structures::Stmt::Attribute(_) | dot_structures::Stmt::GAttribute(_) => {}
+            dot_structures::Stmt::Edge(e) => {
+                let vertices = match e.ty {
+                    dot_structures::EdgeTy::Pair(a, b) => vec![a, b],
+                    dot_structures::EdgeTy::Chain(vs) => vs,
+                };
+                let label = find_label(&e.attributes).ok_or_else(|| {
+                    format!(
+                        "the edge {} has no label",
+                        vertices.iter().map(vertex_to_string).format(" -> ")
+                    )
+                })?;
+                for (a, b) in vertices.iter().tuple_windows() {
+                    for a in vertex_nodes(a) {
+                        for b in vertex_nodes(b) {
+                            let a_id = node_index(&mut graph, &a);
+                            let b_id = node_index(&mut graph, &b);
+                            graph.add_edge(a_id, b_id, label.clone());
+
+                            nodes.entry(a.clone()).or_default().outgoing.push(b.clone());
+                            nodes.entry(b).or_default().ingoing.push(a.clone());
+                        }
                     }
-                    graphviz_rust::dot_structures::Stmt::Subgraph(_) => todo!(),
-                    graphviz_rust::dot_structures::Stmt::Attribute(_) => todo!(),
-                    graphviz_rust::dot_structures::Stmt::GAttribute(_) => todo!(),
-                    graphviz_rust::dot_structures::Stmt::Edge(e) => match e.ty {
-                        graphviz_rust::dot_structures::EdgeTy::Pair(a, b) => match (a, b) {
-                            (
-                                graphviz_rust::dot_structures::Vertex::N(a),
-                                graphviz_rust::dot_structures::Vertex::N(b),
-                            ) => {
-                                let a_id = *node_mapping
-                                    .entry(a.0.to_string())
-                                    .or_insert_with_key(|k| graph.add_node(k.to_string()));
-                                let b_id = *node_mapping
-                                    .entry(b.0.to_string())
-                                    .or_insert_with_key(|k| graph.add_node(k.to_string()));
-                                graph.add_edge(
-                                    a_id,
-                                    b_id,
-                                    e.attributes
-                                        .iter()
-                                        .find_map(|a| match (&a.0, &a.1) {
-                                            (Id::Plain(l), Id::Escaped(v)) if l == "label" => {
-                                                Some(v.to_string())
-                                            }
-                                            (Id::Html(_), Id::Html(_)) => todo!(),
-                                            (Id::Html(_), Id::Escaped(_)) => todo!(),
-                                            (Id::Html(_), Id::Plain(_)) => todo!(),
-                                            (Id::Html(_), Id::Anonymous(_)) => todo!(),
-                                            (Id::Escaped(_), Id::Html(_)) => todo!(),
-                                            (Id::Escaped(_), Id::Escaped(_)) => todo!(),
-                                            (Id::Escaped(_), Id::Plain(_)) => todo!(),
-                                            (Id::Escaped(_), Id::Anonymous(_)) => todo!(),
-                                            (Id::Plain(_), Id::Html(_)) => todo!(),
-                                            (Id::Plain(_), Id::Escaped(_)) => todo!(),
-                                            (Id::Plain(_), Id::Plain(_)) => todo!(),
-                                            (Id::Plain(_), Id::Anonymous(_)) => todo!(),
-                                            (Id::Anonymous(_), Id::Html(_)) => todo!(),
-                                            (Id::Anonymous(_), Id::Escaped(_)) => todo!(),
-                                            (Id::Anonymous(_), Id::Plain(_)) => todo!(),
-                                            (Id::Anonymous(_), Id::Anonymous(_)) => todo!(),
-                                        })
-                                        .unwrap(),
-                                );
-
-                                nodes
-                                    .entry(a.0.to_string())
-                                    .or_default()
-                                    .outgoing
-                                    .push(b.0.to_string());
-                                nodes
-                                    .entry(b.0.to_string())
-                                    .or_default()
-                                    .ingoing
-                                    .push(a.0.to_string());
-                            }
-                            (a, b) => todo!("{a:?} -> {b:?}"),
-                        },
-                        graphviz_rust::dot_structures::EdgeTy::Chain(_) => todo!(),
-                    },
                 }
             }
         }
     }
 
-    let start_node = nodes.iter().find_map(|(n, node)| {
-        (node.outgoing.len() == 1 && node.ingoing.is_empty()).then_some((n, node))
-    });
-    let end_node = nodes.iter().find_map(|(n, node)| {
-        (node.outgoing.is_empty() && node.ingoing.len() == 1).then_some((n, node))
-    });
+    let endpoints = find_endpoint(&nodes, NodeOrder::First)
+        .zip(find_endpoint(&nodes, NodeOrder::Last))
+        .map(|(start, end)| (start.clone(), end.clone()));
+
+    Ok(ParsedGraph {
+        node_mapping,
+        graph,
+        endpoints,
+    })
+}
+
+fn find_label(attributes: &[dot_structures::Attribute]) -> Option<String> {
+    attributes
+        .iter()
+        .find(|a| id_to_string(&a.0) == "label")
+        .map(|a| id_to_string(&a.1))
+}
+
+/// The nodes an edge to or from the given vertex connects to. For subgraphs
+/// this is every node declared in it.
+fn vertex_nodes(v: &dot_structures::Vertex) -> Vec<String> {
+    match v {
+        dot_structures::Vertex::N(n) => vec![id_to_string(&n.0)],
+        dot_structures::Vertex::S(sub) => sub
+            .stmts
+            .iter()
+            .flat_map(|stmt| match stmt {
+                dot_structures::Stmt::Node(n) => vec![id_to_string(&n.id.0)],
+                dot_structures::Stmt::Edge(e) => match &e.ty {
+                    dot_structures::EdgeTy::Pair(a, b) => {
+                        chain!(vertex_nodes(a), vertex_nodes(b)).collect()
+                    }
+                    dot_structures::EdgeTy::Chain(vs) => vs.iter().flat_map(vertex_nodes).collect(),
+                },
+                dot_structures::Stmt::Subgraph(sub) => {
+                    vertex_nodes(&dot_structures::Vertex::S(sub.clone()))
+                }
+                dot_structures::Stmt::Attribute(_) | dot_structures::Stmt::GAttribute(_) => {
+                    vec![]
+                }
+            })
+            .unique()
+            .collect(),
+    }
+}
+
+fn vertex_to_string(v: &dot_structures::Vertex) -> String {
+    match v {
+        dot_structures::Vertex::N(n) => id_to_string(&n.0),
+        dot_structures::Vertex::S(sub) => format!("subgraph {}", id_to_string(&sub.id)),
+    }
+}
+
+/// The textual content of an identifier, with quotes, escapes and HTML
+/// markup removed.
+fn id_to_string(id: &Id) -> String {
+    match id {
+        Id::Plain(s) | Id::Anonymous(s) => s.to_string(),
+        Id::Escaped(s) => s
+            .strip_prefix('"')
+            .and_then(|s| s.strip_suffix('"'))
+            .unwrap_or(s)
+            .replace("\\\"", "\"")
+            .replace("\\\\", "\\"),
+        Id::Html(s) => s
+            .strip_prefix('<')
+            .and_then(|s| s.strip_suffix('>'))
+            .unwrap_or(s)
+            .split('<')
+            .enumerate()
+            .map(|(idx, part)| match part.split_once('>') {
+                // NOTE: Everything up to the first `>` in a part is a tag
+                Some((_tag, text)) if idx > 0 => text,
+                _ => part,
+            })
+            .collect::<String>()
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&amp;", "&"),
+    }
+}
 
-    debug!(
-        start = format!("{start_node:?}"),
-        end = format!("{end_node:?}")
-    );
+#[cfg(test)]
+mod tests {
+    use crate::{
+        env::{Environment, ValidationResult},
+        pg::Determinism,
+    };
 
-    (nodes, node_mapping, graph)
+    use super::{GraphEnv, GraphEnvInput, GraphEnvOutput, GraphFormat};
+
+    fn validate_format(src: &str, format: GraphFormat, graph: &str) -> ValidationResult {
+        let cmds = crate::parse::parse_commands(src).unwrap();
+        GraphEnv
+            .validate(
+                &cmds,
+                &GraphEnvInput {
+                    determinism: Determinism::Deterministic,
+                    format,
+                },
+                &GraphEnvOutput {
+                    format,
+                    graph: graph.to_string(),
+                },
+            )
+            .unwrap()
+    }
+
+    fn validate(src: &str, dot: &str) -> ValidationResult {
+        validate_format(src, GraphFormat::Dot, dot)
+    }
+
+    #[test]
+    fn equivalent_labels_and_layout() {
+        let src = "x := 1; if x > 0 -> y := x + 1 fi";
+        let dot = r#"digraph {
+            rankdir=LR;
+            node [shape=circle];
+            start [label="q▷"];
+            end [label=<<b>q◀</b>>];
+            subgraph cluster_body {
+                start -> a [label="x:=1"];
+                a -> b [label=<<i>x &gt; 0</i> &amp; <i>!false</i>>];
+            }
+            b -> end [label="y := (x + 1)"];
+        }"#;
+        assert_eq!(validate(src, dot), ValidationResult::CorrectTerminated);
+    }
+
+    #[test]
+    fn mismatch_names_edges() {
+        let src = "x := 1; y := 2";
+        let dot = r#"digraph {
+            qStart -> q1 [label="x := 1"];
+            q1 -> qFinal [label="y := 3"];
+        }"#;
+        let ValidationResult::Mismatch { reason } = validate(src, dot) else {
+            panic!("expected a mismatch")
+        };
+        assert!(reason.contains("y := 2"), "{reason}");
+        assert!(reason.contains("y := 3"), "{reason}");
+        assert!(!reason.contains("x := 1"), "{reason}");
+    }
+
+    #[test]
+    fn json_graphs_are_validated() {
+        let src = "x := 1; y := 2";
+        let json = r#"{
+            "initial_node": "a",
+            "final_node": "b",
+            "edges": [
+                { "from": "a", "action": "x := 1", "to": "c" },
+                { "from": "c", "action": "y := 2", "to": "b" }
+            ]
+        }"#;
+        assert_eq!(
+            validate_format(src, GraphFormat::Json, json),
+            ValidationResult::CorrectTerminated
+        );
+        assert!(matches!(
+            validate_format(src, GraphFormat::Json, &json.replace("y := 2", "y := 3")),
+            ValidationResult::Mismatch { .. }
+        ));
+    }
 }
@@ -1,16 +1,20 @@
 use itertools::{chain, Itertools};
-use rand::seq::SliceRandom;
+use rand::{rngs::SmallRng, seq::SliceRandom, Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     ast::Commands,
     generation::Generate,
-    interpreter::{Configuration, Interpreter, InterpreterMemory, TerminationState},
+    interpreter::{
+        Configuration, Interpreter, InterpreterDiagnostic, InterpreterMemory, TerminationState,
+    },
     pg::{Determinism, Node, ProgramGraph},
     sign::{Memory, MemoryRef},
 };
 
-use super::{Analysis, EnvError, Environment, Markdown, ToMarkdown, ValidationResult};
+use super::{
+    check_program, Analysis, EnvError, Environment, Markdown, ToMarkdown, ValidationResult,
+};
 
 #[derive(Debug)]
 pub struct InterpreterEnv;
@@ -20,6 +24,32 @@ pub struct InterpreterInput {
     pub determinism: Determinism,
     pub assignment: InterpreterMemory,
     pub trace_length: u64,
+    #[serde(default)]
+    pub mode: ExecutionMode,
+}
+
+/// How the interpreter chooses between several enabled edges, which can only
+/// happen in [`Determinism::NonDeterministic`] program graphs
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "Case")]
+pub enum ExecutionMode {
+    /// Always take the first enabled edge
+    #[default]
+    First,
+    /// Take an enabled edge at random, with choices determined by the seed
+    Random { seed: u64 },
+    /// Follow every enabled edge, and report how all the executions end
+    Exhaustive,
+}
+
+impl std::fmt::Display for ExecutionMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExecutionMode::First => write!(f, "First enabled edge"),
+            ExecutionMode::Random { seed } => write!(f, "Random (seed {seed})"),
+            ExecutionMode::Exhaustive => write!(f, "Exhaustive"),
+        }
+    }
 }
 
 impl Generate for InterpreterInput {
@@ -41,6 +71,9 @@ impl Generate for InterpreterInput {
                 .unwrap(),
             assignment,
             trace_length: rng.gen_range(10..=15),
+            // NOTE: The other modes ask more of the output, so they are only
+            // used when chosen explicitly in the input
+            mode: ExecutionMode::First,
         }
     }
 }
@@ -73,6 +106,7 @@ impl ToMarkdown for InterpreterInput {
         ]);
 
         table.add_row(["Trace length:".to_string(), self.trace_length.to_string()]);
+        table.add_row(["Mode:".to_string(), self.mode.to_string()]);
 
         format!("{table}").into()
     }
@@ -83,6 +117,52 @@ pub struct InterpreterOutput {
     execution_sequence: Vec<Configuration<String>>,
     #[serde(rename = "final")]
     final_state: TerminationState,
+    /// How all the executions end, only given in [`ExecutionMode::Exhaustive`]
+    #[serde(default)]
+    exploration: Option<Exploration>,
+    /// Why the execution got stuck and at which command, if it did
+    #[serde(default)]
+    diagnostic: Option<InterpreterDiagnostic>,
+}
+
+/// The outcome of every execution of at most the trace length
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Exploration {
+    terminated: Vec<Configuration<String>>,
+    stuck: Vec<Configuration<String>>,
+    every_path_terminates: bool,
+}
+
+impl Exploration {
+    /// Why `given` does not describe the same outcomes as `self`, if it does
+    /// not. The order of the configurations does not matter.
+    fn mismatch(&self, given: &Exploration) -> Option<String> {
+        for (outcome, reference, given) in [
+            ("terminate", &self.terminated, &given.terminated),
+            ("get stuck", &self.stuck, &given.stuck),
+        ] {
+            if let Some(c) = reference.iter().find(|c| !given.contains(c)) {
+                return Some(format!(
+                    "An execution can {outcome} in node '{}' with memory {:?}, but this was not reported",
+                    c.node, c.memory
+                ));
+            }
+            if let Some(c) = given.iter().find(|c| !reference.contains(c)) {
+                return Some(format!(
+                    "No execution can {outcome} in node '{}' with memory {:?}",
+                    c.node, c.memory
+                ));
+            }
+        }
+        if self.every_path_terminates != given.every_path_terminates {
+            return Some(if self.every_path_terminates {
+                "Every execution terminates within the trace length".to_string()
+            } else {
+                "Some executions do not terminate within the trace length".to_string()
+            });
+        }
+        None
+    }
 }
 
 impl ToMarkdown for InterpreterOutput {
@@ -131,16 +211,52 @@ impl ToMarkdown for InterpreterOutput {
                 .map(|(v, _)| v),
             ));
         }
-        let final_message = match self.final_state {
+        let final_message = match &self.final_state {
             TerminationState::Running => {
                 format!("**Stopped after {} steps**", self.execution_sequence.len())
             }
-            TerminationState::Stuck => "**Stuck**".to_string(),
+            TerminationState::Stuck {
+                reason: Some(reason),
+            } => format!("**Stuck: {reason}**"),
+            TerminationState::Stuck { reason: None } => "**Stuck**".to_string(),
             TerminationState::Terminated => "**Terminated successfully**".to_string(),
         };
         table.add_row([final_message]);
 
-        format!("{table}").into()
+        let Some(exploration) = &self.exploration else {
+            return format!("{table}").into();
+        };
+
+        let mut outcomes = comfy_table::Table::new();
+        outcomes
+            .load_preset(comfy_table::presets::ASCII_MARKDOWN)
+            .set_header(["Outcome", "Node", "Memory"]);
+        for (outcome, configurations) in [
+            ("Terminated", &exploration.terminated),
+            ("Stuck", &exploration.stuck),
+        ] {
+            outcomes.add_rows(configurations.iter().map(|c| {
+                [
+                    outcome.to_string(),
+                    c.node.clone(),
+                    c.memory
+                        .iter()
+                        .map(|e| match e {
+                            MemoryRef::Variable(v, x) => format!("`{v} = {x}`"),
+                            MemoryRef::Array(v, x) => format!("`{v} = {x:?}`"),
+                        })
+                        .format(", ")
+                        .to_string(),
+                ]
+            }));
+        }
+        let every_path = if exploration.every_path_terminates {
+            "**Every execution terminates**"
+        } else {
+            "**Some executions do not terminate within the trace length**"
+        };
+
+        format!("{table}\n\n{outcomes}\n\n{every_path}").into()
     }
 }
 
@@ -152,17 +268,49 @@ impl Environment for InterpreterEnv {
     const ANALYSIS: Analysis = Analysis::Interpreter;
 
     fn run(&self, cmds: &Commands, input: &Self::Input) -> Result<Self::Output, EnvError> {
+        check_program(cmds)?;
+
         let pg = ProgramGraph::new(input.determinism, cmds);
-        let (execution_sequence, final_state) =
-            Interpreter::evaluate(input.trace_length, input.assignment.clone(), &pg);
-        let execution_sequence = execution_sequence
+        let execution = match input.mode {
+            ExecutionMode::First | ExecutionMode::Exhaustive => {
+                Interpreter::evaluate(input.trace_length, input.assignment.clone(), &pg)
+            }
+            ExecutionMode::Random { seed } => Interpreter::evaluate_random(
+                input.trace_length,
+                input.assignment.clone(),
+                &pg,
+                &mut SmallRng::seed_from_u64(seed),
+            ),
+        };
+        let execution_sequence = execution
+            .trace
             .into_iter()
             .map(|t| t.map_node(|n| n.to_string()))
             .collect();
 
+        let exploration = match input.mode {
+            ExecutionMode::Exhaustive => {
+                let outcomes =
+                    Interpreter::explore(input.trace_length, input.assignment.clone(), &pg);
+                let configurations = |cs: &[Configuration]| {
+                    cs.iter()
+                        .map(|c| c.clone().map_node(|n| n.to_string()))
+                        .collect()
+                };
+                Some(Exploration {
+                    terminated: configurations(&outcomes.terminated),
+                    stuck: configurations(&outcomes.stuck),
+                    every_path_terminates: outcomes.every_path_terminates(),
+                })
+            }
+            ExecutionMode::First | ExecutionMode::Random { .. } => None,
+        };
+
         Ok(InterpreterOutput {
             execution_sequence,
-            final_state,
+            final_state: execution.termination,
+            exploration,
+            diagnostic: execution.diagnostic,
         })
     }
 
@@ -187,6 +335,20 @@ impl Environment for InterpreterEnv {
             }
         }
 
+        // NOTE: Exhaustive mode is never generated, so an exploration is only
+        // required when it was asked for
+        if let ExecutionMode::Exhaustive = input.mode {
+            let reference = self.run(cmds, input)?;
+            let mismatch = match (&reference.exploration, &output.exploration) {
+                (Some(reference), Some(given)) => reference.mismatch(given),
+                (_, None) => Some("No exploration of every execution was produced".to_string()),
+                (None, Some(_)) => None,
+            };
+            if let Some(reason) = mismatch {
+                return Ok(ValidationResult::Mismatch { reason });
+            }
+        }
+
         let pg = ProgramGraph::new(input.determinism, cmds);
         let mut mem = vec![(Node::Start, input.assignment.clone())];
 
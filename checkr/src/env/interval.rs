@@ -0,0 +1,253 @@
+use std::collections::HashSet;
+
+use indexmap::IndexMap;
+use itertools::{chain, Itertools};
+
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::{
+    analysis::{mono_analysis, FiFo, NodeOrder},
+    ast::{Commands, Target},
+    generation::Generate,
+    interval::{Bound, Interval, IntervalAnalysis, IntervalMemory},
+    pg::{Determinism, Node, ProgramGraph},
+    sign::Memory,
+};
+
+use super::{
+    check_program, Analysis, EnvError, Environment, Markdown, ToMarkdown, ValidationResult,
+};
+
+#[derive(Debug)]
+pub struct IntervalEnv;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IntervalAnalysisInput {
+    pub determinism: Determinism,
+    pub assignment: IntervalMemory,
+}
+
+impl Generate for IntervalAnalysisInput {
+    type Context = Commands;
+
+    fn gen<R: rand::Rng>(cx: &mut Self::Context, rng: &mut R) -> Self {
+        IntervalAnalysisInput {
+            determinism: [Determinism::Deterministic, Determinism::NonDeterministic]
+                .choose(rng)
+                .copied()
+                .unwrap(),
+            assignment: Memory::gen(cx, rng),
+        }
+    }
+}
+
+impl ToMarkdown for IntervalAnalysisInput {
+    fn to_markdown(&self) -> Markdown {
+        let mut table = comfy_table::Table::new();
+        table
+            .load_preset(comfy_table::presets::ASCII_MARKDOWN)
+            .set_header(["Input"]);
+
+        table.add_row([
+            "Determinism:",
+            match self.determinism {
+                Determinism::Deterministic => "**✓**",
+                Determinism::NonDeterministic => "**✕**",
+            },
+        ]);
+
+        table.add_row([
+            "Memory:".to_string(),
+            self.assignment
+                .iter()
+                .map(|e| format!("`{e}`"))
+                .format(", ")
+                .to_string(),
+        ]);
+
+        format!("{table}").into()
+    }
+}
+
+impl Generate for Interval {
+    type Context = Commands;
+
+    fn gen<R: rand::Rng>(_cx: &mut Self::Context, rng: &mut R) -> Self {
+        let lower = rng.gen_range(-10..=10);
+        let upper = lower + rng.gen_range(0..=10);
+        Interval::new(
+            if rng.gen_bool(0.1) {
+                Bound::NegInf
+            } else {
+                Bound::Finite(lower)
+            },
+            if rng.gen_bool(0.1) {
+                Bound::PosInf
+            } else {
+                Bound::Finite(upper)
+            },
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IntervalAnalysisOutput {
+    pub initial_node: String,
+    pub final_node: String,
+    /// The intervals at each node, or `None` if the node is unreachable
+    pub nodes: IndexMap<String, Option<IntervalMemory>>,
+}
+
+impl ToMarkdown for IntervalAnalysisOutput {
+    fn to_markdown(&self) -> Markdown {
+        let variables: HashSet<_> = self
+            .nodes
+            .values()
+            .flatten()
+            .flat_map(|m| m.variables.keys().cloned())
+            .collect();
+        let arrays: HashSet<_> = self
+            .nodes
+            .values()
+            .flatten()
+            .flat_map(|m| m.arrays.keys().cloned())
+            .collect();
+        let variables = variables.into_iter().sorted().collect_vec();
+        let arrays = arrays.into_iter().sorted().collect_vec();
+
+        let mut table = comfy_table::Table::new();
+        table
+            .load_preset(comfy_table::presets::ASCII_MARKDOWN)
+            .set_header(chain!(
+                ["Node".to_string()],
+                variables.iter().map(|v| v.to_string()),
+                arrays.iter().map(|v| v.to_string())
+            ));
+
+        for (n, mem) in self
+            .nodes
+            .iter()
+            .sorted_by_key(|(n, _)| NodeOrder::parse(n))
+        {
+            if let Some(m) = mem {
+                table.add_row(chain!(
+                    [n.to_string()],
+                    variables.iter().map(|var| m
+                        .variables
+                        .get(var)
+                        .copied()
+                        .unwrap_or_default()
+                        .to_string()),
+                    arrays.iter().map(|arr| m
+                        .arrays
+                        .get(arr)
+                        .copied()
+                        .unwrap_or_default()
+                        .to_string()),
+                ));
+            } else {
+                table.add_row([n.to_string()]);
+            }
+        }
+        format!("{table}").into()
+    }
+}
+
+impl Environment for IntervalEnv {
+    type Input = IntervalAnalysisInput;
+
+    type Output = IntervalAnalysisOutput;
+
+    const ANALYSIS: Analysis = Analysis::Interval;
+
+    fn run(&self, cmds: &Commands, input: &Self::Input) -> Result<Self::Output, EnvError> {
+        check_program(cmds)?;
+
+        let pg = ProgramGraph::new(input.determinism, cmds);
+
+        for t in pg.fv() {
+            let missing = match &t {
+                Target::Variable(var) => input.assignment.get_var(var).is_none(),
+                Target::Array(arr, _) => input.assignment.get_arr(arr).is_none(),
+            };
+            if missing {
+                return Err(EnvError::InvalidInputForProgram {
+                    input: super::Input::from_concrete::<Self>(input),
+                    message: format!("`{}` was not in the given input", t.name()),
+                });
+            }
+        }
+
+        Ok(IntervalAnalysisOutput {
+            initial_node: Node::Start.to_string(),
+            final_node: Node::End.to_string(),
+            nodes: mono_analysis::<_, FiFo>(
+                IntervalAnalysis {
+                    assignment: input.assignment.clone(),
+                },
+                &pg,
+            )
+            .facts
+            .into_iter()
+            .sorted_by_key(|(k, _)| *k)
+            .map(|(k, v)| (format!("{k}"), v))
+            .collect(),
+        })
+    }
+
+    fn validate(
+        &self,
+        cmds: &Commands,
+        input: &Self::Input,
+        output: &Self::Output,
+    ) -> Result<ValidationResult, EnvError>
+    where
+        Self::Output: PartialEq + std::fmt::Debug,
+    {
+        let reference = self.run(cmds, input)?;
+
+        for (n, expected) in &reference.nodes {
+            let Some(actual) = output.nodes.get(n) else {
+                return Ok(ValidationResult::Mismatch {
+                    reason: format!("No intervals were given for node {n}"),
+                });
+            };
+            if actual != expected {
+                error!(
+                    node = n,
+                    expected = format!("{expected:?}"),
+                    actual = format!("{actual:?}"),
+                    "intervals differ"
+                );
+                return Ok(ValidationResult::Mismatch {
+                    reason: format!(
+                        "The intervals at node {n} were {}, but the reference has {}",
+                        fmt_memory(actual),
+                        fmt_memory(expected)
+                    ),
+                });
+            }
+        }
+
+        if let Some(n) = output
+            .nodes
+            .keys()
+            .find(|n| !reference.nodes.contains_key(*n))
+        {
+            return Ok(ValidationResult::Mismatch {
+                reason: format!("Node {n} does not exist in the program graph"),
+            });
+        }
+
+        Ok(ValidationResult::CorrectTerminated)
+    }
+}
+
+fn fmt_memory(mem: &Option<IntervalMemory>) -> String {
+    match mem {
+        Some(mem) => format!("{{{}}}", mem.iter().format(", ")),
+        None => "unreachable".to_string(),
+    }
+}
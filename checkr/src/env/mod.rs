@@ -1,23 +1,35 @@
 use std::{ops::Deref, str::FromStr};
 
-use itertools::Either;
+use itertools::{Either, Itertools};
 use rand::rngs::SmallRng;
 use serde::{Deserialize, Serialize};
 
-use crate::{ast::Commands, generation::Generate, sign::Memory, ProgramGenerationBuilder};
+use crate::{
+    ast::Commands, check::CheckError, generation::Generate, sign::Memory, ProgramGenerationBuilder,
+};
+pub use bit_vector::{
+    AvailableExpressionsEnv, LiveVariablesEnv, ReachingDefinitionsEnv, VeryBusyExpressionsEnv,
+};
 pub use graph::GraphEnv;
 pub use interpreter::InterpreterEnv;
+pub use interval::IntervalEnv;
+pub use outline::ProofOutlineEnv;
 pub use parse::ParseEnv;
 pub use pv::ProgramVerificationEnv;
 pub use security::SecurityEnv;
 pub use sign::SignEnv;
+pub use worklist::WorklistEnv;
 
+pub mod bit_vector;
 pub mod graph;
 pub mod interpreter;
+pub mod interval;
+pub mod outline;
 pub mod parse;
 pub mod pv;
 pub mod security;
 pub mod sign;
+pub mod worklist;
 
 macro_rules! define_analysis {
     ( $( $name:ident($env:path, $display:literal, $cmd:literal) ),* $(,)? ) => {
@@ -107,7 +119,14 @@ pub enum Analysis {
     Interpreter,
     ProgramVerification,
     Sign,
+    Interval,
+    LiveVariables,
+    ReachingDefinitions,
+    AvailableExpressions,
+    VeryBusyExpressions,
+    Worklist,
     Security,
+    ProofOutline,
 }
 
 define_analysis!(
@@ -120,7 +139,26 @@ define_analysis!(
         "program-verification"
     ),
     Sign(SignEnv, "Sign", "sign"),
+    Interval(IntervalEnv, "Interval", "interval"),
+    LiveVariables(LiveVariablesEnv, "Live variables", "live-variables"),
+    ReachingDefinitions(
+        ReachingDefinitionsEnv,
+        "Reaching definitions",
+        "reaching-definitions"
+    ),
+    AvailableExpressions(
+        AvailableExpressionsEnv,
+        "Available expressions",
+        "available-expressions"
+    ),
+    VeryBusyExpressions(
+        VeryBusyExpressionsEnv,
+        "Very busy expressions",
+        "very-busy-expressions"
+    ),
+    Worklist(WorklistEnv, "Worklist", "worklist"),
     Security(SecurityEnv, "Security", "security"),
+    ProofOutline(ProofOutlineEnv, "Proof outline", "proof-outline"),
 );
 
 #[typeshare::typeshare]
@@ -172,8 +210,17 @@ pub trait Environment {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ValidationResult {
     CorrectTerminated,
-    CorrectNonTerminated { iterations: u64 },
-    Mismatch { reason: String },
+    CorrectNonTerminated {
+        iterations: u64,
+    },
+    /// The output was accepted, but parts of it were only compared on samples
+    /// rather than shown to be correct
+    ProbablyCorrect {
+        reason: String,
+    },
+    Mismatch {
+        reason: String,
+    },
     TimeOut,
 }
 
@@ -281,7 +328,7 @@ impl<E: Environment + ?Sized> AnyEnvironment for E {
     fn gen_input(&self, cmds: &Commands, rng: &mut SmallRng) -> Input {
         Input {
             analysis: self.analysis(),
-            json: serde_json::to_value(&E::Input::gen(&mut cmds.clone(), rng))
+            json: serde_json::to_value(E::Input::gen(&mut cmds.clone(), rng))
                 .expect("failed to serialize input"),
         }
     }
@@ -368,6 +415,19 @@ pub enum EnvError {
     },
     #[error("input is not valid for the current program: {message}")]
     InvalidInputForProgram { input: Input, message: String },
+    #[error("the program is not well-formed: {}", .errors.iter().format("; "))]
+    InvalidProgram { errors: Vec<CheckError> },
+}
+
+/// Rejects programs which do not pass [`check`](crate::check::check). Every
+/// [`Environment::run`] calls this before analyzing the program.
+pub(crate) fn check_program(cmds: &Commands) -> Result<(), EnvError> {
+    let errors = crate::check::check(cmds);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(EnvError::InvalidProgram { errors })
+    }
 }
 
 impl Analysis {
@@ -0,0 +1,353 @@
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ast::{BExpr, BExprKind, Command, CommandKind, Commands, Guard},
+    egg::EquivChecker,
+    generation::Generate,
+    parse::SourceSpan,
+    pv::VcDiagnostic,
+};
+
+use super::{
+    check_program,
+    pv::{describe_memory, evaluate_on_samples, sample_memories, MIN_AGREEING_SAMPLES},
+    Analysis, EnvError, Environment, Markdown, ToMarkdown, ValidationResult,
+};
+
+/// Checks proof outlines, that is annotated programs with assertions between
+/// their commands. Every local Hoare triple of the outline gives rise to a
+/// single verification condition, and each of these is checked on its own,
+/// such that a mistake is reported against the step where it was made.
+#[derive(Debug)]
+pub struct ProofOutlineEnv;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofOutlineEnvInput {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofOutlineEnvOutput {
+    pub steps: Vec<ProofStep>,
+    /// The steps which do not hold, reported against their command
+    #[serde(default)]
+    pub diagnostics: Vec<VcDiagnostic>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofStep {
+    /// The command of the outline which the step concerns
+    pub command: String,
+    /// The verification condition of the step
+    pub condition: String,
+    pub verdict: Verdict,
+    /// A memory in which the condition does not hold
+    #[serde(default)]
+    pub counterexample: Option<String>,
+}
+
+/// Whether the verification condition of a step holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "Case")]
+pub enum Verdict {
+    Holds,
+    Fails,
+    /// The condition could neither be proven nor refuted
+    Unknown,
+}
+
+impl Generate for ProofOutlineEnvInput {
+    type Context = Commands;
+
+    fn gen<R: rand::Rng>(_cx: &mut Self::Context, _rng: &mut R) -> Self {
+        Self {}
+    }
+}
+
+impl ToMarkdown for ProofOutlineEnvInput {
+    fn to_markdown(&self) -> Markdown {
+        Markdown(String::new())
+    }
+}
+impl ToMarkdown for ProofOutlineEnvOutput {
+    fn to_markdown(&self) -> Markdown {
+        let mut table = comfy_table::Table::new();
+        table
+            .load_preset(comfy_table::presets::ASCII_MARKDOWN)
+            .set_header(["Step", "Command", "Verification condition", "Holds"]);
+
+        table.add_rows(self.steps.iter().enumerate().map(|(i, step)| {
+            [
+                (i + 1).to_string(),
+                format!("`{}`", step.command).replace('|', "\\|"),
+                format!("`{}`", step.condition).replace('|', "\\|"),
+                match (step.verdict, &step.counterexample) {
+                    (Verdict::Holds, _) => "Yes".to_string(),
+                    (Verdict::Fails, Some(m)) => format!("No, not when {m}"),
+                    (Verdict::Fails, None) => "No".to_string(),
+                    (Verdict::Unknown, _) => "Unknown".to_string(),
+                },
+            ]
+        }));
+
+        format!("{table}").into()
+    }
+}
+
+impl Environment for ProofOutlineEnv {
+    type Input = ProofOutlineEnvInput;
+
+    type Output = ProofOutlineEnvOutput;
+
+    const ANALYSIS: Analysis = Analysis::ProofOutline;
+
+    fn setup_generation(&self) -> crate::ProgramGenerationBuilder {
+        crate::ProgramGenerationBuilder::new(Self::ANALYSIS)
+            .no_division(true)
+            .arrays(true)
+            .generate_annotated(true)
+    }
+
+    /// A step holds if egg can rewrite its condition to `true`, or if it
+    /// holds on at least [`MIN_AGREEING_SAMPLES`] sampled memories without
+    /// doing so vacuously, and on none of them it does not. A step fails if a
+    /// sampled memory is a counterexample, and is unknown otherwise.
+    fn run(&self, cmds: &Commands, _input: &Self::Input) -> Result<Self::Output, EnvError> {
+        check_program(cmds)?;
+
+        let cmds = &cmds.infer_invariants();
+        let vcs = cmds.located_vc(&BExprKind::Bool(true).into());
+
+        let mut diagnostics = vec![];
+        let steps = vcs
+            .iter()
+            .map(|vc| {
+                let condition = vc.condition.renumber_quantifiers();
+                // NOTE: Each step is given its own e-graph, such that the
+                // steps do not share the node limit
+                let mut checker = EquivChecker::default();
+                let truth = checker.register(&BExpr::from(BExprKind::Bool(true)));
+                let expr = checker.register(&condition);
+                checker.run();
+
+                let (verdict, counterexample) = if checker.are_equivalent(&expr, &truth) {
+                    (Verdict::Holds, None)
+                } else {
+                    let memories = sample_memories([&condition]);
+                    let samples = evaluate_on_samples(&condition, &memories);
+                    let witnesses = samples
+                        .iter()
+                        .filter(|s| s.is_some_and(|s| s.value && !s.vacuous))
+                        .count();
+                    match samples.iter().position(|s| s.is_some_and(|s| !s.value)) {
+                        Some(i) => (Verdict::Fails, Some(describe_memory(&memories[i]))),
+                        None if witnesses >= MIN_AGREEING_SAMPLES => (Verdict::Holds, None),
+                        None => (Verdict::Unknown, None),
+                    }
+                };
+                let condition = condition.to_string();
+                if verdict == Verdict::Fails {
+                    diagnostics.push(VcDiagnostic {
+                        condition: condition.clone(),
+                        span: vc.span,
+                        counterexample: counterexample
+                            .as_ref()
+                            .map(|m| format!("It does not hold when {m}")),
+                    });
+                }
+                ProofStep {
+                    command: vc
+                        .span
+                        .and_then(|span| command_at(cmds, span))
+                        .map(describe_command)
+                        .unwrap_or_default(),
+                    condition,
+                    verdict,
+                    counterexample,
+                }
+            })
+            .collect();
+
+        Ok(ProofOutlineEnvOutput { steps, diagnostics })
+    }
+
+    fn validate(
+        &self,
+        cmds: &Commands,
+        input: &Self::Input,
+        output: &Self::Output,
+    ) -> Result<ValidationResult, EnvError> {
+        let reference = self.run(cmds, input)?;
+
+        if reference.steps.len() != output.steps.len() {
+            return Ok(ValidationResult::Mismatch {
+                reason: format!(
+                    "produced '{}' steps, expected '{}'",
+                    output.steps.len(),
+                    reference.steps.len()
+                ),
+            });
+        }
+
+        // NOTE: Steps which could not be decided for the reference are not
+        // checked, and the result is then only probably correct
+        let unknown = reference
+            .steps
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.verdict == Verdict::Unknown)
+            .map(|(i, _)| i + 1)
+            .collect_vec();
+        let wrong = reference
+            .steps
+            .iter()
+            .zip(&output.steps)
+            .enumerate()
+            .find(|(_, (r, o))| r.verdict != Verdict::Unknown && r.verdict != o.verdict);
+        Ok(match wrong {
+            None if unknown.is_empty() => ValidationResult::CorrectTerminated,
+            None => ValidationResult::ProbablyCorrect {
+                reason: format!(
+                    "the verification conditions of these steps could neither be proven \
+                     nor refuted, and were not checked: {}",
+                    unknown.iter().format(", ")
+                ),
+            },
+            Some((i, (r, _))) => {
+                let mut reason = format!(
+                    "step {} at `{}` should {}. Its verification condition is `{}`",
+                    i + 1,
+                    r.command,
+                    if r.verdict == Verdict::Holds {
+                        "hold"
+                    } else {
+                        "fail"
+                    },
+                    r.condition,
+                );
+                if let Some(m) = &r.counterexample {
+                    reason += &format!(", which does not hold when {m}");
+                }
+                ValidationResult::Mismatch { reason }
+            }
+        })
+    }
+}
+
+/// The command at `span`, searching within every block of the program
+fn command_at(cmds: &Commands, span: SourceSpan) -> Option<&Command> {
+    cmds.0.iter().find_map(|c| command_in(c, span))
+}
+
+fn command_in(c: &Command, span: SourceSpan) -> Option<&Command> {
+    if c.span == Some(span) {
+        return Some(c);
+    }
+    match &c.kind {
+        CommandKind::If(gs) | CommandKind::Loop(gs) | CommandKind::EnrichedLoop(_, _, gs) => {
+            gs.iter().find_map(|Guard(_, body)| command_at(body, span))
+        }
+        CommandKind::Annotated(_, inner, _) => command_at(inner, span),
+        CommandKind::Assertion(_, inner) => command_in(inner, span),
+        _ => None,
+    }
+}
+
+/// A single line describing the command. Annotated blocks are described by
+/// their pre- and postcondition, and all other commands by their first line.
+fn describe_command(c: &Command) -> String {
+    match &c.kind {
+        CommandKind::Annotated(p, _, q) => format!("{{{p}}} ... {{{q}}}"),
+        _ => c.to_string().lines().next().unwrap_or_default().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        env::{Environment, ValidationResult},
+        parse::parse_commands,
+    };
+
+    use super::{ProofOutlineEnv, ProofOutlineEnvInput, ProofOutlineEnvOutput, Verdict};
+
+    #[test]
+    fn failing_steps_are_reported() -> miette::Result<()> {
+        let src = "{ x >= 0 }
+            y := x + 1;
+            { y > 1 }
+            z := y
+            { z > 0 }";
+        let cmds = parse_commands(src)?;
+        assert_eq!(parse_commands(&cmds.to_string())?, cmds);
+
+        let input = ProofOutlineEnvInput {};
+        let output = ProofOutlineEnv.run(&cmds, &input).unwrap();
+        let verdicts = output
+            .steps
+            .iter()
+            .map(|step| (step.command.as_str(), step.verdict))
+            .collect_vec();
+        assert_eq!(
+            verdicts,
+            [
+                ("{(x >= 0)} ... {(z > 0)}", Verdict::Holds),
+                ("{(y > 1)}", Verdict::Fails)
+            ]
+        );
+
+        let span = output.diagnostics[0].span.unwrap();
+        assert!(src[span.offset()..span.end()].starts_with("{ y > 1 }"));
+        assert_eq!(output.diagnostics[0].condition, output.steps[1].condition);
+
+        let mut given = ProofOutlineEnvOutput {
+            steps: output.steps.clone(),
+            diagnostics: vec![],
+        };
+        given.steps[1].verdict = Verdict::Holds;
+        match ProofOutlineEnv.validate(&cmds, &input, &given).unwrap() {
+            ValidationResult::Mismatch { reason } => {
+                assert!(
+                    reason.starts_with("step 2 at `{(y > 1)}` should fail"),
+                    "{reason}"
+                )
+            }
+            result => panic!("expected a mismatch, but got {result:?}"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn steps_failing_outside_small_values_are_reported() -> miette::Result<()> {
+        let cmds = parse_commands("{ x > 10 } y := x { y > 20 }")?;
+        let output = ProofOutlineEnv
+            .run(&cmds, &ProofOutlineEnvInput {})
+            .unwrap();
+        assert_eq!(output.steps.len(), 1);
+        assert_eq!(output.steps[0].verdict, Verdict::Fails);
+        assert!(output.steps[0].counterexample.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn undecided_steps_are_unknown() -> miette::Result<()> {
+        let cmds = parse_commands("{ x = 1000 & y = 2000 & z = 3000 } skip { x + y = z }")?;
+        let input = ProofOutlineEnvInput {};
+        let output = ProofOutlineEnv.run(&cmds, &input).unwrap();
+        assert_eq!(output.steps.len(), 1);
+        assert_eq!(output.steps[0].verdict, Verdict::Unknown);
+
+        let mut given = output.clone();
+        given.steps[0].verdict = Verdict::Fails;
+        assert!(matches!(
+            ProofOutlineEnv.validate(&cmds, &input, &given).unwrap(),
+            ValidationResult::ProbablyCorrect { .. }
+        ));
+
+        Ok(())
+    }
+}
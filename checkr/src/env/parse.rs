@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::{ast::Commands, generation::Generate};
 
-use super::{Analysis, EnvError, Environment, ToMarkdown, ValidationResult};
+use super::{check_program, Analysis, EnvError, Environment, ToMarkdown, ValidationResult};
 
 #[derive(Debug)]
 pub struct ParseEnv;
@@ -21,6 +21,8 @@ impl Environment for ParseEnv {
     const ANALYSIS: Analysis = Analysis::Parse;
 
     fn run(&self, cmds: &Commands, _input: &Self::Input) -> Result<Self::Output, EnvError> {
+        check_program(cmds)?;
+
         Ok(ParseOutput(cmds.to_string()))
     }
 
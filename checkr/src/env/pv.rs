@@ -1,19 +1,58 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::RangeInclusive,
+};
+
 use itertools::Itertools;
+use rand::{rngs::SmallRng, seq::SliceRandom, Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    ast::{BExpr, Commands, Predicate},
+    ast::{BExpr, BExprKind, Commands, Int, LogicOp, Predicate},
     egg::EquivChecker,
     generation::Generate,
+    interpreter::InterpreterMemory,
+    pv::VcKind,
+    sign::Memory,
 };
 
-use super::{Analysis, EnvError, Environment, Markdown, ToMarkdown, ValidationResult};
+use super::{
+    check_program, Analysis, EnvError, Environment, Markdown, ToMarkdown, ValidationResult,
+};
 
 #[derive(Debug)]
 pub struct ProgramVerificationEnv;
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub struct ProgramVerificationEnvInput {}
+pub struct ProgramVerificationEnvInput {
+    #[serde(default)]
+    pub method: VerificationMethod,
+    /// The invariants inferred for the loops of the program which do not have
+    /// one, in the order the loops appear. These are only informative, as the
+    /// inference is deterministic.
+    #[serde(default)]
+    pub inferred_invariants: Vec<String>,
+}
+
+/// How the verification conditions are derived from the annotations
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "Case")]
+pub enum VerificationMethod {
+    /// Forwards from the preconditions with strongest postconditions
+    #[default]
+    Forward,
+    /// Backwards from the postconditions with weakest preconditions
+    Backward,
+}
+
+impl std::fmt::Display for VerificationMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerificationMethod::Forward => write!(f, "Forward (strongest postcondition)"),
+            VerificationMethod::Backward => write!(f, "Backward (weakest precondition)"),
+        }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ProgramVerificationEnvOutput {
@@ -23,12 +62,15 @@ pub struct ProgramVerificationEnvOutput {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SerializedPredicate {
     predicate: String,
+    #[serde(default)]
+    kind: VcKind,
 }
 
 impl From<Predicate> for SerializedPredicate {
     fn from(value: Predicate) -> Self {
         SerializedPredicate {
             predicate: value.to_string(),
+            kind: VcKind::default(),
         }
     }
 }
@@ -36,6 +78,7 @@ impl From<&'_ Predicate> for SerializedPredicate {
     fn from(value: &'_ Predicate) -> Self {
         SerializedPredicate {
             predicate: value.to_string(),
+            kind: VcKind::default(),
         }
     }
 }
@@ -43,6 +86,10 @@ impl SerializedPredicate {
     pub fn parse(&self) -> Result<Predicate, crate::parse::ParseError> {
         crate::parse::parse_predicate(&self.predicate)
     }
+    /// Whether the condition is needed for partial correctness or termination
+    pub fn kind(&self) -> VcKind {
+        self.kind
+    }
 }
 
 #[allow(dead_code)]
@@ -64,6 +111,14 @@ impl ToMarkdown for ProgramVerificationEnvInput {
             .load_preset(comfy_table::presets::ASCII_MARKDOWN)
             .set_header(["Input"]);
 
+        table.add_row(["Method:".to_string(), self.method.to_string()]);
+        for (i, invariant) in self.inferred_invariants.iter().enumerate() {
+            table.add_row([
+                format!("Inferred invariant of loop {}:", i + 1),
+                format!("`{invariant}`").replace('|', "\\|"),
+            ]);
+        }
+
         format!("{table}").into()
     }
 }
@@ -72,14 +127,18 @@ impl ToMarkdown for ProgramVerificationEnvOutput {
         let mut table = comfy_table::Table::new();
         table
             .load_preset(comfy_table::presets::ASCII_MARKDOWN)
-            .set_header(["Verification conditions"]);
+            .set_header(["Verification conditions", "Needed for"]);
 
         // r#"<code class="predicate">`{}`</code>"#,
-        table.add_rows(
-            self.verification_conditions
-                .iter()
-                .map(|vc| [format!("`{}`", vc.parse().unwrap()).replace('|', "\\|")]),
-        );
+        table.add_rows(self.verification_conditions.iter().map(|vc| {
+            [
+                format!("`{}`", vc.parse().unwrap()).replace('|', "\\|"),
+                match vc.kind {
+                    VcKind::PartialCorrectness => "Partial correctness".to_string(),
+                    VcKind::Termination => "Termination".to_string(),
+                },
+            ]
+        }));
 
         format!("{table}").into()
     }
@@ -88,8 +147,18 @@ impl ToMarkdown for ProgramVerificationEnvOutput {
 impl Generate for ProgramVerificationEnvInput {
     type Context = Commands;
 
-    fn gen<R: rand::Rng>(_cx: &mut Self::Context, _rng: &mut R) -> Self {
-        Self {}
+    fn gen<R: rand::Rng>(cx: &mut Self::Context, rng: &mut R) -> Self {
+        Self {
+            method: [VerificationMethod::Forward, VerificationMethod::Backward]
+                .choose(rng)
+                .copied()
+                .unwrap(),
+            inferred_invariants: cx
+                .inferred_invariants()
+                .iter()
+                .map(|invariant| invariant.to_string())
+                .collect(),
+        }
     }
 }
 
@@ -101,24 +170,37 @@ impl Environment for ProgramVerificationEnv {
     const ANALYSIS: Analysis = Analysis::ProgramVerification;
 
     fn setup_generation(&self) -> crate::ProgramGenerationBuilder {
-        crate::ast::Command::reset_sp_counter();
-
         crate::ProgramGenerationBuilder::new(Self::ANALYSIS)
-            .no_loop(true)
             .no_division(true)
+            .arrays(true)
             .generate_annotated(true)
     }
 
-    fn run(&self, cmds: &Commands, _: &Self::Input) -> Result<Self::Output, EnvError> {
-        let verification_conditions = cmds.vc(&BExpr::Bool(true));
+    fn run(&self, cmds: &Commands, input: &Self::Input) -> Result<Self::Output, EnvError> {
+        check_program(cmds)?;
+
+        // NOTE: Loops without an invariant can only occur outside of annotated
+        // programs, and are given an inferred one rather than `true`
+        let cmds = &cmds.infer_invariants();
+        let verification_conditions = match input.method {
+            VerificationMethod::Forward => cmds.located_vc(&BExprKind::Bool(true).into()),
+            VerificationMethod::Backward => cmds.located_wp_vc(&BExprKind::Bool(true).into()),
+        };
         Ok(ProgramVerificationEnvOutput {
             verification_conditions: verification_conditions
                 .iter()
-                .map(|vc| vc.renumber_quantifiers().into())
+                .map(|vc| SerializedPredicate {
+                    predicate: vc.condition.renumber_quantifiers().to_string(),
+                    kind: vc.kind,
+                })
                 .collect(),
         })
     }
 
+    /// The verification conditions are first matched up to the rewrites known
+    /// to egg. Those left over are compared on sampled memories, and pairs
+    /// with the same value on all of them are accepted as probably
+    /// equivalent, which is reported as [`ValidationResult::ProbablyCorrect`].
     fn validate(
         &self,
         cmds: &Commands,
@@ -164,56 +246,260 @@ impl Environment for ProgramVerificationEnv {
             });
         }
 
-        let mut checker = EquivChecker::default();
+        let mut checker = EquivChecker::default().with_explanations();
 
-        let mut ref_exprs = ref_vc.iter().map(|vc| checker.register(vc)).collect_vec();
-        let mut rel_exprs = rel_vc.iter().map(|vc| checker.register(vc)).collect_vec();
+        let ref_exprs = ref_vc.iter().map(|vc| checker.register(vc)).collect_vec();
+        let rel_exprs = rel_vc.iter().map(|vc| checker.register(vc)).collect_vec();
 
         checker.run();
 
-        ref_exprs.retain(|ref_e| {
-            if let Some(rel_idx) = rel_exprs
+        let mut ref_left = (0..ref_vc.len()).collect_vec();
+        let mut rel_left = (0..rel_vc.len()).collect_vec();
+        ref_left.retain(|&i| {
+            if let Some(pos) = rel_left
                 .iter()
-                .position(|rel_e| checker.are_equivalent(ref_e, rel_e))
+                .position(|&j| checker.are_equivalent(&ref_exprs[i], &rel_exprs[j]))
             {
-                rel_exprs.remove(rel_idx);
+                rel_left.remove(pos);
+                false
+            } else {
+                true
+            }
+        });
+
+        // NOTE: The rewrites known to egg do not cover every equivalence, so
+        // the remaining conditions are compared by their values on sampled
+        // memories instead
+        let memories = sample_memories(
+            ref_left
+                .iter()
+                .map(|&i| &ref_vc[i])
+                .chain(rel_left.iter().map(|&j| &rel_vc[j])),
+        );
+        let values = |vcs: &[BExpr], left: &[usize]| -> BTreeMap<usize, Vec<Option<Sample>>> {
+            left.iter()
+                .map(|&i| (i, evaluate_on_samples(&vcs[i], &memories)))
+                .collect()
+        };
+        let ref_values = values(&ref_vc, &ref_left);
+        let rel_values = values(&rel_vc, &rel_left);
+
+        let compare =
+            |i: usize, j: usize| compare_on_samples(&ref_values[&i], &rel_values[&j], &memories);
+        let mut sampled = vec![];
+        ref_left.retain(|&i| {
+            let found = rel_left
+                .iter()
+                .enumerate()
+                .find_map(|(pos, &j)| match compare(i, j) {
+                    Sampled::Agree(n) => Some((pos, n)),
+                    _ => None,
+                });
+            if let Some((pos, n)) = found {
+                sampled.push((i, rel_left.remove(pos), n));
                 false
             } else {
                 true
             }
         });
 
-        if ref_exprs.is_empty() {
-            Ok(ValidationResult::CorrectTerminated)
-        } else {
-            Ok(ValidationResult::Mismatch {
+        if ref_left.is_empty() {
+            if sampled.is_empty() {
+                return Ok(ValidationResult::CorrectTerminated);
+            }
+            return Ok(ValidationResult::ProbablyCorrect {
                 reason: format!(
-                    "{}. Left in the reference were [{}] and left in the given were [{}]",
-                    "some verification conditions were not found",
-                    ref_exprs.iter().format(", "),
-                    rel_exprs.iter().format(", "),
+                    "{}: {}",
+                    "some verification conditions could not be shown equivalent, \
+                     but agree on every sampled memory",
+                    sampled
+                        .iter()
+                        .map(|&(i, j, n)| format!(
+                            "`{}` and `{}` on {n} memories",
+                            ref_vc[i], rel_vc[j]
+                        ))
+                        .format(", "),
                 ),
-            })
+            });
         }
 
-        // let a = crate::parse::parse_bexpr(&reference.pre_condition).unwrap();
-        // let b = crate::parse::parse_bexpr(&output.pre_condition)
-        //     .expect("could not parse pre-condition");
+        let mut reason = format!(
+            "{}. Left in the reference were [{}] and left in the given were [{}]",
+            "some verification conditions were not found",
+            ref_left.iter().map(|&i| &ref_vc[i]).format(", "),
+            rel_left.iter().map(|&j| &rel_vc[j]).format(", "),
+        );
+        let distinguished = ref_left
+            .iter()
+            .cartesian_product(&rel_left)
+            .find_map(|(&i, &j)| match compare(i, j) {
+                Sampled::Differ(m) => Some((i, j, m)),
+                _ => None,
+            });
+        if let Some((i, j, m)) = distinguished {
+            reason += &format!(
+                ". For example, `{}` and `{}` differ when {}",
+                ref_vc[i],
+                rel_vc[j],
+                describe_memory(m)
+            );
+        }
+        let closest = distinguished
+            .map(|(i, j, _)| (i, j))
+            .or(ref_left.first().copied().zip(rel_left.first().copied()));
+        if let Some((i, j)) = closest {
+            reason += &format!(
+                ". The closest egg could rewrite them were `{}` and `{}`, \
+                 by the rewrites\n{}\nand\n{}",
+                checker.simplest(&ref_exprs[i]),
+                checker.simplest(&rel_exprs[j]),
+                checker.rewrite_path(&ref_exprs[i]),
+                checker.rewrite_path(&rel_exprs[j]),
+            );
+        }
+        Ok(ValidationResult::Mismatch { reason })
+    }
+}
+
+/// The number of memories on which verification conditions are compared when
+/// egg could not show them to be equivalent
+const SAMPLES: usize = 100;
+/// The values of variables and array elements in sampled memories
+const SAMPLE_VALUES: RangeInclusive<Int> = -4..=4;
+/// The values quantified variables range over when comparing by sampling
+const QUANTIFIER_DOMAIN: RangeInclusive<Int> = -6..=6;
+/// The number of memories on which two verification conditions must agree,
+/// without both holding vacuously, for them to be accepted as probably
+/// equivalent. Likewise for a single condition to be accepted as holding.
+pub(super) const MIN_AGREEING_SAMPLES: usize = 10;
+/// The number of values tried for quantified variables in a single evaluation
+/// before giving up on it
+const QUANTIFIER_FUEL: u64 = 2_000;
+
+/// Samples memories for the free variables of the conditions. Values are
+/// drawn from [`SAMPLE_VALUES`], from around the constants of the conditions,
+/// or among the values picked earlier for the same memory, such that
+/// comparisons against constants and between variables come out both ways.
+pub(super) fn sample_memories<'a>(
+    vcs: impl IntoIterator<Item = &'a BExpr>,
+) -> Vec<InterpreterMemory> {
+    let mut targets = BTreeSet::new();
+    let mut near = BTreeSet::new();
+    for vc in vcs {
+        targets.extend(vc.fv());
+        for n in vc.constants() {
+            for c in [n, n.saturating_neg()] {
+                near.extend([c.saturating_sub(1), c, c.saturating_add(1)]);
+            }
+        }
+    }
+    let near = near.into_iter().collect_vec();
+    let mut rng = SmallRng::seed_from_u64(0xBADA55);
+    (0..SAMPLES)
+        .map(|_| {
+            Memory::from_targets_with(
+                targets.iter().cloned(),
+                (&mut rng, vec![]),
+                |(rng, picked), _| sample_value(rng, &near, picked),
+                |(rng, picked), _| {
+                    let len = rng.gen_range(1..=5);
+                    (0..len).map(|_| sample_value(rng, &near, picked)).collect()
+                },
+            )
+        })
+        .collect()
+}
+
+fn sample_value(rng: &mut SmallRng, near: &[Int], picked: &mut Vec<Int>) -> Int {
+    let value = match rng.gen_range(0..3) {
+        0 if !near.is_empty() => *near.choose(rng).unwrap(),
+        1 if !picked.is_empty() => *picked.choose(rng).unwrap(),
+        _ => rng.gen_range(SAMPLE_VALUES),
+    };
+    picked.push(value);
+    value
+}
+
+enum Sampled<'a> {
+    /// Both conditions had the same value on every memory where both could be
+    /// evaluated, and there were at least [`MIN_AGREEING_SAMPLES`] such
+    /// memories where they did not both hold vacuously, the number of which
+    /// is given
+    Agree(usize),
+    Differ(&'a InterpreterMemory),
+    /// The conditions could be evaluated together on too few of the memories,
+    /// for example because of division by zero, or held vacuously on most of
+    /// them
+    Inconclusive,
+}
 
-        // let mut rng = SmallRng::seed_from_u64(0xBADA55);
+/// The value of a condition on a sampled memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Sample {
+    pub value: bool,
+    /// Whether the condition is an implication that holds because its
+    /// antecedent does not
+    pub vacuous: bool,
+}
 
-        // for _ in 0..100 {
-        //     let sample = InterpreterInput::gen(&mut cmds.clone(), &mut rng);
+/// The value of `p` on each of the memories, or `None` where it could not be
+/// evaluated
+pub(super) fn evaluate_on_samples(
+    p: &BExpr,
+    memories: &[InterpreterMemory],
+) -> Vec<Option<Sample>> {
+    let mut antecedents = vec![];
+    let mut consequent = p;
+    while let BExprKind::Logic(l, LogicOp::Implies, r) = &consequent.kind {
+        antecedents.push(l.as_ref());
+        consequent = r;
+    }
 
-        //     if a.semantics(&sample.assignment) != b.semantics(&sample.assignment) {
-        //         return ValidationResult::Mismatch {
-        //             reason: format!(
-        //                 "Did not produce the same logical value for {:?}",
-        //                 sample.assignment
-        //             ),
-        //         };
-        //     }
-        // }
+    memories
+        .iter()
+        .map(|m| {
+            let evaluate = |p: &BExpr| {
+                p.bounded_semantics(m, &QUANTIFIER_DOMAIN, &mut QUANTIFIER_FUEL.clone())
+                    .ok()
+            };
+            Some(Sample {
+                value: evaluate(p)?,
+                vacuous: antecedents.iter().any(|a| evaluate(a) == Some(false)),
+            })
+        })
+        .collect()
+}
+
+/// The memory as a list of assignments, such as `x = 1, A = [2, 3]`
+pub(super) fn describe_memory(m: &InterpreterMemory) -> String {
+    m.variables
+        .iter()
+        .map(|(x, v)| format!("{x} = {v}"))
+        .chain(
+            m.arrays
+                .iter()
+                .map(|(a, vs)| format!("{a} = [{}]", vs.iter().format(", "))),
+        )
+        .join(", ")
+}
+
+fn compare_on_samples<'a>(
+    a: &[Option<Sample>],
+    b: &[Option<Sample>],
+    memories: &'a [InterpreterMemory],
+) -> Sampled<'a> {
+    let mut agreeing = 0;
+    for ((x, y), m) in a.iter().zip(b).zip(memories) {
+        match (x, y) {
+            (Some(x), Some(y)) if x.value != y.value => return Sampled::Differ(m),
+            (Some(x), Some(y)) if !(x.vacuous && y.vacuous) => agreeing += 1,
+            _ => {}
+        }
+    }
+    if agreeing >= MIN_AGREEING_SAMPLES {
+        Sampled::Agree(agreeing)
+    } else {
+        Sampled::Inconclusive
     }
 }
 
@@ -221,6 +507,138 @@ impl Environment for ProgramVerificationEnv {
 mod tests {
     use pretty_assertions::assert_eq;
 
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    use crate::{
+        env::{Environment, ToMarkdown, ValidationResult},
+        generation::Generate,
+        parse::{parse_commands, parse_predicate},
+        pv::VcKind,
+    };
+
+    use super::{
+        compare_on_samples, sample_memories, ProgramVerificationEnv, ProgramVerificationEnvInput,
+        ProgramVerificationEnvOutput, Sample, Sampled, SerializedPredicate, VerificationMethod,
+    };
+
+    #[test]
+    fn equivalent_conditions_are_found_by_sampling() -> miette::Result<()> {
+        let cmds = parse_commands("{ x >= 0 } y := x + 1 { y > 0 }")?;
+        let input = ProgramVerificationEnvInput {
+            method: VerificationMethod::Backward,
+            inferred_invariants: vec![],
+        };
+        let validate = |vc: &str| {
+            let output = ProgramVerificationEnvOutput {
+                verification_conditions: vec![SerializedPredicate {
+                    predicate: vc.to_string(),
+                    kind: VcKind::PartialCorrectness,
+                }],
+            };
+            ProgramVerificationEnv
+                .validate(&cmds, &input, &output)
+                .unwrap()
+        };
+
+        assert_eq!(
+            validate("!(x < 0) ==> 0 < x + 1 + 0"),
+            ValidationResult::CorrectTerminated
+        );
+        match validate("x >= 0 ==> x > -1") {
+            ValidationResult::ProbablyCorrect { reason } => {
+                assert!(
+                    reason.contains("`((x >= 0) ==> (x > -1))` on 52 memories"),
+                    "{reason}"
+                )
+            }
+            result => panic!("expected a probable match, but got {result:?}"),
+        }
+        match validate("x >= 0 ==> x + 1 > 1") {
+            ValidationResult::Mismatch { reason } => {
+                assert!(reason.contains("differ when x = 0. "), "{reason}");
+                assert!(reason.contains("(Rewrite=> desugar-ge"), "{reason}");
+            }
+            result => panic!("expected a mismatch, but got {result:?}"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn conditions_differing_outside_small_values_are_distinguished() -> miette::Result<()> {
+        let cmds = parse_commands("{ x > 10 } y := x { y > 10 }")?;
+        let input = ProgramVerificationEnvInput {
+            method: VerificationMethod::Forward,
+            inferred_invariants: vec![],
+        };
+        let output = ProgramVerificationEnvOutput {
+            verification_conditions: vec![SerializedPredicate {
+                predicate: "(x > 10 & y = x) ==> y > 20".to_string(),
+                kind: VcKind::PartialCorrectness,
+            }],
+        };
+
+        match ProgramVerificationEnv
+            .validate(&cmds, &input, &output)
+            .unwrap()
+        {
+            ValidationResult::Mismatch { reason } => {
+                assert!(reason.contains("differ when x = "), "{reason}")
+            }
+            result => panic!("expected a mismatch, but got {result:?}"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn inferred_invariants_are_shown_in_the_input() -> miette::Result<()> {
+        let mut cmds = parse_commands("i := 0; do i < 10 -> i := i + 1 od")?;
+        let input = ProgramVerificationEnvInput::gen(&mut cmds, &mut SmallRng::seed_from_u64(0));
+
+        let invariant = parse_predicate("0 <= i & i <= 10")?.to_string();
+        assert!(input.to_markdown().contains(&invariant));
+        assert_eq!(input.inferred_invariants, [invariant]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn a_single_sample_is_inconclusive() {
+        let memories = sample_memories([]);
+        let holds = Sample {
+            value: true,
+            vacuous: false,
+        };
+        let mut a = vec![None; memories.len()];
+        a[0] = Some(holds);
+        let b = vec![Some(holds); memories.len()];
+        assert!(matches!(
+            compare_on_samples(&a, &b, &memories),
+            Sampled::Inconclusive
+        ));
+        assert!(matches!(
+            compare_on_samples(&b, &b, &memories),
+            Sampled::Agree(n) if n == memories.len()
+        ));
+
+        let vacuous = vec![
+            Some(Sample {
+                value: true,
+                vacuous: true,
+            });
+            memories.len()
+        ];
+        assert!(matches!(
+            compare_on_samples(&vacuous, &vacuous, &memories),
+            Sampled::Inconclusive
+        ));
+        assert!(matches!(
+            compare_on_samples(&vacuous, &b, &memories),
+            Sampled::Agree(n) if n == memories.len()
+        ));
+    }
+
     #[test]
     fn normalization_simple() -> miette::Result<()> {
         let a = "exists _f0 :: exists _f1 :: _f0 = _f1";
@@ -10,7 +10,9 @@ use crate::{
     sign::Memory,
 };
 
-use super::{Analysis, EnvError, Environment, Markdown, ToMarkdown, ValidationResult};
+use super::{
+    check_program, Analysis, EnvError, Environment, Markdown, ToMarkdown, ValidationResult,
+};
 
 #[derive(Debug)]
 pub struct SecurityEnv;
@@ -146,6 +148,8 @@ impl Environment for SecurityEnv {
     const ANALYSIS: Analysis = Analysis::Security;
 
     fn run(&self, cmds: &Commands, input: &Self::Input) -> Result<Self::Output, EnvError> {
+        check_program(cmds)?;
+
         let lattice = SecurityLattice::new(&input.lattice.0);
         Ok(SecurityAnalysisOutput::run(
             &input.classification,
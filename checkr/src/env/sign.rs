@@ -8,14 +8,16 @@ use serde::{Deserialize, Serialize};
 use tracing::error;
 
 use crate::{
-    analysis::{mono_analysis, FiFo, NodeOrder},
+    analysis::{NodeOrder, WorklistStrategy},
     ast::{Commands, Target},
     generation::Generate,
     pg::{Determinism, Node, ProgramGraph},
     sign::{Memory, Sign, SignAnalysis, SignMemory, Signs},
 };
 
-use super::{Analysis, EnvError, Environment, Markdown, ToMarkdown, ValidationResult};
+use super::{
+    check_program, Analysis, EnvError, Environment, Markdown, ToMarkdown, ValidationResult,
+};
 
 #[derive(Debug)]
 pub struct SignEnv;
@@ -155,14 +157,17 @@ impl ToMarkdown for SignAnalysisOutput {
     }
 }
 
-impl Environment for SignEnv {
-    type Input = SignAnalysisInput;
-
-    type Output = SignAnalysisOutput;
-
-    const ANALYSIS: Analysis = Analysis::Sign;
+impl SignEnv {
+    /// Runs the analysis using the given worklist, additionally returning the
+    /// number of times the semantic function was applied
+    pub fn analyze(
+        &self,
+        cmds: &Commands,
+        input: &SignAnalysisInput,
+        worklist: WorklistStrategy,
+    ) -> Result<(SignAnalysisOutput, usize), EnvError> {
+        check_program(cmds)?;
 
-    fn run(&self, cmds: &Commands, input: &Self::Input) -> Result<Self::Output, EnvError> {
         let pg = ProgramGraph::new(input.determinism, cmds);
 
         for t in pg.fv() {
@@ -186,20 +191,36 @@ impl Environment for SignEnv {
             }
         }
 
-        Ok(SignAnalysisOutput {
+        let results = worklist.analyze(
+            SignAnalysis {
+                assignment: input.assignment.clone(),
+            },
+            &pg,
+        );
+
+        let output = SignAnalysisOutput {
             initial_node: Node::Start.to_string(),
             final_node: Node::End.to_string(),
-            nodes: mono_analysis::<_, FiFo>(
-                SignAnalysis {
-                    assignment: input.assignment.clone(),
-                },
-                &pg,
-            )
-            .facts
-            .into_iter()
-            .map(|(k, v)| (format!("{k}"), v))
-            .collect(),
-        })
+            nodes: results
+                .facts
+                .into_iter()
+                .map(|(k, v)| (format!("{k}"), v))
+                .collect(),
+        };
+
+        Ok((output, results.semantic_calls))
+    }
+}
+
+impl Environment for SignEnv {
+    type Input = SignAnalysisInput;
+
+    type Output = SignAnalysisOutput;
+
+    const ANALYSIS: Analysis = Analysis::Sign;
+
+    fn run(&self, cmds: &Commands, input: &Self::Input) -> Result<Self::Output, EnvError> {
+        Ok(self.analyze(cmds, input, WorklistStrategy::FiFo)?.0)
     }
 
     fn validate(
@@ -0,0 +1,125 @@
+//! Sign analysis with a choice of worklist, for comparing how many steps each
+//! worklist algorithm needs to reach the fixpoint.
+
+use itertools::Itertools;
+
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::{analysis::WorklistStrategy, ast::Commands, generation::Generate};
+
+use super::{
+    sign::{SignAnalysisInput, SignAnalysisOutput},
+    Analysis, EnvError, Environment, Markdown, SignEnv, ToMarkdown, ValidationResult,
+};
+
+#[derive(Debug)]
+pub struct WorklistEnv;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorklistAnalysisInput {
+    #[serde(flatten)]
+    pub sign: SignAnalysisInput,
+    pub worklist: WorklistStrategy,
+}
+
+impl Generate for WorklistAnalysisInput {
+    type Context = Commands;
+
+    fn gen<R: rand::Rng>(cx: &mut Self::Context, rng: &mut R) -> Self {
+        WorklistAnalysisInput {
+            sign: SignAnalysisInput::gen(cx, rng),
+            worklist: *WorklistStrategy::ALL.choose(rng).unwrap(),
+        }
+    }
+}
+
+impl ToMarkdown for WorklistAnalysisInput {
+    fn to_markdown(&self) -> Markdown {
+        format!(
+            "{}\n\n**Worklist:** {}",
+            self.sign.to_markdown().trim_end(),
+            self.worklist
+        )
+        .into()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorklistAnalysisOutput {
+    #[serde(flatten)]
+    pub sign: SignAnalysisOutput,
+    /// The number of times the semantic function was applied to an edge
+    pub semantic_calls: usize,
+}
+
+impl ToMarkdown for WorklistAnalysisOutput {
+    fn to_markdown(&self) -> Markdown {
+        format!(
+            "{}\n\n**Semantic calls:** {}",
+            self.sign.to_markdown().trim_end(),
+            self.semantic_calls
+        )
+        .into()
+    }
+}
+
+impl Environment for WorklistEnv {
+    type Input = WorklistAnalysisInput;
+
+    type Output = WorklistAnalysisOutput;
+
+    const ANALYSIS: Analysis = Analysis::Worklist;
+
+    fn run(&self, cmds: &Commands, input: &Self::Input) -> Result<Self::Output, EnvError> {
+        let (sign, semantic_calls) = SignEnv.analyze(cmds, &input.sign, input.worklist)?;
+        Ok(WorklistAnalysisOutput {
+            sign,
+            semantic_calls,
+        })
+    }
+
+    /// Only the facts are validated, as the number of semantic calls depends
+    /// on details of the implementation, such as the order of outgoing edges
+    fn validate(
+        &self,
+        cmds: &Commands,
+        input: &Self::Input,
+        output: &Self::Output,
+    ) -> Result<ValidationResult, EnvError> {
+        let reference = self.run(cmds, input)?;
+
+        for (n, expected) in &reference.sign.nodes {
+            match output.sign.nodes.get(n) {
+                Some(actual) if actual == expected => {}
+                Some(actual) => {
+                    return Ok(ValidationResult::Mismatch {
+                        reason: format!(
+                            "At node {n} the worlds were {{{}}}, but the reference has {{{}}}",
+                            actual.iter().map(|w| w.iter().format(", ")).format("; "),
+                            expected.iter().map(|w| w.iter().format(", ")).format("; "),
+                        ),
+                    })
+                }
+                None => {
+                    return Ok(ValidationResult::Mismatch {
+                        reason: format!("No worlds were given for node {n}"),
+                    })
+                }
+            }
+        }
+
+        if let Some(n) = output
+            .sign
+            .nodes
+            .keys()
+            .find(|n| !reference.sign.nodes.contains_key(*n))
+        {
+            return Ok(ValidationResult::Mismatch {
+                reason: format!("Node {n} does not exist in the program graph"),
+            });
+        }
+
+        Ok(ValidationResult::CorrectTerminated)
+    }
+}
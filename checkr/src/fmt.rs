@@ -3,8 +3,8 @@ use std::fmt::Display;
 use itertools::Itertools;
 
 use crate::ast::{
-    AExpr, AOp, Array, BExpr, Command, Commands, Function, Guard, LogicOp, Quantifier, RelOp,
-    Target, Variable,
+    AExpr, AExprKind, AOp, Array, BExpr, BExprKind, Command, CommandKind, Commands, Function,
+    Guard, LogicOp, Quantifier, RelOp, Target, Variable,
 };
 
 impl Display for Variable {
@@ -37,17 +37,22 @@ impl std::fmt::Display for Target<()> {
 
 impl Display for Command {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Command::Assignment(target, expr) => write!(f, "{target} := {expr}"),
-            Command::If(guards) => write!(f, "if {}\nfi", guards.iter().format("\n[] ")),
-            Command::Loop(guards) => write!(f, "do {}\nod", guards.iter().format("\n[] ")),
-            Command::EnrichedLoop(pred, guards) => {
-                write!(f, "do {{{pred}}}\n   {}\nod", guards.iter().format("\n[] "))
+        match &self.kind {
+            CommandKind::Assignment(target, expr) => write!(f, "{target} := {expr}"),
+            CommandKind::If(guards) => write!(f, "if {}\nfi", guards.iter().format("\n[] ")),
+            CommandKind::Loop(guards) => write!(f, "do {}\nod", guards.iter().format("\n[] ")),
+            CommandKind::EnrichedLoop(pred, variant, guards) => {
+                write!(f, "do {{{pred}}}")?;
+                if let Some(variant) = variant {
+                    write!(f, " {{{variant}}}")?;
+                }
+                write!(f, "\n   {}\nod", guards.iter().format("\n[] "))
             }
-            Command::Annotated(p, c, q) => write!(f, "{{{p}}}\n{c}\n{{{q}}}"),
-            Command::Break => write!(f, "break"),
-            Command::Continue => write!(f, "continue"),
-            Command::Skip => write!(f, "skip"),
+            CommandKind::Annotated(p, c, q) => write!(f, "{{{p}}}\n{c}\n{{{q}}}"),
+            CommandKind::Assertion(p, c) => write!(f, "{{{p}}}\n{c}"),
+            CommandKind::Break => write!(f, "break"),
+            CommandKind::Continue => write!(f, "continue"),
+            CommandKind::Skip => write!(f, "skip"),
         }
     }
 }
@@ -75,12 +80,12 @@ impl Display for Guard {
 
 impl Display for AExpr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            AExpr::Number(n) => write!(f, "{n}"),
-            AExpr::Reference(x) => write!(f, "{x}"),
-            AExpr::Binary(l, op, r) => write!(f, "({l} {op} {r})"),
-            AExpr::Minus(m) => write!(f, "-{m}"),
-            AExpr::Function(fun) => write!(f, "{fun}"),
+        match &self.kind {
+            AExprKind::Number(n) => write!(f, "{n}"),
+            AExprKind::Reference(x) => write!(f, "{x}"),
+            AExprKind::Binary(l, op, r) => write!(f, "({l} {op} {r})"),
+            AExprKind::Minus(m) => write!(f, "-{m}"),
+            AExprKind::Function(fun) => write!(f, "{fun}"),
         }
     }
 }
@@ -112,12 +117,12 @@ impl Display for Function {
 }
 impl Display for BExpr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            BExpr::Bool(b) => write!(f, "{b}"),
-            BExpr::Rel(l, op, r) => write!(f, "({l} {op} {r})"),
-            BExpr::Logic(l, op, r) => write!(f, "({l} {op} {r})"),
-            BExpr::Not(b) => write!(f, "!{b}"),
-            BExpr::Quantified(q, x, b) => write!(f, "({q} {x} :: {b})"),
+        match &self.kind {
+            BExprKind::Bool(b) => write!(f, "{b}"),
+            BExprKind::Rel(l, op, r) => write!(f, "({l} {op} {r})"),
+            BExprKind::Logic(l, op, r) => write!(f, "({l} {op} {r})"),
+            BExprKind::Not(b) => write!(f, "!{b}"),
+            BExprKind::Quantified(q, x, b) => write!(f, "({q} {x} :: {b})"),
         }
     }
 }
@@ -1,7 +1,8 @@
 use rand::{seq::SliceRandom, Rng};
 
 use crate::ast::{
-    AExpr, AOp, Array, BExpr, Command, Commands, Guard, LogicOp, RelOp, Target, Variable,
+    AExpr, AExprKind, AOp, Array, BExpr, BExprKind, Command, CommandKind, Commands, Guard, LogicOp,
+    RelOp, Target, Variable,
 };
 
 pub struct Context {
@@ -11,6 +12,8 @@ pub struct Context {
     no_loops: bool,
     no_division: bool,
     no_unary_minus: bool,
+    arrays: bool,
+    loop_depth: u32,
     names: Vec<String>,
 }
 
@@ -25,6 +28,8 @@ impl Context {
             no_loops: false,
             no_division: false,
             no_unary_minus: false,
+            arrays: false,
+            loop_depth: 0,
             names: ["a", "b", "c", "d"].map(Into::into).to_vec(),
         }
     }
@@ -41,9 +46,13 @@ impl Context {
         self.no_unary_minus = no_unary_minus;
         self
     }
+    pub fn set_arrays(&mut self, arrays: bool) -> &mut Self {
+        self.arrays = arrays;
+        self
+    }
 
     fn use_array(&self) -> bool {
-        false
+        self.arrays
     }
 
     fn reference<R: Rng>(&mut self, rng: &mut R) -> Target<Box<AExpr>> {
@@ -119,6 +128,9 @@ impl Generate for Commands {
     }
 }
 
+/// Wraps `cmds` in a pre- and postcondition derived from a sign analysis.
+/// Annotated programs cannot contain loops without invariants, so these are
+/// [inferred](Commands::infer_invariants).
 pub fn annotate_cmds<R: Rng>(mut cmds: Commands, rng: &mut R) -> Command {
     use crate::{
         env::{
@@ -137,7 +149,7 @@ pub fn annotate_cmds<R: Rng>(mut cmds: Commands, rng: &mut R) -> Command {
     let pre = signs_in(&sign_result.nodes[&sign_result.initial_node]);
     let post = signs_in(&sign_result.nodes[&sign_result.final_node]);
 
-    return Command::Annotated(pre, cmds, post);
+    return CommandKind::Annotated(pre, cmds.infer_invariants(), post).into();
 
     fn signs_in(assignment: &HashSet<Memory<Sign, Signs>>) -> BExpr {
         assignment
@@ -147,22 +159,29 @@ pub fn annotate_cmds<R: Rng>(mut cmds: Commands, rng: &mut R) -> Command {
                     .variables
                     .iter()
                     .map(|(v, s)| {
-                        let v = AExpr::Reference(v.clone().into());
+                        let v = AExprKind::Reference(v.clone().into()).into();
                         let op = match s {
                             Sign::Positive => RelOp::Gt,
                             Sign::Zero => RelOp::Eq,
                             Sign::Negative => RelOp::Lt,
                         };
-                        BExpr::Rel(v, op, AExpr::Number(0))
+                        BExprKind::Rel(v, op, AExprKind::Number(0).into()).into()
                     })
                     .reduce(|a, b| BExpr::logic(a, LogicOp::And, b))
             })
             .reduce(|a, b| BExpr::logic(a, LogicOp::Or, b))
-            .unwrap_or(BExpr::Bool(true))
+            .unwrap_or(BExprKind::Bool(true).into())
     }
 }
 
 impl Generate for Command {
+    type Context = Context;
+    fn gen<R: Rng>(cx: &mut Self::Context, rng: &mut R) -> Self {
+        CommandKind::gen(cx, rng).into()
+    }
+}
+
+impl Generate for CommandKind {
     type Context = Context;
     fn gen<R: Rng>(cx: &mut Self::Context, rng: &mut R) -> Self {
         cx.recursion_limit = 5;
@@ -173,13 +192,29 @@ impl Generate for Command {
                 (
                     1.0,
                     Box::new(|cx, rng| {
-                        Command::Assignment(Target::gen(cx, rng), AExpr::gen(cx, rng))
+                        CommandKind::Assignment(Target::gen(cx, rng), AExpr::gen(cx, rng))
                     }),
                 ),
-                (0.6, Box::new(|cx, rng| Command::If(cx.many(1, 10, rng)))),
+                (
+                    0.6,
+                    Box::new(|cx, rng| CommandKind::If(cx.many(1, 10, rng))),
+                ),
                 (
                     if cx.no_loops { 0.0 } else { 0.3 },
-                    Box::new(|cx, rng| Command::Loop(cx.many(1, 10, rng))),
+                    Box::new(|cx, rng| {
+                        cx.loop_depth += 1;
+                        let guards = cx.many(1, 10, rng);
+                        cx.loop_depth -= 1;
+                        CommandKind::Loop(guards)
+                    }),
+                ),
+                (
+                    if cx.loop_depth == 0 { 0.0 } else { 0.1 },
+                    Box::new(|_, _| CommandKind::Break),
+                ),
+                (
+                    if cx.loop_depth == 0 { 0.0 } else { 0.1 },
+                    Box::new(|_, _| CommandKind::Continue),
                 ),
             ],
         )
@@ -212,9 +247,12 @@ impl Generate for AExpr {
             vec![
                 (
                     0.4,
-                    Box::new(|_, rng| AExpr::Number(rng.gen_range(-100..=100))),
+                    Box::new(|_, rng| AExprKind::Number(rng.gen_range(-100..=100)).into()),
+                ),
+                (
+                    0.8,
+                    Box::new(|cx, rng| AExprKind::Reference(cx.reference(rng)).into()),
                 ),
-                (0.8, Box::new(|cx, rng| AExpr::Reference(cx.reference(rng)))),
                 (
                     if cx.recursion_limit == 0 || cx.fuel == 0 {
                         0.0
@@ -222,7 +260,7 @@ impl Generate for AExpr {
                         0.9
                     },
                     Box::new(|cx, rng| {
-                        cx.recursion_limit = cx.recursion_limit.checked_sub(1).unwrap_or_default();
+                        cx.recursion_limit = cx.recursion_limit.saturating_sub(1);
                         AExpr::binary(AExpr::gen(cx, rng), AOp::gen(cx, rng), AExpr::gen(cx, rng))
                     }),
                 ),
@@ -258,22 +296,23 @@ impl Generate for BExpr {
         cx.sample(
             rng,
             vec![
-                (0.2, Box::new(|_cx, rng| BExpr::Bool(rng.gen()))),
+                (0.2, Box::new(|_cx, rng| BExprKind::Bool(rng.gen()).into())),
                 (
                     if cx.recursion_limit == 0 { 0.0 } else { 0.7 },
                     Box::new(|cx, rng| {
-                        cx.recursion_limit = cx.recursion_limit.checked_sub(1).unwrap_or_default();
-                        BExpr::Rel(
+                        cx.recursion_limit = cx.recursion_limit.saturating_sub(1);
+                        BExprKind::Rel(
                             AExpr::gen(cx, rng),
                             RelOp::gen(cx, rng),
                             AExpr::gen(cx, rng),
                         )
+                        .into()
                     }),
                 ),
                 (
                     if cx.recursion_limit == 0 { 0.0 } else { 0.7 },
                     Box::new(|cx, rng| {
-                        cx.recursion_limit = cx.recursion_limit.checked_sub(1).unwrap_or_default();
+                        cx.recursion_limit = cx.recursion_limit.saturating_sub(1);
                         BExpr::logic(
                             BExpr::gen(cx, rng),
                             LogicOp::gen(cx, rng),
@@ -284,8 +323,8 @@ impl Generate for BExpr {
                 (
                     if cx.negation_limit == 0 { 0.0 } else { 0.4 },
                     Box::new(|cx, rng| {
-                        cx.negation_limit = cx.negation_limit.checked_sub(1).unwrap_or_default();
-                        BExpr::Not(Box::new(BExpr::gen(cx, rng)))
+                        cx.negation_limit = cx.negation_limit.saturating_sub(1);
+                        BExprKind::Not(Box::new(BExpr::gen(cx, rng))).into()
                     }),
                 ),
             ],
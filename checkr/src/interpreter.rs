@@ -1,7 +1,15 @@
+use std::{collections::HashSet, ops::RangeInclusive};
+
+use miette::Diagnostic;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 use crate::{
-    ast::{AExpr, AOp, BExpr, Function, Int, LogicOp, RelOp, Target},
+    ast::{
+        AExpr, AExprKind, AOp, BExpr, BExprKind, Function, Int, LogicOp, Quantifier, RelOp, Target,
+    },
+    parse::SourceSpan,
     pg::{Action, Node, ProgramGraph},
     sign::Memory,
 };
@@ -16,20 +24,35 @@ impl InterpreterMemory {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(tag = "Case")]
 pub enum TerminationState {
     Running,
-    Stuck,
+    /// No edge could be taken from a node other than the end. The reason is
+    /// the first error other than [`InterpreterError::NoProgression`] met by
+    /// an outgoing edge, and `NoProgression` if no guard was enabled.
+    Stuck {
+        #[serde(default)]
+        reason: Option<InterpreterError>,
+    },
     Terminated,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Configuration<N = Node> {
     pub node: N,
     pub memory: InterpreterMemory,
 }
 
+/// A single execution of a program graph, see [`Interpreter::evaluate`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Execution {
+    pub trace: Vec<Configuration>,
+    pub termination: TerminationState,
+    /// Why the execution got stuck and at which command, if it did
+    pub diagnostic: Option<InterpreterDiagnostic>,
+}
+
 impl<A> Configuration<A> {
     pub fn map_node<B>(self, f: impl FnOnce(A) -> B) -> Configuration<B> {
         Configuration {
@@ -40,40 +63,157 @@ impl<A> Configuration<A> {
 }
 
 impl Interpreter {
-    pub fn evaluate(
+    /// Executes at most `steps` configurations, always taking the first
+    /// enabled edge
+    pub fn evaluate(steps: u64, memory: InterpreterMemory, pg: &ProgramGraph) -> Execution {
+        Interpreter::evaluate_by(steps, memory, pg, |mut next| next.swap_remove(0))
+    }
+
+    /// Like [`Interpreter::evaluate`], but whenever several edges are enabled
+    /// one of them is chosen at random
+    pub fn evaluate_random(
+        steps: u64,
+        memory: InterpreterMemory,
+        pg: &ProgramGraph,
+        rng: &mut impl Rng,
+    ) -> Execution {
+        Interpreter::evaluate_by(steps, memory, pg, |mut next| {
+            next.swap_remove(rng.gen_range(0..next.len()))
+        })
+    }
+
+    fn evaluate_by(
         mut steps: u64,
         memory: InterpreterMemory,
         pg: &ProgramGraph,
-    ) -> (Vec<Configuration>, TerminationState) {
+        mut choose: impl FnMut(Vec<Configuration>) -> Configuration,
+    ) -> Execution {
         let mut state = Configuration {
             node: Node::Start,
             memory,
         };
         let mut trace = vec![state.clone()];
 
-        let termination = loop {
+        let (termination, diagnostic) = loop {
             if steps < 2 {
-                break TerminationState::Running;
+                break (TerminationState::Running, None);
             }
             steps -= 1;
 
-            let next = pg.outgoing(state.node).iter().find_map(|e| {
-                e.1.semantics(&state.memory)
-                    .map(|m| Configuration {
-                        node: e.2,
-                        memory: m,
-                    })
-                    .ok()
-            });
-            state = match next {
-                Some(s) => s,
-                None if state.node == Node::End => break TerminationState::Terminated,
-                None => break TerminationState::Stuck,
+            state = match successors(pg, &state) {
+                Ok(next) => choose(next),
+                Err(_) if state.node == Node::End => break (TerminationState::Terminated, None),
+                Err(diagnostic) => {
+                    let termination = TerminationState::Stuck {
+                        reason: Some(diagnostic.error.clone()),
+                    };
+                    break (termination, Some(diagnostic));
+                }
             };
             trace.push(state.clone());
         };
 
-        (trace, termination)
+        Execution {
+            trace,
+            termination,
+            diagnostic,
+        }
+    }
+
+    /// Follows every execution with at most `steps` configurations. The
+    /// executions are explored breadth-first, and executions which reach the
+    /// same configuration after the same number of steps are only followed
+    /// once, such that loops with several enabled guards do not make the
+    /// exploration grow exponentially in `steps`.
+    pub fn explore(mut steps: u64, memory: InterpreterMemory, pg: &ProgramGraph) -> Outcomes {
+        let mut outcomes = Outcomes::default();
+        let mut frontier = vec![Configuration {
+            node: Node::Start,
+            memory,
+        }];
+
+        while !frontier.is_empty() {
+            if steps < 2 {
+                outcomes.running = true;
+                break;
+            }
+            steps -= 1;
+
+            let mut seen = HashSet::new();
+            let mut next = vec![];
+            for state in frontier {
+                match successors(pg, &state) {
+                    Ok(successors) => {
+                        next.extend(successors.into_iter().filter(|c| seen.insert(c.clone())))
+                    }
+                    Err(_) if state.node == Node::End => outcomes.terminated.push(state),
+                    Err(_) => outcomes.stuck.push(state),
+                }
+            }
+            frontier = next;
+        }
+
+        // NOTE: The same configuration can be reached after different numbers
+        // of steps
+        for configurations in [&mut outcomes.terminated, &mut outcomes.stuck] {
+            let mut seen = HashSet::new();
+            configurations.retain(|c| seen.insert(c.clone()));
+        }
+        outcomes
+    }
+}
+
+/// The configurations which can follow `state` by taking a single edge, or
+/// why no edge can be taken, see [`TerminationState::Stuck`]. The reason is
+/// reported against the command of the edge which raised it, or of the first
+/// outgoing edge if no guard was enabled.
+fn successors(
+    pg: &ProgramGraph,
+    state: &Configuration,
+) -> Result<Vec<Configuration>, InterpreterDiagnostic> {
+    let outgoing = pg.outgoing(state.node);
+    let mut next = vec![];
+    let mut reason = InterpreterDiagnostic {
+        error: InterpreterError::NoProgression,
+        span: outgoing.first().and_then(|e| e.span()),
+    };
+    for e in outgoing {
+        match e.1.semantics(&state.memory) {
+            Ok(m) => next.push(Configuration {
+                node: e.2,
+                memory: m,
+            }),
+            Err(error) if reason.error == InterpreterError::NoProgression => {
+                reason = InterpreterDiagnostic {
+                    error,
+                    span: e.span(),
+                }
+            }
+            Err(_) => {}
+        }
+    }
+    if next.is_empty() {
+        Err(reason)
+    } else {
+        Ok(next)
+    }
+}
+
+/// How the executions of a program end, see [`Interpreter::explore`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Outcomes {
+    /// The distinct configurations in which some execution terminated
+    pub terminated: Vec<Configuration>,
+    /// The distinct configurations in which some execution got stuck
+    pub stuck: Vec<Configuration>,
+    /// Whether some execution was still running when the bound was reached
+    pub running: bool,
+}
+
+impl Outcomes {
+    /// Whether every execution terminated within the bound
+    pub fn every_path_terminates(&self) -> bool {
+        self.stuck.is_empty() && !self.running
     }
 }
 
@@ -100,12 +240,13 @@ impl Action {
                         data[idx as usize] = a.semantics(m)?;
                         Ok(m2)
                     }
-                    Some(_) => Err(InterpreterError::ArrayNotFound {
+                    Some(data) => Err(InterpreterError::IndexOutOfBound {
                         name: arr.to_string(),
+                        index: idx,
+                        length: data.len(),
                     }),
-                    None => Err(InterpreterError::IndexOutOfBound {
+                    None => Err(InterpreterError::ArrayNotFound {
                         name: arr.to_string(),
-                        index: idx,
                     }),
                 }
             }
@@ -117,15 +258,18 @@ impl Action {
                     Err(InterpreterError::NoProgression)
                 }
             }
+            Action::Block(edges) => edges
+                .iter()
+                .try_fold(m.clone(), |m, e| e.action().semantics(&m)),
         }
     }
 }
 
 impl AExpr {
     pub fn semantics(&self, m: &InterpreterMemory) -> Result<Int, InterpreterError> {
-        Ok(match self {
-            AExpr::Number(n) => *n,
-            AExpr::Reference(Target::Variable(x)) => {
+        Ok(match &self.kind {
+            AExprKind::Number(n) => *n,
+            AExprKind::Reference(Target::Variable(x)) => {
                 if let Some(x) = m.variables.get(x) {
                     *x
                 } else {
@@ -134,7 +278,7 @@ impl AExpr {
                     });
                 }
             }
-            AExpr::Reference(Target::Array(arr, idx)) => {
+            AExprKind::Reference(Target::Array(arr, idx)) => {
                 let data = if let Some(data) = m.arrays.get(arr) {
                     data
                 } else {
@@ -149,14 +293,15 @@ impl AExpr {
                     return Err(InterpreterError::IndexOutOfBound {
                         name: arr.to_string(),
                         index: idx,
+                        length: data.len(),
                     });
                 }
             }
-            AExpr::Binary(l, op, r) => op.semantic(l.semantics(m)?, r.semantics(m)?)?,
-            AExpr::Minus(n) => (n.semantics(m)?)
+            AExprKind::Binary(l, op, r) => op.semantic(l.semantics(m)?, r.semantics(m)?)?,
+            AExprKind::Minus(n) => (n.semantics(m)?)
                 .checked_neg()
                 .ok_or(InterpreterError::ArithmeticOverflow)?,
-            AExpr::Function(f) => match f {
+            AExprKind::Function(f) => match f {
                 Function::Division(l, r) => {
                     AOp::Divide.semantic(l.semantics(m)?, r.semantics(m)?)?
                 }
@@ -207,7 +352,8 @@ impl AExpr {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+#[derive(Debug, Clone, PartialEq, Eq, Error, Serialize, Deserialize)]
+#[serde(tag = "Case")]
 pub enum InterpreterError {
     #[error("division by zero")]
     DivisionByZero,
@@ -217,9 +363,14 @@ pub enum InterpreterError {
     VariableNotFound { name: String },
     #[error("array '{name}' not found")]
     ArrayNotFound { name: String },
-    #[error("index {index} in '{name}' is out-of-bounds")]
-    IndexOutOfBound { name: String, index: Int },
-    #[error("no progression")]
+    #[error("index {index} in '{name}' is out-of-bounds for its length {length}")]
+    IndexOutOfBound {
+        name: String,
+        index: Int,
+        length: usize,
+    },
+    /// None of the guards of the edges hold
+    #[error("no guard is enabled")]
     NoProgression,
     #[error("an arithmetic operation overflowed")]
     ArithmeticOverflow,
@@ -229,6 +380,17 @@ pub enum InterpreterError {
     OutsideFunctionDomain,
 }
 
+/// An [`InterpreterError`] reported against the command in the source whose
+/// edge was being executed, see [`Edge::span`](crate::pg::Edge::span). Attach
+/// the program with [`miette::Report::with_source_code`] to render it.
+#[derive(Debug, Clone, PartialEq, Eq, Error, Diagnostic, Serialize, Deserialize)]
+#[error("Evaluation failed: {error}")]
+pub struct InterpreterDiagnostic {
+    pub error: InterpreterError,
+    #[label("The error occurred while executing this command")]
+    pub span: Option<SourceSpan>,
+}
+
 impl AOp {
     pub fn semantic(&self, l: Int, r: Int) -> Result<Int, InterpreterError> {
         Ok(match self {
@@ -243,14 +405,17 @@ impl AOp {
                 .ok_or(InterpreterError::ArithmeticOverflow)?,
             AOp::Divide => {
                 if r != 0 {
-                    l / r
+                    l.checked_div(r)
+                        .ok_or(InterpreterError::ArithmeticOverflow)?
                 } else {
                     return Err(InterpreterError::DivisionByZero);
                 }
             }
             AOp::Pow => {
                 if r >= 0 {
-                    l.checked_pow(r as _)
+                    u32::try_from(r)
+                        .ok()
+                        .and_then(|r| l.checked_pow(r))
                         .ok_or(InterpreterError::ArithmeticOverflow)?
                 } else {
                     return Err(InterpreterError::NegativeExponent);
@@ -262,12 +427,50 @@ impl AOp {
 
 impl BExpr {
     pub fn semantics(&self, m: &InterpreterMemory) -> Result<bool, InterpreterError> {
-        Ok(match self {
-            BExpr::Bool(b) => *b,
-            BExpr::Rel(l, op, r) => op.semantic(l.semantics(m)?, r.semantics(m)?),
-            BExpr::Logic(l, op, r) => op.semantic(l.semantics(m)?, || r.semantics(m))?,
-            BExpr::Not(b) => !b.semantics(m)?,
-            BExpr::Quantified(_, _, _) => return Err(InterpreterError::EvaluateQuantifier),
+        Ok(match &self.kind {
+            BExprKind::Bool(b) => *b,
+            BExprKind::Rel(l, op, r) => op.semantic(l.semantics(m)?, r.semantics(m)?),
+            BExprKind::Logic(l, op, r) => op.semantic(l.semantics(m)?, || r.semantics(m))?,
+            BExprKind::Not(b) => !b.semantics(m)?,
+            BExprKind::Quantified(_, _, _) => return Err(InterpreterError::EvaluateQuantifier),
+        })
+    }
+    /// Like [`BExpr::semantics`], but quantified variables range over `domain`
+    /// instead of all integers. Each value tried for a quantified variable
+    /// uses up one unit of `fuel`, and once it runs out, or if an array is
+    /// quantified, the evaluation gives up.
+    pub fn bounded_semantics(
+        &self,
+        m: &InterpreterMemory,
+        domain: &RangeInclusive<Int>,
+        fuel: &mut u64,
+    ) -> Result<bool, InterpreterError> {
+        Ok(match &self.kind {
+            BExprKind::Logic(l, op, r) => op
+                .semantic(l.bounded_semantics(m, domain, fuel)?, || {
+                    r.bounded_semantics(m, domain, fuel)
+                })?,
+            BExprKind::Not(b) => !b.bounded_semantics(m, domain, fuel)?,
+            BExprKind::Quantified(q, Target::Variable(x), b) => {
+                let mut m = m.clone();
+                let mut holds = domain.clone().map(|v| {
+                    *fuel = fuel
+                        .checked_sub(1)
+                        .ok_or(InterpreterError::EvaluateQuantifier)?;
+                    m.variables.insert(x.clone(), v);
+                    b.bounded_semantics(&m, domain, fuel)
+                });
+                match q {
+                    Quantifier::Exists => holds.find(|r| !matches!(r, Ok(false))),
+                    Quantifier::Forall => holds.find(|r| !matches!(r, Ok(true))),
+                }
+                .transpose()?
+                .unwrap_or(matches!(q, Quantifier::Forall))
+            }
+            BExprKind::Quantified(_, Target::Array(_, _), _) => {
+                return Err(InterpreterError::EvaluateQuantifier)
+            }
+            _ => self.semantics(m)?,
         })
     }
 }
@@ -309,3 +512,108 @@ impl LogicOp {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    use crate::{
+        ast::Variable,
+        parse::parse_commands,
+        pg::{Determinism, ProgramGraph},
+    };
+
+    use super::{Interpreter, InterpreterError, InterpreterMemory, TerminationState};
+
+    #[test]
+    fn every_nondeterministic_choice_is_explored() -> miette::Result<()> {
+        let cmds = parse_commands(
+            "if true -> x := 1
+             [] true -> x := 2
+             [] true -> do x >= 0 -> skip od
+             fi;
+             if x > 1 -> skip fi",
+        )?;
+        let pg = ProgramGraph::new(Determinism::NonDeterministic, &cmds);
+        let x = |c: &super::Configuration| c.memory.get_var(&Variable("x".to_string())).copied();
+
+        let outcomes = Interpreter::explore(10, InterpreterMemory::zero(&pg), &pg);
+        assert_eq!(outcomes.terminated.iter().map(x).collect_vec(), [Some(2)]);
+        assert_eq!(outcomes.stuck.iter().map(x).collect_vec(), [Some(1)]);
+        assert!(outcomes.running);
+        assert!(!outcomes.every_path_terminates());
+
+        let first = Interpreter::evaluate(10, InterpreterMemory::zero(&pg), &pg).trace;
+        let random = |seed| {
+            let mut rng = SmallRng::seed_from_u64(seed);
+            Interpreter::evaluate_random(10, InterpreterMemory::zero(&pg), &pg, &mut rng)
+        };
+        assert_eq!(x(first.last().unwrap()), Some(1));
+        assert_eq!(random(7), random(7));
+        assert!((0..20).any(|seed| random(seed).termination == TerminationState::Terminated));
+
+        Ok(())
+    }
+
+    #[test]
+    fn exploration_merges_equal_configurations() -> miette::Result<()> {
+        let cmds = parse_commands("do true -> skip [] true -> skip od")?;
+        let pg = ProgramGraph::new(Determinism::NonDeterministic, &cmds);
+
+        let outcomes = Interpreter::explore(1_000, InterpreterMemory::zero(&pg), &pg);
+        assert!(outcomes.terminated.is_empty() && outcomes.stuck.is_empty());
+        assert!(outcomes.running);
+
+        Ok(())
+    }
+
+    #[test]
+    fn stuck_executions_explain_why() -> miette::Result<()> {
+        fn stuck(src: &str) -> miette::Result<(InterpreterError, &str)> {
+            let cmds = parse_commands(src)?;
+            let pg = ProgramGraph::new(Determinism::Deterministic, &cmds);
+            let memory = crate::sign::Memory::from_targets(pg.fv(), |_| 0, |_| vec![0, 0]);
+            let execution = Interpreter::evaluate(10, memory, &pg);
+            let diagnostic = execution.diagnostic.expect("the execution gets stuck");
+            let span = diagnostic.span.expect("the command was parsed");
+            assert_eq!(
+                execution.termination,
+                TerminationState::Stuck {
+                    reason: Some(diagnostic.error.clone())
+                }
+            );
+            Ok((diagnostic.error, &src[span.offset()..span.end()]))
+        }
+
+        assert_eq!(
+            stuck("skip; if x > 0 -> skip fi")?,
+            (InterpreterError::NoProgression, "if x > 0 -> skip fi")
+        );
+        assert_eq!(
+            stuck("y := 1;\nx := 1 / x")?,
+            (InterpreterError::DivisionByZero, "x := 1 / x")
+        );
+        assert_eq!(
+            stuck("A[2] := 1")?,
+            (
+                InterpreterError::IndexOutOfBound {
+                    name: "A".to_string(),
+                    index: 2,
+                    length: 2,
+                },
+                "A[2] := 1"
+            )
+        );
+        assert_eq!(
+            stuck("y := -9223372036854775807 - 1;\nx := y / -1")?,
+            (InterpreterError::ArithmeticOverflow, "x := y / -1")
+        );
+        assert_eq!(
+            stuck("x := 2 ^ 4294967296")?,
+            (InterpreterError::ArithmeticOverflow, "x := 2 ^ 4294967296")
+        );
+
+        Ok(())
+    }
+}
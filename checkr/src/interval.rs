@@ -0,0 +1,648 @@
+//! Interval analysis of program graphs.
+//!
+//! Each variable is approximated by an [`Interval`] of the values it may take,
+//! and each array by a single interval containing all of its elements. Since
+//! the domain has infinite height, the analysis widens at loop heads to
+//! guarantee termination.
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::{Direction, Lattice, MonotoneFramework, WideningPoints},
+    ast::{AExpr, AExprKind, AOp, BExpr, BExprKind, Function, Int, LogicOp, RelOp, Target},
+    pg::{Action, Edge, ProgramGraph},
+    sign::Memory,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct IntervalAnalysis {
+    pub assignment: IntervalMemory,
+}
+
+pub type IntervalMemory = Memory<Interval, Interval>;
+
+/// A set of consecutive integers. A missing bound means that the interval is
+/// unbounded in that direction.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "Case")]
+pub enum Interval {
+    #[default]
+    Empty,
+    Range {
+        lower: Option<Int>,
+        upper: Option<Int>,
+    },
+}
+
+/// An endpoint of an interval
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Bound {
+    NegInf,
+    Finite(Int),
+    PosInf,
+}
+
+impl std::fmt::Display for Interval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Interval::Empty => write!(f, "∅"),
+            Interval::Range { lower, upper } => {
+                match lower {
+                    Some(l) => write!(f, "[{l}, ")?,
+                    None => write!(f, "[-∞, ")?,
+                }
+                match upper {
+                    Some(u) => write!(f, "{u}]"),
+                    None => write!(f, "∞]"),
+                }
+            }
+        }
+    }
+}
+
+impl Interval {
+    /// Constructs the interval `[lower, upper]`, which is empty if `lower` is
+    /// above `upper`
+    pub fn new(lower: Bound, upper: Bound) -> Interval {
+        if lower > upper || lower == Bound::PosInf || upper == Bound::NegInf {
+            return Interval::Empty;
+        }
+        Interval::Range {
+            lower: lower.finite(),
+            upper: upper.finite(),
+        }
+    }
+    pub fn constant(n: Int) -> Interval {
+        Interval::new(Bound::Finite(n), Bound::Finite(n))
+    }
+    pub fn top() -> Interval {
+        Interval::new(Bound::NegInf, Bound::PosInf)
+    }
+    pub fn is_empty(self) -> bool {
+        self == Interval::Empty
+    }
+    pub fn bounds(self) -> Option<(Bound, Bound)> {
+        match self {
+            Interval::Empty => None,
+            Interval::Range { lower, upper } => Some((
+                lower.map_or(Bound::NegInf, Bound::Finite),
+                upper.map_or(Bound::PosInf, Bound::Finite),
+            )),
+        }
+    }
+    pub fn contains_value(self, n: Int) -> bool {
+        match self.bounds() {
+            Some((l, u)) => l <= Bound::Finite(n) && Bound::Finite(n) <= u,
+            None => false,
+        }
+    }
+    pub fn meet(self, other: Interval) -> Interval {
+        match (self.bounds(), other.bounds()) {
+            (Some((l1, u1)), Some((l2, u2))) => Interval::new(l1.max(l2), u1.min(u2)),
+            _ => Interval::Empty,
+        }
+    }
+    /// The standard interval widening: any bound which grew since `self` is
+    /// moved to infinity.
+    pub fn widen(self, next: Interval) -> Interval {
+        match (self.bounds(), next.bounds()) {
+            (None, _) => next,
+            (_, None) => self,
+            (Some((l1, u1)), Some((l2, u2))) => Interval::new(
+                if l2 < l1 { Bound::NegInf } else { l1 },
+                if u2 > u1 { Bound::PosInf } else { u1 },
+            ),
+        }
+    }
+    /// Widening with thresholds: any bound which grew since `self` is moved to
+    /// the nearest of the `thresholds` beyond it, or to infinity if there is
+    /// none. Since there are finitely many thresholds, this still ensures
+    /// that every ascending chain stabilizes.
+    pub fn widen_with_thresholds(self, next: Interval, thresholds: &BTreeSet<Int>) -> Interval {
+        match (self.bounds(), next.bounds()) {
+            (None, _) => next,
+            (_, None) => self,
+            (Some((l1, u1)), Some((l2, u2))) => Interval::new(
+                if l2 < l1 {
+                    l2.finite()
+                        .and_then(|l| thresholds.range(..=l).next_back())
+                        .map_or(Bound::NegInf, |&t| Bound::Finite(t))
+                } else {
+                    l1
+                },
+                if u2 > u1 {
+                    u2.finite()
+                        .and_then(|u| thresholds.range(u..).next())
+                        .map_or(Bound::PosInf, |&t| Bound::Finite(t))
+                } else {
+                    u1
+                },
+            ),
+        }
+    }
+    /// The standard interval narrowing: only the infinite bounds of `self` are
+    /// replaced by those of `next`.
+    pub fn narrow(self, next: Interval) -> Interval {
+        match (self.bounds(), next.bounds()) {
+            (Some((l1, u1)), Some((l2, u2))) => Interval::new(
+                if l1 == Bound::NegInf { l2 } else { l1 },
+                if u1 == Bound::PosInf { u2 } else { u1 },
+            ),
+            _ => Interval::Empty,
+        }
+    }
+
+    /// Applies `f` to the bounds of two non-empty intervals
+    fn lift2(
+        self,
+        other: Interval,
+        f: impl FnOnce((Bound, Bound), (Bound, Bound)) -> Interval,
+    ) -> Interval {
+        match (self.bounds(), other.bounds()) {
+            (Some(a), Some(b)) => f(a, b),
+            _ => Interval::Empty,
+        }
+    }
+    /// The smallest interval containing all of the `corners`
+    fn hull(corners: impl IntoIterator<Item = Bound>) -> Interval {
+        let corners: Vec<_> = corners.into_iter().collect();
+        match (corners.iter().min(), corners.iter().max()) {
+            (Some(l), Some(u)) => Interval::new(*l, *u),
+            _ => Interval::Empty,
+        }
+    }
+
+    fn neg(self) -> Interval {
+        match self.bounds() {
+            Some((l, u)) => Interval::new(u.neg(), l.neg()),
+            None => Interval::Empty,
+        }
+    }
+    fn add(self, other: Interval) -> Interval {
+        self.lift2(other, |(l1, u1), (l2, u2)| {
+            Interval::new(l1.add(l2), u1.add(u2))
+        })
+    }
+    fn mul(self, other: Interval) -> Interval {
+        self.lift2(other, |(l1, u1), (l2, u2)| {
+            Interval::hull([l1.mul(l2), l1.mul(u2), u1.mul(l2), u1.mul(u2)])
+        })
+    }
+    fn div(self, other: Interval) -> Interval {
+        // NOTE: Division by zero fails, so the divisor is split into its
+        // strictly negative and strictly positive parts, on which truncating
+        // division is monotone and thus bounded by the corners.
+        let negative = other.meet(Interval::new(Bound::NegInf, Bound::Finite(-1)));
+        let positive = other.meet(Interval::new(Bound::Finite(1), Bound::PosInf));
+        [negative, positive]
+            .into_iter()
+            .map(|d| {
+                self.lift2(d, |(l1, u1), (l2, u2)| {
+                    Interval::hull([l1.div(l2), l1.div(u2), u1.div(l2), u1.div(u2)])
+                })
+            })
+            .fold(Interval::Empty, |acc, i| acc.lub(&i))
+    }
+    fn pow(self, other: Interval) -> Interval {
+        // NOTE: Negative exponents fail
+        let exp = other.meet(Interval::new(Bound::Finite(0), Bound::PosInf));
+        self.lift2(exp, |(l1, u1), (l2, u2)| match (l1, u1, l2, u2) {
+            (Bound::Finite(b), Bound::Finite(b2), Bound::Finite(e), Bound::Finite(e2))
+                if b == b2 && e == e2 =>
+            {
+                u32::try_from(e)
+                    .ok()
+                    .and_then(|e| b.checked_pow(e))
+                    .map_or(Interval::Empty, Interval::constant)
+            }
+            _ if l1 >= Bound::Finite(0) => Interval::new(
+                if l1 >= Bound::Finite(1) {
+                    l1.pow(l2)
+                } else {
+                    Bound::Finite(0)
+                },
+                if u1 <= Bound::Finite(1) {
+                    Bound::Finite(1)
+                } else {
+                    u1.pow(u2)
+                },
+            ),
+            _ => {
+                let magnitude = l1.neg().max(u1).pow(u2);
+                Interval::new(magnitude.neg(), magnitude)
+            }
+        })
+    }
+    fn min(self, other: Interval) -> Interval {
+        self.lift2(other, |(l1, u1), (l2, u2)| {
+            Interval::new(l1.min(l2), u1.min(u2))
+        })
+    }
+    fn max(self, other: Interval) -> Interval {
+        self.lift2(other, |(l1, u1), (l2, u2)| {
+            Interval::new(l1.max(l2), u1.max(u2))
+        })
+    }
+    /// Applies a function which is non-decreasing on the natural numbers and
+    /// undefined on negative numbers
+    fn monotone(self, f: impl Fn(Int) -> Option<Int>) -> Interval {
+        let domain = self.meet(Interval::new(Bound::Finite(0), Bound::PosInf));
+        let apply = |b| match b {
+            Bound::Finite(n) => f(n).map_or(Bound::PosInf, Bound::Finite),
+            b => b,
+        };
+        match domain.bounds() {
+            Some((l, u)) => Interval::new(apply(l), apply(u)),
+            None => Interval::Empty,
+        }
+    }
+}
+
+impl Bound {
+    fn finite(self) -> Option<Int> {
+        match self {
+            Bound::Finite(n) => Some(n),
+            _ => None,
+        }
+    }
+    fn signum(self) -> Int {
+        match self {
+            Bound::NegInf => -1,
+            Bound::Finite(n) => n.signum(),
+            Bound::PosInf => 1,
+        }
+    }
+    /// The infinity with the given sign
+    fn infinity(sign: Int) -> Bound {
+        if sign < 0 {
+            Bound::NegInf
+        } else {
+            Bound::PosInf
+        }
+    }
+    fn neg(self) -> Bound {
+        match self {
+            Bound::NegInf => Bound::PosInf,
+            Bound::Finite(n) => n.checked_neg().map_or(Bound::PosInf, Bound::Finite),
+            Bound::PosInf => Bound::NegInf,
+        }
+    }
+    fn add(self, other: Bound) -> Bound {
+        match (self, other) {
+            (Bound::Finite(a), Bound::Finite(b)) => a
+                .checked_add(b)
+                .map_or(Bound::infinity(a.signum()), Bound::Finite),
+            (Bound::NegInf, _) | (_, Bound::NegInf) => Bound::NegInf,
+            _ => Bound::PosInf,
+        }
+    }
+    fn mul(self, other: Bound) -> Bound {
+        match (self, other) {
+            (Bound::Finite(0), _) | (_, Bound::Finite(0)) => Bound::Finite(0),
+            (Bound::Finite(a), Bound::Finite(b)) => a
+                .checked_mul(b)
+                .map_or(Bound::infinity(a.signum() * b.signum()), Bound::Finite),
+            _ => Bound::infinity(self.signum() * other.signum()),
+        }
+    }
+    /// Truncating division by a non-zero bound
+    fn div(self, other: Bound) -> Bound {
+        match (self, other) {
+            (Bound::Finite(a), Bound::Finite(b)) => {
+                a.checked_div(b).map_or(Bound::PosInf, Bound::Finite)
+            }
+            (_, Bound::NegInf | Bound::PosInf) => Bound::Finite(0),
+            _ => Bound::infinity(self.signum() * other.signum()),
+        }
+    }
+    /// Exponentiation of a bound above 1 by a non-negative bound
+    fn pow(self, other: Bound) -> Bound {
+        match (self, other) {
+            (_, Bound::Finite(0)) => Bound::Finite(1),
+            (Bound::Finite(b), Bound::Finite(e)) => u32::try_from(e)
+                .ok()
+                .and_then(|e| b.checked_pow(e))
+                .map_or(Bound::PosInf, Bound::Finite),
+            _ => Bound::PosInf,
+        }
+    }
+}
+
+impl Lattice for Interval {
+    fn bottom() -> Self {
+        Interval::Empty
+    }
+
+    fn lub(&self, other: &Self) -> Self {
+        match (self.bounds(), other.bounds()) {
+            (None, _) => *other,
+            (_, None) => *self,
+            (Some((l1, u1)), Some((l2, u2))) => Interval::new(l1.min(l2), u1.max(u2)),
+        }
+    }
+
+    fn contains(&self, other: &Self) -> bool {
+        self.meet(*other) == *other
+    }
+}
+
+/// Combines the intervals of every variable and array of two memories
+pub(crate) fn pointwise(
+    prev: &IntervalMemory,
+    next: &IntervalMemory,
+    f: impl Fn(Interval, Interval) -> Interval,
+) -> IntervalMemory {
+    let mut result = prev.clone();
+    for (var, v) in result.variables.iter_mut() {
+        *v = f(*v, next.variables[var]);
+    }
+    for (arr, v) in result.arrays.iter_mut() {
+        *v = f(*v, next.arrays[arr]);
+    }
+    result
+}
+
+impl MonotoneFramework for IntervalAnalysis {
+    /// The intervals of every variable and array, or `None` if the node is
+    /// unreachable
+    type Domain = Option<IntervalMemory>;
+
+    fn semantic(&self, pg: &ProgramGraph, e: &Edge, prev: &Self::Domain) -> Self::Domain {
+        let mem = prev.as_ref()?;
+        match e.action() {
+            Action::Assignment(Target::Variable(var), x) => {
+                let value = x.semantics_interval(mem);
+                if value.is_empty() {
+                    return None;
+                }
+                let mut new_mem = mem.clone();
+                new_mem.variables.insert(var.clone(), value);
+                Some(new_mem)
+            }
+            Action::Assignment(Target::Array(arr, idx), expr) => {
+                let idx = idx.semantics_interval(mem);
+                let value = expr.semantics_interval(mem);
+                let current = *mem
+                    .get_arr(arr)
+                    .unwrap_or_else(|| panic!("could not get interval of array '{arr}'"));
+                if current.is_empty()
+                    || value.is_empty()
+                    || idx
+                        .meet(Interval::new(Bound::Finite(0), Bound::PosInf))
+                        .is_empty()
+                {
+                    return None;
+                }
+                // NOTE: Only one element is updated, so the previous elements
+                // may still be present
+                let mut new_mem = mem.clone();
+                new_mem.arrays.insert(arr.clone(), current.lub(&value));
+                Some(new_mem)
+            }
+            Action::Skip => Some(mem.clone()),
+            Action::Condition(b) => b.refine_interval(mem, true),
+            Action::Block(_) => self.transfer(pg, e, prev),
+        }
+    }
+
+    fn direction() -> Direction {
+        Direction::Forward
+    }
+
+    fn initial(&self, _pg: &ProgramGraph) -> Self::Domain {
+        Some(self.assignment.clone())
+    }
+
+    fn widening_points() -> WideningPoints {
+        WideningPoints::LoopHeads
+    }
+
+    fn widen(&self, prev: &Self::Domain, next: &Self::Domain) -> Self::Domain {
+        match (prev, next) {
+            (Some(prev), Some(next)) => Some(pointwise(prev, next, Interval::widen)),
+            _ => prev.lub(next),
+        }
+    }
+
+    fn narrow(&self, prev: &Self::Domain, next: &Self::Domain) -> Self::Domain {
+        match (prev, next) {
+            (Some(prev), Some(next)) => Some(pointwise(prev, next, Interval::narrow)),
+            _ => next.clone(),
+        }
+    }
+}
+
+impl BExpr {
+    /// Restricts `mem` to the memories in which `self` may evaluate to
+    /// `expected`, returning `None` if there are none
+    fn refine_interval(&self, mem: &IntervalMemory, expected: bool) -> Option<IntervalMemory> {
+        match &self.kind {
+            BExprKind::Bool(b) => (*b == expected).then(|| mem.clone()),
+            BExprKind::Rel(l, op, r) => {
+                let op = if expected { *op } else { op.negate() };
+                refine_rel(mem, l, op, r)
+            }
+            BExprKind::Logic(l, op, r) => {
+                let both = |lb, rb| {
+                    l.refine_interval(mem, lb)
+                        .and_then(|mem| r.refine_interval(&mem, rb))
+                };
+                let either = |lb, rb| l.refine_interval(mem, lb).lub(&r.refine_interval(mem, rb));
+                match (op, expected) {
+                    (LogicOp::And | LogicOp::Land, true) => both(true, true),
+                    (LogicOp::And | LogicOp::Land, false) => either(false, false),
+                    (LogicOp::Or | LogicOp::Lor, true) => either(true, true),
+                    (LogicOp::Or | LogicOp::Lor, false) => both(false, false),
+                    (LogicOp::Implies, true) => either(false, true),
+                    (LogicOp::Implies, false) => both(true, false),
+                }
+            }
+            BExprKind::Not(b) => b.refine_interval(mem, !expected),
+            BExprKind::Quantified(_, _, _) => Some(mem.clone()),
+        }
+    }
+}
+
+impl RelOp {
+    fn negate(self) -> RelOp {
+        match self {
+            RelOp::Eq => RelOp::Ne,
+            RelOp::Ne => RelOp::Eq,
+            RelOp::Gt => RelOp::Le,
+            RelOp::Ge => RelOp::Lt,
+            RelOp::Lt => RelOp::Ge,
+            RelOp::Le => RelOp::Gt,
+        }
+    }
+    /// The operator with the operands swapped
+    fn flip(self) -> RelOp {
+        match self {
+            RelOp::Eq => RelOp::Eq,
+            RelOp::Ne => RelOp::Ne,
+            RelOp::Gt => RelOp::Lt,
+            RelOp::Ge => RelOp::Le,
+            RelOp::Lt => RelOp::Gt,
+            RelOp::Le => RelOp::Ge,
+        }
+    }
+    /// The values `v` such that `v op r` holds for some `r` in `rhs`
+    fn satisfying(self, rhs: Interval) -> Interval {
+        let Some((l, u)) = rhs.bounds() else {
+            return Interval::Empty;
+        };
+        match self {
+            RelOp::Eq => rhs,
+            RelOp::Ne if l == u => Interval::Empty,
+            RelOp::Ne => Interval::top(),
+            RelOp::Gt => Interval::new(l.add(Bound::Finite(1)), Bound::PosInf),
+            RelOp::Ge => Interval::new(l, Bound::PosInf),
+            RelOp::Lt => Interval::new(Bound::NegInf, u.add(Bound::Finite(-1))),
+            RelOp::Le => Interval::new(Bound::NegInf, u),
+        }
+    }
+}
+
+/// Restricts `value` to the values `v` such that `v op r` holds for some `r`
+/// in `rhs`
+fn restrict(value: Interval, op: RelOp, rhs: Interval) -> Interval {
+    match (op, value.bounds(), rhs.bounds()) {
+        // NOTE: Disequality with a constant can only cut off an endpoint
+        (RelOp::Ne, Some((l, u)), Some((c, c2))) if c == c2 => {
+            if l == u && l == c {
+                Interval::Empty
+            } else if l == c {
+                Interval::new(l.add(Bound::Finite(1)), u)
+            } else if u == c {
+                Interval::new(l, u.add(Bound::Finite(-1)))
+            } else {
+                value
+            }
+        }
+        (RelOp::Ne, _, _) => value,
+        _ => value.meet(op.satisfying(rhs)),
+    }
+}
+
+fn refine_rel(mem: &IntervalMemory, l: &AExpr, op: RelOp, r: &AExpr) -> Option<IntervalMemory> {
+    let lv = l.semantics_interval(mem);
+    let rv = r.semantics_interval(mem);
+
+    let l_new = restrict(lv, op, rv);
+    let r_new = restrict(rv, op.flip(), lv);
+    if l_new.is_empty() || r_new.is_empty() {
+        return None;
+    }
+
+    let mut new_mem = mem.clone();
+    if let AExprKind::Reference(Target::Variable(x)) = &l.kind {
+        new_mem.variables.insert(x.clone(), l_new);
+    }
+    if let AExprKind::Reference(Target::Variable(y)) = &r.kind {
+        let current = new_mem.variables[y];
+        let refined = current.meet(r_new);
+        if refined.is_empty() {
+            return None;
+        }
+        new_mem.variables.insert(y.clone(), refined);
+    }
+    Some(new_mem)
+}
+
+impl AExpr {
+    /// The interval of values `self` may evaluate to, which is empty if the
+    /// evaluation always fails
+    fn semantics_interval(&self, mem: &IntervalMemory) -> Interval {
+        match &self.kind {
+            AExprKind::Number(n) => Interval::constant(*n),
+            AExprKind::Reference(Target::Variable(x)) => *mem
+                .get_var(x)
+                .unwrap_or_else(|| panic!("could not get interval of '{x}'")),
+            AExprKind::Reference(Target::Array(arr, idx)) => {
+                let idx = idx.semantics_interval(mem);
+                if idx
+                    .meet(Interval::new(Bound::Finite(0), Bound::PosInf))
+                    .is_empty()
+                {
+                    return Interval::Empty;
+                }
+                *mem.get_arr(arr)
+                    .unwrap_or_else(|| panic!("could not get interval of array '{arr}'"))
+            }
+            AExprKind::Binary(l, op, r) => {
+                let l = l.semantics_interval(mem);
+                let r = r.semantics_interval(mem);
+                match op {
+                    AOp::Plus => l.add(r),
+                    AOp::Minus => l.add(r.neg()),
+                    AOp::Times => l.mul(r),
+                    AOp::Divide => l.div(r),
+                    AOp::Pow => l.pow(r),
+                }
+            }
+            AExprKind::Minus(n) => n.semantics_interval(mem).neg(),
+            AExprKind::Function(f) => match f {
+                Function::Division(l, r) => {
+                    l.semantics_interval(mem).div(r.semantics_interval(mem))
+                }
+                Function::Min(x, y) => x.semantics_interval(mem).min(y.semantics_interval(mem)),
+                Function::Max(x, y) => x.semantics_interval(mem).max(y.semantics_interval(mem)),
+                Function::Count(_, x) | Function::LogicalCount(_, x) => {
+                    if x.semantics_interval(mem).is_empty() {
+                        Interval::Empty
+                    } else {
+                        Interval::new(Bound::Finite(0), Bound::PosInf)
+                    }
+                }
+                Function::Length(_) | Function::LogicalLength(_) => {
+                    Interval::new(Bound::Finite(0), Bound::PosInf)
+                }
+                Function::Fac(x) => x
+                    .semantics_interval(mem)
+                    .monotone(|n| (1..=n).try_fold(1 as Int, |acc, x| acc.checked_mul(x))),
+                Function::Fib(x) => x.semantics_interval(mem).monotone(|n| {
+                    (0..n)
+                        .try_fold((0 as Int, 1 as Int), |(a, b), _| {
+                            Some((b, a.checked_add(b)?))
+                        })
+                        .map(|(x, _)| x)
+                }),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        analysis::{mono_analysis, FiFo},
+        ast::Variable,
+        parse::parse_commands,
+        pg::{Determinism, Node, ProgramGraph},
+        sign::Memory,
+    };
+
+    use super::{Bound, Interval, IntervalAnalysis};
+
+    #[test]
+    fn narrowing_recovers_loop_bounds() -> miette::Result<()> {
+        let cmds = parse_commands("i := 0; do i < 10 -> i := i + 1 od")?;
+        let pg = ProgramGraph::new(Determinism::Deterministic, &cmds);
+        let a = IntervalAnalysis {
+            assignment: Memory::from_targets(cmds.fv(), |_| Interval::top(), |_| Interval::top()),
+        };
+        let results = mono_analysis::<_, FiFo>(a, &pg);
+
+        let i = Variable("i".to_string());
+        let interval_at = |n| results.facts[&n].as_ref().unwrap().variables[&i];
+        let head = *pg.loop_heads().iter().next().unwrap();
+        assert_eq!(
+            interval_at(head),
+            Interval::new(Bound::Finite(0), Bound::Finite(10))
+        );
+        assert_eq!(interval_at(Node::End), Interval::constant(10));
+
+        Ok(())
+    }
+}
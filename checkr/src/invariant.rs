@@ -0,0 +1,295 @@
+//! Inference of invariants for loops without one.
+//!
+//! The invariant proposed for a loop is the result of the
+//! [interval analysis](crate::interval) at the head of the loop, starting from
+//! a memory where every variable and array may hold any value. The analysis
+//! over-approximates the reachable memories, so the invariant holds every
+//! time the loop is entered or repeated, although it is rarely strong enough
+//! to prove anything beyond the bounds of variables.
+//!
+//! Widening stops at the constants of the guards before moving a bound to
+//! infinity. Narrowing alone does not recover the bound of a loop counter
+//! which is also read by a nested loop, as the nested loop keeps the bound
+//! of the outer one alive.
+
+use std::collections::BTreeSet;
+
+use crate::{
+    analysis::{mono_analysis, Direction, FiFo, MonotoneFramework, WideningPoints},
+    ast::{
+        AExpr, AExprKind, Array, BExpr, BExprKind, Command, CommandKind, Commands, Function, Guard,
+        Int, LogicOp, Quantifier, RelOp, Target, Variable,
+    },
+    interval::{pointwise, Interval, IntervalAnalysis, IntervalMemory},
+    pg::{Action, Determinism, Edge, ProgramGraph},
+    sign::Memory,
+};
+
+impl Commands {
+    /// Turns every [`CommandKind::Loop`] into a [`CommandKind::EnrichedLoop`]
+    /// with an inferred invariant. Loops which already have an invariant are
+    /// kept as they are.
+    pub fn infer_invariants(&self) -> Commands {
+        enrich_loops(self, &mut self.loop_invariants().into_iter())
+    }
+
+    /// The invariants [inferred](Commands::infer_invariants) for the loops
+    /// without one, in the order the loops appear in the program
+    pub fn inferred_invariants(&self) -> Vec<BExpr> {
+        let mut loops = vec![];
+        collect_loops(self, &mut loops);
+        loops
+            .into_iter()
+            .zip(self.loop_invariants())
+            .filter(|(c, _)| matches!(c.kind, CommandKind::Loop(_)))
+            .map(|(_, invariant)| invariant)
+            .collect()
+    }
+
+    /// The invariant proposed for every loop, in the order of
+    /// [`ProgramGraph::loop_command_heads`]
+    fn loop_invariants(&self) -> Vec<BExpr> {
+        let pg = ProgramGraph::new(Determinism::NonDeterministic, self);
+        let assignment = Memory::from_targets(pg.fv(), |_| Interval::top(), |_| Interval::top());
+        let a = ThresholdIntervalAnalysis {
+            intervals: IntervalAnalysis { assignment },
+            thresholds: guard_thresholds(&pg),
+        };
+        let facts = mono_analysis::<_, FiFo>(a, &pg).facts;
+
+        pg.loop_command_heads()
+            .iter()
+            .map(|head| match facts.get(head) {
+                Some(fact) => interval_predicate(fact),
+                None => BExprKind::Bool(true).into(),
+            })
+            .collect()
+    }
+}
+
+/// Every loop command, with a loop coming before the loops nested within it
+fn collect_loops<'a>(cmds: &'a Commands, loops: &mut Vec<&'a Command>) {
+    for c in &cmds.0 {
+        collect_loop(c, loops);
+    }
+}
+
+fn collect_loop<'a>(c: &'a Command, loops: &mut Vec<&'a Command>) {
+    match &c.kind {
+        CommandKind::Loop(gs) | CommandKind::EnrichedLoop(_, _, gs) => {
+            loops.push(c);
+            for Guard(_, body) in gs {
+                collect_loops(body, loops);
+            }
+        }
+        CommandKind::If(gs) => {
+            for Guard(_, body) in gs {
+                collect_loops(body, loops);
+            }
+        }
+        CommandKind::Annotated(_, inner, _) => collect_loops(inner, loops),
+        CommandKind::Assertion(_, inner) => collect_loop(inner, loops),
+        _ => {}
+    }
+}
+
+/// The [`IntervalAnalysis`] with [widening to
+/// thresholds](Interval::widen_with_thresholds)
+struct ThresholdIntervalAnalysis {
+    intervals: IntervalAnalysis,
+    thresholds: BTreeSet<Int>,
+}
+
+impl MonotoneFramework for ThresholdIntervalAnalysis {
+    type Domain = Option<IntervalMemory>;
+
+    fn semantic(&self, pg: &ProgramGraph, e: &Edge, prev: &Self::Domain) -> Self::Domain {
+        self.intervals.semantic(pg, e, prev)
+    }
+
+    fn direction() -> Direction {
+        IntervalAnalysis::direction()
+    }
+
+    fn initial(&self, pg: &ProgramGraph) -> Self::Domain {
+        self.intervals.initial(pg)
+    }
+
+    fn widening_points() -> WideningPoints {
+        IntervalAnalysis::widening_points()
+    }
+
+    fn widen(&self, prev: &Self::Domain, next: &Self::Domain) -> Self::Domain {
+        match (prev, next) {
+            (Some(prev), Some(next)) => Some(pointwise(prev, next, |a, b| {
+                a.widen_with_thresholds(b, &self.thresholds)
+            })),
+            _ => self.intervals.widen(prev, next),
+        }
+    }
+
+    fn narrow(&self, prev: &Self::Domain, next: &Self::Domain) -> Self::Domain {
+        self.intervals.narrow(prev, next)
+    }
+}
+
+/// The constants compared against in the conditions of the graph, together
+/// with their neighbours, such that both `x < c` and `x <= c` give bounds
+fn guard_thresholds(pg: &ProgramGraph) -> BTreeSet<Int> {
+    let mut thresholds = BTreeSet::new();
+    for e in pg.edges() {
+        if let Action::Condition(b) = e.action() {
+            for n in b.constants() {
+                for c in [n, n.saturating_neg()] {
+                    thresholds.extend([c.saturating_sub(1), c, c.saturating_add(1)]);
+                }
+            }
+        }
+    }
+    thresholds
+}
+
+/// Gives every [`CommandKind::Loop`] the next of the `invariants`, which
+/// contains one invariant for each loop, including those which already have
+/// one, in the order of [`ProgramGraph::loop_command_heads`]
+fn enrich_loops(cmds: &Commands, invariants: &mut impl Iterator<Item = BExpr>) -> Commands {
+    Commands(cmds.0.iter().map(|c| enrich_loop(c, invariants)).collect())
+}
+
+fn enrich_loop(c: &Command, invariants: &mut impl Iterator<Item = BExpr>) -> Command {
+    let guards = |guards: &[Guard], invariants: &mut _| {
+        guards
+            .iter()
+            .map(|Guard(b, c)| Guard(b.clone(), enrich_loops(c, invariants)))
+            .collect()
+    };
+    match &c.kind {
+        CommandKind::Loop(gs) => {
+            let invariant = invariants
+                .next()
+                .unwrap_or_else(|| BExprKind::Bool(true).into());
+            Command::new(
+                CommandKind::EnrichedLoop(invariant, None, guards(gs, invariants)),
+                c.span,
+            )
+        }
+        CommandKind::EnrichedLoop(i, v, gs) => {
+            invariants.next();
+            Command::new(
+                CommandKind::EnrichedLoop(i.clone(), v.clone(), guards(gs, invariants)),
+                c.span,
+            )
+        }
+        CommandKind::If(gs) => Command::new(CommandKind::If(guards(gs, invariants)), c.span),
+        CommandKind::Annotated(p, inner, q) => Command::new(
+            CommandKind::Annotated(p.clone(), enrich_loops(inner, invariants), q.clone()),
+            c.span,
+        ),
+        CommandKind::Assertion(p, inner) => Command::new(
+            CommandKind::Assertion(p.clone(), Box::new(enrich_loop(inner, invariants))),
+            c.span,
+        ),
+        _ => c.clone(),
+    }
+}
+
+/// The predicate which holds exactly in the memories described by `fact`.
+/// An array is described by the bounds of all its elements.
+fn interval_predicate(fact: &Option<IntervalMemory>) -> BExpr {
+    let Some(mem) = fact else {
+        return BExprKind::Bool(false).into();
+    };
+    let variables = mem.variables.iter().map(|(v, i)| {
+        bounds(
+            &AExprKind::Reference(Target::Variable(v.clone())).into(),
+            *i,
+        )
+    });
+    let arrays = mem.arrays.iter().map(|(a, i)| element_bounds(a, *i));
+    variables
+        .chain(arrays)
+        .filter(|b| b.kind != BExprKind::Bool(true))
+        .reduce(|a, b| BExpr::logic(a, LogicOp::Land, b))
+        .unwrap_or(BExprKind::Bool(true).into())
+}
+
+fn bounds(e: &AExpr, i: Interval) -> BExpr {
+    match i {
+        Interval::Empty => BExprKind::Bool(false).into(),
+        Interval::Range {
+            lower: Some(l),
+            upper: Some(u),
+        } if l == u => BExpr::rel(e.clone(), RelOp::Eq, AExprKind::Number(l).into()),
+        Interval::Range { lower, upper } => [
+            lower.map(|l| BExpr::rel(AExprKind::Number(l).into(), RelOp::Le, e.clone())),
+            upper.map(|u| BExpr::rel(e.clone(), RelOp::Le, AExprKind::Number(u).into())),
+        ]
+        .into_iter()
+        .flatten()
+        .reduce(|a, b| BExpr::logic(a, LogicOp::Land, b))
+        .unwrap_or(BExprKind::Bool(true).into()),
+    }
+}
+
+/// `forall _i :: _i < 0 || _i >= length(arr) || bounds(arr[_i])`, where the
+/// short-circuiting disjunctions keep the reads within bounds
+fn element_bounds(arr: &Array, i: Interval) -> BExpr {
+    let idx = Variable("_i".to_string());
+    let idx_expr: AExpr = AExprKind::Reference(Target::Variable(idx.clone())).into();
+    let element = bounds(
+        &AExprKind::Reference(Target::Array(arr.clone(), Box::new(idx_expr.clone()))).into(),
+        i,
+    );
+    if element.kind == BExprKind::Bool(true) {
+        return element;
+    }
+    let outside = BExpr::logic(
+        BExpr::rel(idx_expr.clone(), RelOp::Lt, AExprKind::Number(0).into()),
+        LogicOp::Or,
+        BExpr::rel(
+            idx_expr,
+            RelOp::Ge,
+            AExprKind::Function(Function::Length(arr.clone())).into(),
+        ),
+    );
+    BExprKind::Quantified(
+        Quantifier::Forall,
+        Target::Variable(idx),
+        Box::new(BExpr::logic(outside, LogicOp::Or, element)),
+    )
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::CommandKind,
+        parse::{parse_commands, parse_predicate},
+    };
+
+    #[test]
+    fn loops_are_given_interval_invariants() -> miette::Result<()> {
+        let cmds = parse_commands(
+            "i := 0;
+            do i < 10 ->
+                i := i + 1; j := 0;
+                do j < i -> j := j + 1 od
+            od",
+        )?
+        .infer_invariants();
+
+        let CommandKind::EnrichedLoop(outer, None, guards) = &cmds.0[1].kind else {
+            panic!("`{}` should be a loop with an invariant", cmds.0[1]);
+        };
+        let CommandKind::EnrichedLoop(inner, None, _) = &guards[0].1 .0[2].kind else {
+            panic!("`{}` should be a loop with an invariant", guards[0].1);
+        };
+        assert_eq!(outer, &parse_predicate("0 <= i & i <= 10")?);
+        assert_eq!(
+            inner,
+            &parse_predicate("(1 <= i & i <= 10) & (0 <= j & j <= 10)")?
+        );
+
+        Ok(())
+    }
+}
@@ -40,6 +40,8 @@ use crate::ast::Commands;
 
 pub mod analysis;
 pub mod ast;
+pub mod bit_vector;
+pub mod check;
 pub mod config;
 pub mod driver;
 pub mod egg;
@@ -48,11 +50,14 @@ pub mod fmt;
 mod gcl;
 pub mod generation;
 pub mod interpreter;
+pub mod interval;
+pub mod invariant;
 pub mod parse;
 pub mod pg;
 pub mod pv;
 pub mod security;
 pub mod sign;
+pub mod smt;
 
 #[derive(Debug)]
 pub struct ProgramGenerationBuilder {
@@ -61,6 +66,7 @@ pub struct ProgramGenerationBuilder {
     seed: Option<u64>,
     no_loop: bool,
     no_division: bool,
+    arrays: bool,
     generate_annotated: bool,
 }
 
@@ -78,6 +84,7 @@ impl ProgramGenerationBuilder {
             seed: Default::default(),
             no_loop: Default::default(),
             no_division: Default::default(),
+            arrays: Default::default(),
             generate_annotated: Default::default(),
         }
     }
@@ -97,6 +104,9 @@ impl ProgramGenerationBuilder {
             ..self
         }
     }
+    pub fn arrays(self, arrays: bool) -> Self {
+        ProgramGenerationBuilder { arrays, ..self }
+    }
 
     pub fn generate_annotated(self, generate_annotated: bool) -> Self {
         ProgramGenerationBuilder {
@@ -115,7 +125,8 @@ impl ProgramGenerationBuilder {
 
         let mut cx = generation::Context::new(fuel, &mut rng);
         cx.set_no_loop(self.no_loop)
-            .set_no_division(self.no_division);
+            .set_no_division(self.no_division)
+            .set_arrays(self.arrays);
 
         let cmds = match cmds {
             Some(cmds) => cmds,
@@ -1,13 +1,18 @@
 use miette::Diagnostic;
 use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use lalrpop_util::lexer::Token;
+
 use crate::{
-    ast::{BExpr, Commands, Predicate},
+    ast::{AExpr, BExpr, Commands, Int, Predicate},
     gcl,
+    pg::Action,
+    security::{Flow, SecurityClass},
 };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct SourceSpan {
     /// The start of the span.
     offset: usize,
@@ -65,22 +70,106 @@ impl From<(usize, usize)> for SourceSpan {
     }
 }
 
-pub fn parse_commands(src: &str) -> Result<Commands, ParseError> {
+type LalrpopError<'a> = lalrpop_util::ParseError<usize, Token<'a>, UserError>;
+type ErrorRecovery<'a> = lalrpop_util::ErrorRecovery<usize, Token<'a>, UserError>;
+
+/// Parses a program, recovering from syntax errors in individual commands.
+///
+/// Every syntax error is reported, and commands which could not be parsed are
+/// replaced by `skip` in the returned program. The program is only absent if
+/// the parser was unable to recover.
+pub fn parse_commands_recovering(src: &str) -> (Option<Commands>, Vec<ParseError>) {
     static PARSER: Lazy<gcl::CommandsParser> = Lazy::new(gcl::CommandsParser::new);
 
-    PARSER.parse(src).map_err(|e| ParseError::new(src, e))
+    recovering(src, |errors| PARSER.parse(errors, src))
+}
+
+pub fn parse_commands(src: &str) -> Result<Commands, ParseError> {
+    match parse_commands_recovering(src) {
+        (Some(cmds), errors) if errors.is_empty() => Ok(cmds),
+        (_, errors) => Err(first_error(errors)),
+    }
+}
+
+pub fn parse_aexpr(src: &str) -> Result<AExpr, ParseError> {
+    static PARSER: Lazy<gcl::AExprParser> = Lazy::new(gcl::AExprParser::new);
+
+    strict(src, |errors| PARSER.parse(errors, src))
 }
 
 pub fn parse_bexpr(src: &str) -> Result<BExpr, ParseError> {
     static PARSER: Lazy<gcl::BExprParser> = Lazy::new(gcl::BExprParser::new);
 
-    PARSER.parse(src).map_err(|e| ParseError::new(src, e))
+    strict(src, |errors| PARSER.parse(errors, src))
+}
+
+pub fn parse_action(src: &str) -> Result<Action, ParseError> {
+    static PARSER: Lazy<gcl::ActionParser> = Lazy::new(gcl::ActionParser::new);
+
+    strict(src, |errors| PARSER.parse(errors, src))
 }
 
 pub fn parse_predicate(src: &str) -> Result<Predicate, ParseError> {
     static PARSER: Lazy<gcl::PredicateParser> = Lazy::new(gcl::PredicateParser::new);
 
-    PARSER.parse(src).map_err(|e| ParseError::new(src, e))
+    strict(src, |errors| PARSER.parse(errors, src))
+}
+
+pub fn parse_security_lattice(src: &str) -> Result<Vec<Flow<SecurityClass>>, ParseError> {
+    static PARSER: Lazy<gcl::SecurityLatticeParser> = Lazy::new(gcl::SecurityLatticeParser::new);
+
+    strict(src, |errors| PARSER.parse(errors, src))
+}
+
+fn recovering<'a, T>(
+    src: &'a str,
+    parse: impl FnOnce(&mut Vec<ErrorRecovery<'a>>) -> Result<T, LalrpopError<'a>>,
+) -> (Option<T>, Vec<ParseError>) {
+    let mut recovered = vec![];
+    let result = parse(&mut recovered);
+    let mut errors: Vec<_> = recovered
+        .into_iter()
+        .map(|e| ParseError::new(src, e.error))
+        .collect();
+
+    match result {
+        Ok(t) => (Some(t), errors),
+        Err(e) => {
+            errors.push(ParseError::new(src, e));
+            (None, errors)
+        }
+    }
+}
+
+fn strict<'a, T>(
+    src: &'a str,
+    parse: impl FnOnce(&mut Vec<ErrorRecovery<'a>>) -> Result<T, LalrpopError<'a>>,
+) -> Result<T, ParseError> {
+    match recovering(src, parse) {
+        (Some(t), errors) if errors.is_empty() => Ok(t),
+        (_, errors) => Err(first_error(errors)),
+    }
+}
+
+fn first_error(errors: Vec<ParseError>) -> ParseError {
+    errors
+        .into_iter()
+        .next()
+        .expect("a failed parse reports at least one error")
+}
+
+/// Errors raised by the actions of the grammar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserError {
+    IntegerTooLarge { span: SourceSpan },
+}
+
+impl std::fmt::Display for UserError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UserError::IntegerTooLarge { .. } => write!(f, "integer literal is too large"),
+        }
+    }
 }
 
 #[derive(Debug, Error, Diagnostic, Clone)]
@@ -113,25 +202,38 @@ pub enum ParseError {
         err_span: SourceSpan,
         expected: String,
     },
+    #[error("Extra Token")]
+    #[diagnostic()]
+    ExtraToken {
+        #[source_code]
+        src: String,
+        #[label = "The token \"{token}\" was not expected after the end of the program"]
+        err_span: SourceSpan,
+        token: String,
+    },
+    #[error("Integer Too Large")]
+    #[diagnostic(help("Integers must be between {} and {}", Int::MIN, Int::MAX))]
+    IntegerTooLarge {
+        #[source_code]
+        src: String,
+        #[label = "This integer literal does not fit in 64 bits"]
+        err_span: SourceSpan,
+    },
+}
+impl ParseError {
+    pub fn span(&self) -> SourceSpan {
+        match self {
+            ParseError::InvalidToken { err_span, .. }
+            | ParseError::UnrecognizedToken { err_span, .. }
+            | ParseError::UnrecognizedEof { err_span, .. }
+            | ParseError::ExtraToken { err_span, .. }
+            | ParseError::IntegerTooLarge { err_span, .. } => *err_span,
+        }
+    }
 }
-// impl ParseError {
-//     pub fn span(&self) -> Span {
-//         match self {
-//             ParseError::InvalidToken { err_span, .. }
-//             | ParseError::UnrecognizedToken { err_span, .. }
-//             | ParseError::UnrecognizedEof { err_span, .. } => Span {
-//                 start: err_span.offset(),
-//                 end: err_span.offset() + err_span.len(),
-//             },
-//         }
-//     }
-// }
 
 impl ParseError {
-    pub(crate) fn new(
-        src: &str,
-        e: lalrpop_util::ParseError<usize, lalrpop_util::lexer::Token, &str>,
-    ) -> Self {
+    pub(crate) fn new(src: &str, e: LalrpopError) -> Self {
         let prep_src = || format!("{src}\n");
 
         match e {
@@ -155,8 +257,72 @@ impl ParseError {
                     hint: None,
                 }
             }
-            lalrpop_util::ParseError::ExtraToken { .. } => todo!(),
-            lalrpop_util::ParseError::User { .. } => todo!(),
+            lalrpop_util::ParseError::ExtraToken { token } => ParseError::ExtraToken {
+                src: prep_src(),
+                err_span: (token.0, token.2 - token.0).into(),
+                token: token.1.to_string(),
+            },
+            lalrpop_util::ParseError::User {
+                error: UserError::IntegerTooLarge { span },
+            } => ParseError::IntegerTooLarge {
+                src: prep_src(),
+                err_span: span,
+            },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{AExprKind, BExprKind};
+
+    use super::{parse_bexpr, parse_commands, parse_commands_recovering, ParseError};
+
+    #[test]
+    fn recovers_from_every_broken_command() {
+        let src = "x := ; y := 1; if y > 0 -> z := * fi; w := 99999999999999999999";
+        let (cmds, errors) = parse_commands_recovering(src);
+
+        let cmds = cmds.expect("the parser recovers from each error");
+        assert_eq!(cmds.0.len(), 4);
+        assert_eq!(cmds.0[1].to_string(), "y := 1");
+        assert!(matches!(
+            errors.as_slice(),
+            [
+                ParseError::UnrecognizedToken { .. },
+                ParseError::UnrecognizedToken { .. },
+                ParseError::IntegerTooLarge { .. },
+            ]
+        ));
+
+        assert!(parse_commands(src).is_err());
+    }
+
+    #[test]
+    fn expressions_carry_their_span() -> Result<(), ParseError> {
+        let src = "x + 1 > (2 * y) && !true";
+        let b = parse_bexpr(src)?;
+        assert_eq!(b, parse_bexpr("x+1>2*y&&!(true)")?);
+
+        let text = |span: Option<super::SourceSpan>| {
+            let span = span.unwrap();
+            &src[span.offset()..span.end()]
+        };
+        assert_eq!(text(b.span), src);
+        let BExprKind::Logic(rel, _, not) = &b.kind else {
+            panic!("expected a conjunction, but got {b}")
+        };
+        assert_eq!(text(not.span), "!true");
+        let BExprKind::Rel(l, _, r) = &rel.kind else {
+            panic!("expected a relation, but got {rel}")
+        };
+        assert_eq!(text(l.span), "x + 1");
+        assert_eq!(text(r.span), "2 * y");
+        let AExprKind::Binary(x, _, _) = &l.kind else {
+            panic!("expected a sum, but got {l}")
+        };
+        assert_eq!(text(x.span), "x");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+//! Compression of straight-line chains of edges into basic blocks.
+//!
+//! A node with exactly one incoming and one outgoing edge is merely a point
+//! between two actions. Removing such nodes leaves a smaller graph with the
+//! same branching structure, where each edge carries an [`Action::Block`] of
+//! the edges it replaced.
+
+use std::collections::HashSet;
+
+use super::{Action, Edge, Node, ProgramGraph};
+
+impl ProgramGraph {
+    /// Merges every chain of edges through nodes with a single incoming and a
+    /// single outgoing edge into one edge. The start and final node are always
+    /// kept, and so are all other nodes with several incoming or outgoing
+    /// edges, which includes the heads of loops.
+    pub fn compress_basic_blocks(&self) -> Self {
+        let is_interior = |n: Node| {
+            !matches!(n, Node::Start | Node::End)
+                && self.incoming(n).len() == 1
+                && self.outgoing(n).len() == 1
+        };
+
+        let mut merged = HashSet::new();
+        let mut edges = vec![];
+
+        for e in self.edges.iter().filter(|e| !is_interior(e.from())) {
+            let mut chain = vec![e.clone()];
+            while is_interior(chain.last().unwrap().to()) {
+                let next = &self.outgoing(chain.last().unwrap().to())[0];
+                chain.push(next.clone());
+            }
+            merged.extend(chain.iter().cloned());
+
+            edges.push(match chain.as_slice() {
+                [single] => single.clone(),
+                [first, .., last] => Edge(
+                    first.from(),
+                    Action::Block(chain.clone()),
+                    last.to(),
+                    chain
+                        .iter()
+                        .filter_map(|e| e.span())
+                        .reduce(|a, b| a.join(b)),
+                ),
+                [] => unreachable!("a chain contains at least the edge it started from"),
+            });
+        }
+
+        // NOTE: A cycle of interior nodes cannot be entered from the rest of
+        // the graph, and is kept as it is
+        edges.extend(self.edges.iter().filter(|e| !merged.contains(e)).cloned());
+
+        Self {
+            loops: self.loops.clone(),
+            ..Self::from_edges(edges)
+        }
+    }
+
+    /// Replaces every [`Action::Block`] with the edges it was made from. This
+    /// is the inverse of [`ProgramGraph::compress_basic_blocks`], up to the
+    /// order of the edges.
+    pub fn expand_basic_blocks(&self) -> Self {
+        fn expand(e: &Edge, edges: &mut Vec<Edge>) {
+            match e.action() {
+                Action::Block(inner) => {
+                    for e in inner {
+                        expand(e, edges);
+                    }
+                }
+                _ => edges.push(e.clone()),
+            }
+        }
+
+        let mut edges = vec![];
+        for e in &self.edges {
+            expand(e, &mut edges);
+        }
+        Self {
+            loops: self.loops.clone(),
+            ..Self::from_edges(edges)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use crate::{
+        analysis::{mono_analysis, FiFo},
+        bit_vector::{LiveVariables, ReachingDefinitions},
+        parse::parse_commands,
+        pg::{Determinism, ProgramGraph},
+    };
+
+    #[test]
+    fn compression_preserves_facts() -> miette::Result<()> {
+        let cmds = parse_commands(
+            "x := 1; y := 2;
+            do x < 10 -> x := x + y; y := y + 1; if x > 5 -> break [] true -> skip fi od;
+            z := x; x := z",
+        )?;
+        let pg = ProgramGraph::new(Determinism::Deterministic, &cmds);
+        let compressed = pg.compress_basic_blocks();
+
+        assert!(compressed.nodes().len() < pg.nodes().len());
+        assert!(compressed.nodes().is_subset(pg.nodes()));
+        let sorted_edges = |pg: &ProgramGraph| pg.edges().iter().cloned().sorted().collect_vec();
+        assert_eq!(
+            sorted_edges(&compressed.expand_basic_blocks()),
+            sorted_edges(&pg)
+        );
+
+        let reaching = mono_analysis::<_, FiFo>(ReachingDefinitions, &pg).facts;
+        let reaching_compressed = mono_analysis::<_, FiFo>(ReachingDefinitions, &compressed).facts;
+        let live = mono_analysis::<_, FiFo>(LiveVariables, &pg).facts;
+        let live_compressed = mono_analysis::<_, FiFo>(LiveVariables, &compressed).facts;
+        for n in compressed.nodes() {
+            assert_eq!(reaching[n], reaching_compressed[n]);
+            assert_eq!(live[n], live_compressed[n]);
+        }
+
+        Ok(())
+    }
+}
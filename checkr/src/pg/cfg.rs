@@ -0,0 +1,248 @@
+//! Control-flow structure of program graphs: dominators, back edges, natural
+//! loops, strongly connected components and unreachable nodes.
+//!
+//! Program graphs constructed from GCL programs are reducible, even with
+//! `break` and `continue`, so every back edge goes to a node dominating its
+//! source. The commands following a `break` or `continue` are however not
+//! reachable from the start node, and such nodes have no dominators.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use itertools::Itertools;
+
+use super::{Edge, Node, ProgramGraph};
+
+/// The dominator tree of a program graph, rooted either at the start node or,
+/// for post-dominators, at the final node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dominators {
+    root: Node,
+    /// The immediate dominator of every node reachable from the root, except
+    /// the root itself
+    idom: BTreeMap<Node, Node>,
+}
+
+impl Dominators {
+    pub fn root(&self) -> Node {
+        self.root
+    }
+
+    /// The closest strict dominator of `n`, or `None` if `n` is the root or is
+    /// not reachable from it
+    pub fn immediate_dominator(&self, n: Node) -> Option<Node> {
+        self.idom.get(&n).copied()
+    }
+
+    /// The dominators of `n`, starting with `n` itself and ending with the
+    /// root, or `None` if `n` is not reachable from the root
+    pub fn dominators(&self, n: Node) -> Option<Vec<Node>> {
+        if n != self.root && !self.idom.contains_key(&n) {
+            return None;
+        }
+        Some(std::iter::successors(Some(n), |&d| self.immediate_dominator(d)).collect())
+    }
+
+    /// Whether every path from the root to `b` passes through `a`. Every node
+    /// dominates itself.
+    pub fn dominates(&self, a: Node, b: Node) -> bool {
+        self.dominators(b).is_some_and(|ds| ds.contains(&a))
+    }
+}
+
+/// The nodes of a loop with a single entry, `head`, together with the back
+/// edges jumping to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NaturalLoop {
+    pub head: Node,
+    pub back_edges: Vec<Edge>,
+    /// The nodes of the loop, including the head
+    pub body: BTreeSet<Node>,
+}
+
+impl ProgramGraph {
+    /// The dominators with respect to the start node
+    pub fn dominators(&self) -> Dominators {
+        let (g, node_mapping, node_mapping_rev) = self.as_petgraph();
+        dominators_from(&g, Node::Start, &node_mapping, &node_mapping_rev)
+    }
+
+    /// The dominators with respect to the final node in the reversed graph,
+    /// that is the nodes which every path to the final node passes through
+    pub fn post_dominators(&self) -> Dominators {
+        let (g, node_mapping, node_mapping_rev) = self.as_petgraph();
+        dominators_from(
+            petgraph::visit::Reversed(&g),
+            Node::End,
+            &node_mapping,
+            &node_mapping_rev,
+        )
+    }
+
+    /// The edges going back to an ancestor in a depth-first traversal starting
+    /// from the start node. Every cycle contains one of them, including cycles
+    /// which are not reachable from the start node.
+    pub fn back_edges(&self) -> Vec<Edge> {
+        let (g, node_mapping, node_mapping_rev) = self.as_petgraph();
+
+        // NOTE: The traversal continues from the remaining nodes in order, to
+        // also cover the commands following a `break` or `continue`
+        let roots = node_mapping
+            .get(&Node::Start)
+            .into_iter()
+            .chain(node_mapping.values())
+            .copied();
+
+        let mut back = HashSet::new();
+        petgraph::visit::depth_first_search(&g, roots, |event| {
+            if let petgraph::visit::DfsEvent::BackEdge(from, to) = event {
+                back.insert((node_mapping_rev[&from], node_mapping_rev[&to]));
+            }
+        });
+
+        self.edges
+            .iter()
+            .filter(|e| back.contains(&(e.from(), e.to())))
+            .cloned()
+            .collect()
+    }
+
+    /// The targets of [back edges](Self::back_edges)
+    pub fn loop_heads(&self) -> HashSet<Node> {
+        self.back_edges().iter().map(|e| e.to()).collect()
+    }
+
+    /// The loops of the graph, one for each loop head, ordered by their head.
+    /// The body of a loop is every node which can reach one of its back edges
+    /// without passing through the head. This includes the unreachable nodes
+    /// following a `break` or `continue` inside the loop.
+    pub fn natural_loops(&self) -> Vec<NaturalLoop> {
+        self.back_edges()
+            .into_iter()
+            .into_group_map_by(|e| e.to())
+            .into_iter()
+            .sorted_by_key(|(head, _)| *head)
+            .map(|(head, back_edges)| {
+                let mut body = BTreeSet::from([head]);
+                let mut stack = back_edges.iter().map(|e| e.from()).collect_vec();
+                while let Some(n) = stack.pop() {
+                    if body.insert(n) {
+                        stack.extend(self.incoming(n).iter().map(|e| e.from()));
+                    }
+                }
+                NaturalLoop {
+                    head,
+                    back_edges,
+                    body,
+                }
+            })
+            .collect()
+    }
+
+    /// The strongly connected components in topological order, each given as
+    /// its sorted nodes
+    pub fn strongly_connected_components(&self) -> Vec<Vec<Node>> {
+        let (g, _, node_mapping_rev) = self.as_petgraph();
+
+        // NOTE: Tarjan's algorithm finds the components in reverse
+        // topological order
+        petgraph::algo::tarjan_scc(&g)
+            .into_iter()
+            .rev()
+            .map(|scc| scc.iter().map(|n| node_mapping_rev[n]).sorted().collect())
+            .collect()
+    }
+
+    /// The nodes which cannot be reached from the start node, such as the
+    /// nodes between a `break` and the end of the loop body
+    pub fn unreachable_nodes(&self) -> BTreeSet<Node> {
+        let mut reachable = HashSet::from([Node::Start]);
+        let mut stack = vec![Node::Start];
+        while let Some(n) = stack.pop() {
+            for e in self.outgoing(n) {
+                if reachable.insert(e.to()) {
+                    stack.push(e.to());
+                }
+            }
+        }
+        self.nodes
+            .iter()
+            .filter(|n| !reachable.contains(n))
+            .copied()
+            .collect()
+    }
+}
+
+fn dominators_from<G>(
+    g: G,
+    root: Node,
+    node_mapping: &BTreeMap<Node, petgraph::graph::NodeIndex>,
+    node_mapping_rev: &BTreeMap<petgraph::graph::NodeIndex, Node>,
+) -> Dominators
+where
+    G: petgraph::visit::IntoNeighbors<NodeId = petgraph::graph::NodeIndex>
+        + petgraph::visit::Visitable,
+{
+    let idom = match node_mapping.get(&root) {
+        Some(&r) => {
+            let doms = petgraph::algo::dominators::simple_fast(g, r);
+            node_mapping
+                .iter()
+                .filter_map(|(&n, &idx)| {
+                    let d = doms.immediate_dominator(idx)?;
+                    Some((n, node_mapping_rev[&d]))
+                })
+                .collect()
+        }
+        None => BTreeMap::new(),
+    };
+    Dominators { root, idom }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parse::parse_commands,
+        pg::{Determinism, Node, ProgramGraph},
+    };
+
+    #[test]
+    fn loops_and_dead_code() -> miette::Result<()> {
+        let cmds = parse_commands(
+            "i := 0;
+            do i < 10 ->
+                if i = 5 -> break; i := 7 [] true -> skip fi;
+                i := i + 1
+            od;
+            y := i",
+        )?;
+        let pg = ProgramGraph::new(Determinism::NonDeterministic, &cmds);
+
+        let loops = pg.natural_loops();
+        assert_eq!(loops.len(), 1);
+        let head = loops[0].head;
+        assert_eq!(pg.loop_heads(), [head].into_iter().collect());
+
+        let unreachable = pg.unreachable_nodes();
+        assert_eq!(unreachable.len(), 1);
+        let dead = *unreachable.first().unwrap();
+        assert_eq!(pg.outgoing(dead)[0].action().to_string(), "i := 7");
+        assert!(loops[0].body.contains(&dead));
+
+        let dominators = pg.dominators();
+        assert_eq!(dominators.dominators(dead), None);
+        for &n in loops[0].body.difference(&unreachable) {
+            assert!(dominators.dominates(head, n));
+        }
+        assert!(dominators.dominates(head, Node::End));
+        assert!(pg.post_dominators().dominates(Node::End, Node::Start));
+
+        let sccs = pg.strongly_connected_components();
+        assert!(sccs.contains(&loops[0].body.difference(&unreachable).copied().collect()));
+        let component = |n: Node| sccs.iter().position(|scc| scc.contains(&n)).unwrap();
+        for e in pg.edges() {
+            assert!(component(e.from()) <= component(e.to()));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,260 @@
+//! Serializations of program graphs in formats other than DOT.
+//!
+//! Nodes are named as in [`ProgramGraph::dot`], and edges are labelled with
+//! their action in the concrete syntax of GCL. Only the JSON format can be
+//! imported again.
+
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use miette::Diagnostic;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::parse;
+
+use super::{Edge, Node, NodeId, ProgramGraph};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "Case")]
+pub enum GraphFormat {
+    #[default]
+    Dot,
+    Json,
+    Mermaid,
+    GraphML,
+}
+
+impl GraphFormat {
+    /// The language of a fenced code block containing a graph in this format
+    pub fn code_block_language(self) -> &'static str {
+        match self {
+            GraphFormat::Dot => "dot",
+            GraphFormat::Json => "json",
+            GraphFormat::Mermaid => "mermaid",
+            GraphFormat::GraphML => "xml",
+        }
+    }
+}
+
+impl std::fmt::Display for GraphFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GraphFormat::Dot => write!(f, "DOT"),
+            GraphFormat::Json => write!(f, "JSON"),
+            GraphFormat::Mermaid => write!(f, "Mermaid"),
+            GraphFormat::GraphML => write!(f, "GraphML"),
+        }
+    }
+}
+
+/// A program graph given as a list of edges. Nodes other than the initial and
+/// final node can have any name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JsonGraph {
+    pub initial_node: String,
+    pub final_node: String,
+    pub edges: Vec<JsonEdge>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JsonEdge {
+    pub from: String,
+    pub action: String,
+    pub to: String,
+}
+
+#[derive(Debug, Error, Diagnostic, Clone, PartialEq, Eq)]
+pub enum GraphImportError {
+    #[error("the graph is not valid JSON: {message}")]
+    Json { message: String },
+    #[error("the initial and final node are both `{node}`")]
+    #[diagnostic(help("The initial and final node must be different"))]
+    SameEndpoints { node: String },
+    #[error("could not parse the edge label {label:?} as an action")]
+    Action { label: String },
+}
+
+impl ProgramGraph {
+    pub fn export(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.dot(),
+            GraphFormat::Json => self.json(),
+            GraphFormat::Mermaid => self.mermaid(),
+            GraphFormat::GraphML => self.graphml(),
+        }
+    }
+
+    pub fn to_json_graph(&self) -> JsonGraph {
+        JsonGraph {
+            initial_node: Node::Start.to_string(),
+            final_node: Node::End.to_string(),
+            edges: self
+                .edges
+                .iter()
+                .map(|e| JsonEdge {
+                    from: e.from().to_string(),
+                    action: e.action().to_string(),
+                    to: e.to().to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn json(&self) -> String {
+        serde_json::to_string_pretty(&self.to_json_graph())
+            .expect("program graphs can always be serialized")
+    }
+
+    /// A Mermaid flowchart. Nodes are identified by their ASCII name, see
+    /// [`Node`]'s `Debug` implementation, and displayed with their usual name.
+    pub fn mermaid(&self) -> String {
+        let nodes = self
+            .nodes
+            .iter()
+            .sorted()
+            .map(|n| format!("    {n:?}((\"{}\"))", mermaid_escape(&n.to_string())));
+        let edges = self.edges.iter().map(|e| {
+            format!(
+                "    {:?} -->|\"{}\"| {:?}",
+                e.from(),
+                mermaid_escape(&e.action().to_string()),
+                e.to()
+            )
+        });
+        format!("flowchart TD\n{}", nodes.chain(edges).format("\n"))
+    }
+
+    pub fn graphml(&self) -> String {
+        let nodes = self.nodes.iter().sorted().map(|n| {
+            format!(
+                "    <node id=\"{n:?}\"><data key=\"label\">{}</data></node>",
+                xml_escape(&n.to_string())
+            )
+        });
+        let edges = self.edges.iter().map(|e| {
+            format!(
+                "    <edge source=\"{:?}\" target=\"{:?}\"><data key=\"label\">{}</data></edge>",
+                e.from(),
+                e.to(),
+                xml_escape(&e.action().to_string())
+            )
+        });
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n  \
+             <key id=\"label\" for=\"all\" attr.name=\"label\" attr.type=\"string\"/>\n  \
+             <graph id=\"G\" edgedefault=\"directed\">\n\
+             {}\n  \
+             </graph>\n\
+             </graphml>",
+            nodes.chain(edges).format("\n")
+        )
+    }
+
+    pub fn from_json(src: &str) -> Result<Self, GraphImportError> {
+        let graph: JsonGraph = serde_json::from_str(src).map_err(|err| GraphImportError::Json {
+            message: err.to_string(),
+        })?;
+        Self::from_json_graph(&graph)
+    }
+
+    /// Builds the program graph with the given edges. The nodes are numbered
+    /// in reverse post-order, like in [`ProgramGraph::new`], so the names used
+    /// in the JSON are not preserved.
+    pub fn from_json_graph(graph: &JsonGraph) -> Result<Self, GraphImportError> {
+        if graph.initial_node == graph.final_node {
+            return Err(GraphImportError::SameEndpoints {
+                node: graph.initial_node.clone(),
+            });
+        }
+
+        let mut nodes: HashMap<String, Node> = [
+            (graph.initial_node.clone(), Node::Start),
+            (graph.final_node.clone(), Node::End),
+        ]
+        .into_iter()
+        .collect();
+        let mut next_id = 0;
+        let mut node = |name: &str| {
+            *nodes.entry(name.to_string()).or_insert_with(|| {
+                next_id += 1;
+                Node::Node(NodeId(next_id))
+            })
+        };
+
+        let edges = graph
+            .edges
+            .iter()
+            .map(|e| {
+                let action =
+                    parse::parse_action(&e.action).map_err(|_| GraphImportError::Action {
+                        label: e.action.clone(),
+                    })?;
+                Ok(Edge(node(&e.from), action, node(&e.to), None))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self::from_edges(edges).rename_with_reverse_post_order())
+    }
+}
+
+fn mermaid_escape(s: &str) -> String {
+    s.replace('"', "#quot;")
+        .replace('<', "#lt;")
+        .replace('>', "#gt;")
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parse::parse_commands,
+        pg::{Determinism, ProgramGraph},
+    };
+
+    use super::GraphImportError;
+
+    #[test]
+    fn json_round_trips() -> miette::Result<()> {
+        let cmds =
+            parse_commands("x := 1; do x < 10 -> if x = 5 -> break [] true -> x := x + 1 fi od")?;
+        let pg = ProgramGraph::new(Determinism::NonDeterministic, &cmds);
+
+        let imported = ProgramGraph::from_json(&pg.json())?;
+        assert_eq!(imported.dot(), pg.dot());
+
+        Ok(())
+    }
+
+    #[test]
+    fn json_import_accepts_any_node_names() -> miette::Result<()> {
+        let json = r#"{
+            "initial_node": "start",
+            "final_node": "end",
+            "edges": [
+                { "from": "start", "action": "x := 1", "to": "middle" },
+                { "from": "middle", "action": "y:=x", "to": "end" }
+            ]
+        }"#;
+        let cmds = parse_commands("x := 1; y := x")?;
+        let pg = ProgramGraph::new(Determinism::Deterministic, &cmds);
+        assert_eq!(ProgramGraph::from_json(json)?.dot(), pg.dot());
+
+        let bad = json.replace("y:=x", "y:=");
+        assert_eq!(
+            ProgramGraph::from_json(&bad).unwrap_err(),
+            GraphImportError::Action {
+                label: "y:=".to_string()
+            }
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,623 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    ast::{AExpr, BExpr, BExprKind, Command, CommandKind, Commands, Guard, LogicOp, Target},
+    parse::SourceSpan,
+};
+
+pub use cfg::{Dominators, NaturalLoop};
+pub use export::{GraphFormat, GraphImportError, JsonEdge, JsonGraph};
+
+pub mod basic_block;
+pub mod cfg;
+pub mod export;
+
+#[derive(Debug, Clone)]
+pub struct ProgramGraph {
+    edges: Vec<Edge>,
+    nodes: HashSet<Node>,
+    outgoing: HashMap<Node, Vec<Edge>>,
+    incoming: HashMap<Node, Vec<Edge>>,
+    /// The head of every loop command, see [`ProgramGraph::loop_command_heads`]
+    loops: Vec<Node>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(tag = "Case")]
+pub enum Determinism {
+    Deterministic,
+    NonDeterministic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct NodeId(u64);
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Node {
+    Start,
+    Node(NodeId),
+    End,
+}
+
+impl std::fmt::Debug for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Node::Start => write!(f, "qStart"),
+            Node::Node(n) => write!(f, "q{}", n.0),
+            Node::End => write!(f, "qFinal"),
+        }
+    }
+}
+impl std::fmt::Display for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Node::Start => write!(f, "q▷"),
+            Node::Node(n) => write!(
+                f,
+                "q{}",
+                n.0,
+                // n.0.to_string()
+                //     .chars()
+                //     .map(|c| match c {
+                //         '0' => '₀',
+                //         '1' => '₁',
+                //         '2' => '₂',
+                //         '3' => '₃',
+                //         '4' => '₄',
+                //         '5' => '₅',
+                //         '6' => '₆',
+                //         '7' => '₇',
+                //         '8' => '₈',
+                //         '9' => '₉',
+                //         c => c,
+                //     })
+                //     .format("")
+            ),
+            Node::End => write!(f, "q◀"),
+        }
+    }
+}
+
+/// The state used while constructing the edges of a single program graph. Each
+/// graph numbers its nodes from zero, independently of any other graph.
+#[derive(Debug)]
+struct GraphBuilder {
+    det: Determinism,
+    next_id: u64,
+    loops: Vec<Node>,
+}
+
+impl GraphBuilder {
+    fn new(det: Determinism) -> Self {
+        GraphBuilder {
+            det,
+            next_id: 0,
+            loops: vec![],
+        }
+    }
+    fn fresh(&mut self) -> Node {
+        let id = self.next_id;
+        self.next_id += 1;
+        Node::Node(NodeId(id))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Action {
+    Assignment(Target<Box<AExpr>>, AExpr),
+    Skip,
+    Condition(BExpr),
+    /// A chain of edges merged by [`ProgramGraph::compress_basic_blocks`].
+    /// The edges are kept, such that the chain can be expanded again.
+    Block(Vec<Edge>),
+}
+impl Action {
+    fn fv(&self) -> HashSet<Target> {
+        match self {
+            Action::Assignment(x, a) => x.fv().union(&a.fv()).cloned().collect(),
+            Action::Skip => Default::default(),
+            Action::Condition(b) => b.fv(),
+            Action::Block(edges) => edges.iter().flat_map(|e| e.action().fv()).collect(),
+        }
+    }
+}
+
+/// An edge in the program graph. The last component is the span of the
+/// command the edge was constructed from.
+///
+/// Like for [`Command`](crate::ast::Command), the span is ignored when
+/// comparing and hashing edges, such that the graph of a program does not
+/// depend on how it was formatted.
+#[derive(Debug, Clone)]
+pub struct Edge(pub Node, pub Action, pub Node, pub Option<SourceSpan>);
+
+impl PartialEq for Edge {
+    fn eq(&self, other: &Self) -> bool {
+        (&self.0, &self.1, &self.2) == (&other.0, &other.1, &other.2)
+    }
+}
+impl Eq for Edge {}
+impl PartialOrd for Edge {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Edge {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (&self.0, &self.1, &self.2).cmp(&(&other.0, &other.1, &other.2))
+    }
+}
+impl std::hash::Hash for Edge {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        (&self.0, &self.1, &self.2).hash(state);
+    }
+}
+
+impl Edge {
+    pub fn action(&self) -> &Action {
+        &self.1
+    }
+    pub fn span(&self) -> Option<SourceSpan> {
+        self.3
+    }
+
+    pub fn from(&self) -> Node {
+        self.0
+    }
+    pub fn to(&self) -> Node {
+        self.2
+    }
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Action::Assignment(v, x) => write!(f, "{v} := {x}"),
+            Action::Skip => write!(f, "skip"),
+            Action::Condition(b) => write!(f, "{b}"),
+            Action::Block(edges) => write!(f, "{}", edges.iter().map(|e| e.action()).format("; ")),
+        }
+    }
+}
+
+/// The head and exit nodes of the innermost enclosing loop. These are the
+/// targets of `continue` and `break` respectively.
+#[derive(Debug, Clone, Copy)]
+struct LoopNodes {
+    head: Node,
+    exit: Node,
+}
+
+impl Commands {
+    fn edges(&self, cx: &mut GraphBuilder, lp: Option<LoopNodes>, s: Node, t: Node) -> Vec<Edge> {
+        let mut edges = vec![];
+
+        let mut prev = s;
+        for (idx, cmd) in self.0.iter().enumerate() {
+            let is_last = idx + 1 == self.0.len();
+            let next = if is_last { t } else { cx.fresh() };
+            edges.extend(cmd.edges(cx, lp, prev, next));
+            prev = next;
+        }
+
+        edges
+    }
+}
+
+/// Computes the edges and the condition which is true iff all guards are false
+fn guard_edges(
+    cx: &mut GraphBuilder,
+    lp: Option<LoopNodes>,
+    span: Option<SourceSpan>,
+    guards: &[Guard],
+    s: Node,
+    t: Node,
+) -> (Vec<Edge>, BExpr) {
+    match cx.det {
+        Determinism::Deterministic => {
+            // See the "if" and "do" Commands on Page 25 of Formal Methods
+            let mut prev: BExpr = BExprKind::Bool(false).into();
+
+            let mut edges = vec![];
+
+            for Guard(b, c) in guards {
+                let q = cx.fresh();
+
+                edges.push(Edge(
+                    s,
+                    Action::Condition(BExpr::logic(
+                        b.clone(),
+                        LogicOp::Land,
+                        BExprKind::Not(Box::new(prev.clone())).into(),
+                    )),
+                    q,
+                    span,
+                ));
+                edges.extend(c.edges(cx, lp, q, t));
+                prev = BExpr::logic(b.to_owned().clone(), LogicOp::Lor, prev);
+            }
+
+            // Wraps in "not" so that the "d" part can be used directly by "do"
+            (edges, BExprKind::Not(Box::new(prev)).into())
+        }
+        Determinism::NonDeterministic => {
+            let e = guards
+                .iter()
+                .flat_map(|Guard(b, c)| {
+                    let q = cx.fresh();
+                    let mut edges = c.edges(cx, lp, q, t);
+                    edges.push(Edge(s, Action::Condition(b.clone()), q, span));
+                    edges
+                })
+                .collect();
+            (e, done(guards))
+        }
+    }
+}
+
+impl Command {
+    fn edges(&self, cx: &mut GraphBuilder, lp: Option<LoopNodes>, s: Node, t: Node) -> Vec<Edge> {
+        let span = self.span;
+        match &self.kind {
+            CommandKind::Assignment(v, expr) => {
+                vec![Edge(
+                    s,
+                    Action::Assignment(v.clone(), expr.clone()),
+                    t,
+                    span,
+                )]
+            }
+            CommandKind::Skip => vec![Edge(s, Action::Skip, t, span)],
+            CommandKind::If(guards) => guard_edges(cx, lp, span, guards, s, t).0,
+            CommandKind::Loop(guards) | CommandKind::EnrichedLoop(_, _, guards) => {
+                cx.loops.push(s);
+                let lp = LoopNodes { head: s, exit: t };
+                let (mut edges, b) = guard_edges(cx, Some(lp), span, guards, s, s);
+                edges.push(Edge(s, Action::Condition(b), t, span));
+                edges
+            }
+            CommandKind::Annotated(_, c, _) => c.edges(cx, lp, s, t),
+            CommandKind::Assertion(_, c) => c.edges(cx, lp, s, t),
+            CommandKind::Break => match lp {
+                Some(lp) => vec![Edge(s, Action::Skip, lp.exit, span)],
+                None => {
+                    warn!("`break` outside of a loop is treated as `skip`");
+                    vec![Edge(s, Action::Skip, t, span)]
+                }
+            },
+            CommandKind::Continue => match lp {
+                Some(lp) => vec![Edge(s, Action::Skip, lp.head, span)],
+                None => {
+                    warn!("`continue` outside of a loop is treated as `skip`");
+                    vec![Edge(s, Action::Skip, t, span)]
+                }
+            },
+        }
+    }
+}
+
+fn done(guards: &[Guard]) -> BExpr {
+    guards
+        .iter()
+        .map(|Guard(b, _c)| BExprKind::Not(Box::new(b.clone())).into())
+        .reduce(|a, b| BExpr::logic(a, LogicOp::Land, b))
+        .unwrap_or(BExprKind::Bool(true).into())
+}
+
+impl ProgramGraph {
+    pub fn new(det: Determinism, cmds: &Commands) -> Self {
+        let mut cx = GraphBuilder::new(det);
+        let edges = cmds.edges(&mut cx, None, Node::Start, Node::End);
+        Self {
+            loops: cx.loops,
+            ..Self::from_edges(edges)
+        }
+        .rename_with_reverse_post_order()
+    }
+    fn from_edges(edges: Vec<Edge>) -> Self {
+        let mut outgoing: HashMap<Node, Vec<Edge>> = HashMap::new();
+        let mut incoming: HashMap<Node, Vec<Edge>> = HashMap::new();
+        let mut nodes: HashSet<Node> = Default::default();
+
+        for e in &edges {
+            outgoing.entry(e.0).or_default().push(e.clone());
+            incoming.entry(e.2).or_default().push(e.clone());
+            nodes.insert(e.0);
+            nodes.insert(e.2);
+        }
+
+        Self {
+            edges,
+            nodes,
+            outgoing,
+            incoming,
+            loops: vec![],
+        }
+    }
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+    /// The head node of each loop command the graph was constructed from, in
+    /// the order the loops appear in the program, such that a loop comes
+    /// before the loops nested within it. Graphs which were not constructed
+    /// from commands have none.
+    pub fn loop_command_heads(&self) -> &[Node] {
+        &self.loops
+    }
+    pub fn nodes(&self) -> &HashSet<Node> {
+        &self.nodes
+    }
+    pub fn outgoing(&self, node: Node) -> &[Edge] {
+        self.outgoing
+            .get(&node)
+            .map(|s| s.as_slice())
+            .unwrap_or_default()
+    }
+    pub fn incoming(&self, node: Node) -> &[Edge] {
+        self.incoming
+            .get(&node)
+            .map(|s| s.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn fv(&self) -> HashSet<Target> {
+        self.edges.iter().flat_map(|e| e.action().fv()).collect()
+    }
+
+    pub fn dot(&self) -> String {
+        format!(
+            "digraph G {{\n{}\n}}",
+            self.edges
+                .iter()
+                .map(|e| format!(
+                    "  {:?}[label=\"{}\"]; {:?} -> {:?}[label={:?}]; {:?}[label=\"{}\"];",
+                    e.0,
+                    e.0,
+                    e.0,
+                    e.2,
+                    e.1.to_string(),
+                    e.2,
+                    e.2,
+                ))
+                .format("  \n")
+        )
+    }
+
+    pub fn as_petgraph(
+        &self,
+    ) -> (
+        petgraph::Graph<Node, Action>,
+        BTreeMap<Node, petgraph::graph::NodeIndex>,
+        BTreeMap<petgraph::graph::NodeIndex, Node>,
+    ) {
+        let mut g = petgraph::Graph::new();
+
+        let node_mapping: BTreeMap<Node, petgraph::graph::NodeIndex> = self
+            .nodes
+            .iter()
+            .copied()
+            .map(|n| (n, g.add_node(n)))
+            .collect();
+        let node_mapping_rev: BTreeMap<petgraph::graph::NodeIndex, Node> =
+            node_mapping.iter().map(|(a, b)| (*b, *a)).collect();
+
+        for Edge(from, action, to, _) in &self.edges {
+            g.add_edge(node_mapping[from], node_mapping[to], action.clone());
+        }
+
+        (g, node_mapping, node_mapping_rev)
+    }
+
+    /// The nodes in reverse post-order of a depth-first traversal from the
+    /// start node, followed by the nodes which are not reachable from it
+    pub fn reverse_post_order(&self) -> Vec<Node> {
+        let (g, node_mapping, node_mapping_rev) = self.as_petgraph();
+
+        let mut order = VecDeque::new();
+
+        if let Some(&initial_node) = node_mapping.get(&Node::Start) {
+            let mut dfs = petgraph::visit::DfsPostOrder::new(&g, initial_node);
+            while let Some(n) = dfs.next(&g) {
+                order.push_front(node_mapping_rev[&n]);
+            }
+        }
+
+        // NOTE: Commands following a `break` or `continue` are not reachable
+        // from the start node, but their nodes still need a place
+        let unreachable = node_mapping
+            .keys()
+            .filter(|n| !order.contains(n))
+            .copied()
+            .collect_vec();
+        order.extend(unreachable);
+
+        order.into()
+    }
+
+    pub fn rename_with_reverse_post_order(&self) -> Self {
+        if !self.nodes.contains(&Node::Start) {
+            warn!("graph did not have a start node");
+            return self.clone();
+        }
+        let new_order = self.reverse_post_order();
+
+        let mut node_mapping_new: BTreeMap<Node, Node> = Default::default();
+
+        enum NamingStage {
+            Start,
+            Middle { idx: u64 },
+        }
+
+        let mut stage = NamingStage::Start;
+        for n in new_order.iter() {
+            stage = match stage {
+                NamingStage::Start => {
+                    node_mapping_new.insert(*n, Node::Start);
+                    NamingStage::Middle { idx: 1 }
+                }
+                NamingStage::Middle { idx } => match n {
+                    Node::Start => todo!(),
+                    Node::Node(_) => {
+                        node_mapping_new.insert(*n, Node::Node(NodeId(idx)));
+                        NamingStage::Middle { idx: idx + 1 }
+                    }
+                    Node::End => {
+                        node_mapping_new.insert(*n, Node::End);
+                        NamingStage::Middle { idx }
+                    }
+                },
+            }
+        }
+
+        let edges = self
+            .edges
+            .iter()
+            .map(|Edge(a, action, b, span)| {
+                Edge(
+                    node_mapping_new[a],
+                    action.clone(),
+                    node_mapping_new[b],
+                    *span,
+                )
+            })
+            .collect();
+        Self {
+            loops: self.loops.iter().map(|n| node_mapping_new[n]).collect(),
+            ..Self::from_edges(edges)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::Variable,
+        interpreter::{Interpreter, InterpreterMemory, TerminationState},
+    };
+
+    use super::{Determinism, Edge, Node, ProgramGraph};
+
+    #[test]
+    fn break_and_continue_target_innermost_loop() -> miette::Result<()> {
+        let cmds = crate::parse::parse_commands(
+            "do true ->
+                i := i + 1;
+                do true -> break od;
+                if i < 3 -> continue [] i >= 3 -> break fi;
+                i := 100
+             od",
+        )?;
+        let i = Variable("i".to_string());
+
+        for det in [Determinism::Deterministic, Determinism::NonDeterministic] {
+            let pg = ProgramGraph::new(det, &cmds);
+            let execution = Interpreter::evaluate(100, InterpreterMemory::zero(&pg), &pg);
+
+            assert_eq!(execution.termination, TerminationState::Terminated);
+            let last = execution.trace.last().unwrap();
+            assert_eq!(last.node, Node::End);
+            assert_eq!(last.memory.get_var(&i), Some(&3));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn edges_carry_the_span_of_their_command() -> miette::Result<()> {
+        let src = "x := 1;\nif x > 0 -> y := x [] x <= 0 -> skip fi";
+        let cmds = crate::parse::parse_commands(src)?;
+
+        // NOTE: Spans do not take part in comparisons, neither of commands nor
+        // of edges
+        let reformatted = crate::parse::parse_commands("x:=1; if x>0 -> y:=x [] x<=0 -> skip fi")?;
+        assert_eq!(cmds, reformatted);
+
+        let pg = ProgramGraph::new(Determinism::Deterministic, &cmds);
+        assert_eq!(
+            pg.edges(),
+            ProgramGraph::new(Determinism::Deterministic, &reformatted).edges()
+        );
+        let text = |e: &Edge| {
+            let span = e.span().unwrap();
+            &src[span.offset()..span.end()]
+        };
+        let mut texts = pg.edges().iter().map(text).collect::<Vec<_>>();
+        texts.sort();
+        texts.dedup();
+
+        assert_eq!(
+            texts,
+            [
+                "if x > 0 -> y := x [] x <= 0 -> skip fi",
+                "skip",
+                "x := 1",
+                "y := x"
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn incoming_mirrors_outgoing() -> miette::Result<()> {
+        let cmds = crate::parse::parse_commands(
+            "do x > 0 -> if x = 1 -> break [] true -> x := x - 1 fi od; y := x",
+        )?;
+        let pg = ProgramGraph::new(Determinism::NonDeterministic, &cmds);
+
+        for &n in pg.nodes() {
+            for e in pg.outgoing(n) {
+                assert!(pg.incoming(e.to()).contains(e));
+            }
+            for e in pg.incoming(n) {
+                assert!(pg.outgoing(e.from()).contains(e));
+            }
+        }
+        let incoming: usize = pg.nodes().iter().map(|&n| pg.incoming(n).len()).sum();
+        assert_eq!(incoming, pg.edges().len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn graphs_built_concurrently_are_numbered_independently() -> miette::Result<()> {
+        let cmds = crate::parse::parse_commands(
+            "do x > 0 -> if x = 1 -> x := 0 [] true -> x := x - 1 fi od; y := x",
+        )?;
+        let expected = ProgramGraph::new(Determinism::Deterministic, &cmds).dot();
+
+        let dots = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| s.spawn(|| ProgramGraph::new(Determinism::Deterministic, &cmds).dot()))
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        assert!(dots.iter().all(|dot| *dot == expected));
+
+        Ok(())
+    }
+
+    #[test]
+    fn loop_commands_record_their_head() -> miette::Result<()> {
+        let cmds = crate::parse::parse_commands(
+            "x := 0; do x < 3 -> y := 0; do y < x -> y := y + 1 od; x := x + 1 od",
+        )?;
+        let pg = ProgramGraph::new(Determinism::NonDeterministic, &cmds);
+
+        let heads = pg.loop_command_heads();
+        assert_eq!(heads.len(), 2);
+        assert_eq!(pg.outgoing(Node::Start)[0].to(), heads[0]);
+        assert!(heads.iter().all(|n| pg.loop_heads().contains(n)));
+        assert_eq!(pg.compress_basic_blocks().loop_command_heads(), heads);
+
+        Ok(())
+    }
+}
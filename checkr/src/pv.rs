@@ -1,144 +1,797 @@
-use std::sync::atomic::AtomicU64;
+use std::collections::HashSet;
 
-use crate::ast::{
-    AExpr, BExpr, Command, Commands, Function, Guard, LogicOp, Quantifier, RelOp, Target, Variable,
+use itertools::Itertools;
+use miette::Diagnostic;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    ast::{
+        AExpr, AExprKind, AOp, Array, BExpr, BExprKind, Command, CommandKind, Commands, Function,
+        Guard, LogicOp, Quantifier, RelOp, Target, Variable,
+    },
+    parse::SourceSpan,
 };
 
+/// Supplies the names of the fresh variables introduced by the strongest
+/// postcondition of assignments. A single context is shared by all the
+/// commands of a computation, such that the names never clash.
+#[derive(Debug, Default)]
+struct SpContext {
+    next_fresh: u64,
+    /// Facts which hold before the current command, as none of their
+    /// variables have been assigned since. See [`framed`].
+    frame: Vec<BExpr>,
+}
+
+impl SpContext {
+    fn fresh(&mut self) -> Target<Box<AExpr>> {
+        let id = self.next_fresh;
+        self.next_fresh += 1;
+        Target::Variable(Variable(format!("_fresh_{id}")))
+    }
+}
+
+/// What a verification condition is needed for
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "Case")]
+pub enum VcKind {
+    /// If the program terminates, it establishes its postcondition
+    #[default]
+    PartialCorrectness,
+    /// Every loop with a variant terminates
+    Termination,
+}
+
+/// A verification condition together with the span of the command which gave
+/// rise to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocatedVc {
+    pub condition: BExpr,
+    pub span: Option<SourceSpan>,
+    pub kind: VcKind,
+}
+
+impl LocatedVc {
+    fn partial(condition: BExpr, span: Option<SourceSpan>) -> Self {
+        LocatedVc {
+            condition,
+            span,
+            kind: VcKind::PartialCorrectness,
+        }
+    }
+    fn termination(condition: BExpr, span: Option<SourceSpan>) -> Self {
+        LocatedVc {
+            condition,
+            span,
+            kind: VcKind::Termination,
+        }
+    }
+}
+
+type LocatedVcs = Vec<LocatedVc>;
+
 impl Commands {
     pub fn sp(&self, p: &BExpr) -> BExpr {
-        self.0.iter().fold(p.clone(), |acc, c| c.sp(&acc))
+        self.exits(&mut SpContext::default(), p).normal
     }
     pub fn vc(&self, r: &BExpr) -> Vec<BExpr> {
-        self.0
-            .iter()
-            .scan(r.clone(), |acc, c| {
-                let vc = c.vc(acc);
+        without_spans(self.located_vc(r))
+    }
+    /// The verification conditions paired with the span of the command which
+    /// gave rise to them and what they are needed for
+    pub fn located_vc(&self, r: &BExpr) -> LocatedVcs {
+        self.located_vc_in(&mut SpContext::default(), r)
+    }
+    fn located_vc_in(&self, cx: &mut SpContext, r: &BExpr) -> LocatedVcs {
+        let mut acc = r.clone();
+        let mut conditions = vec![];
+        for c in &self.0 {
+            conditions.extend(c.located_vc_in(cx, &acc));
+            acc = c.exits(cx, &acc).normal;
+        }
+        conditions
+    }
+    fn exits(&self, cx: &mut SpContext, p: &BExpr) -> Exits {
+        let frame = cx.frame.clone();
+        let mut assigned = HashSet::new();
+        let exits = self.0.iter().fold(Exits::normal(p.clone()), |mut acc, c| {
+            cx.frame = unassigned(&frame, &assigned);
+            let exits = c.exits(cx, &acc.normal);
+            assigned.extend(command_assigned(c));
+            acc.normal = exits.normal;
+            acc.breaks.extend(exits.breaks);
+            acc.continues.extend(exits.continues);
+            acc
+        });
+        cx.frame = frame;
+        exits
+    }
+}
 
-                *acc = c.sp(acc);
+/// The strongest postconditions of a command, split by how control leaves
+/// it: either by falling through to the next command, or by jumping out of
+/// the innermost loop with `break` or back to its head with `continue`.
+#[derive(Debug, Clone)]
+struct Exits {
+    normal: BExpr,
+    breaks: Vec<BExpr>,
+    continues: Vec<BExpr>,
+}
 
-                Some(vc)
-            })
-            .flatten()
-            .collect()
+impl Exits {
+    fn normal(normal: BExpr) -> Exits {
+        Exits {
+            normal,
+            breaks: vec![],
+            continues: vec![],
+        }
     }
 }
 
-static FRESH_ID: AtomicU64 = AtomicU64::new(0);
 impl Command {
-    pub fn reset_sp_counter() {
-        FRESH_ID.store(0, std::sync::atomic::Ordering::Relaxed);
-    }
     pub fn sp(&self, p: &BExpr) -> BExpr {
-        match self {
-            Command::Assignment(x, e) => {
-                fn fresh() -> Target<Box<AExpr>> {
-                    Target::Variable(Variable(format!(
-                        "_fresh_{}",
-                        FRESH_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
-                    )))
-                }
-
-                let y = fresh();
-                let y_expr = AExpr::Reference(y.clone());
+        self.exits(&mut SpContext::default(), p).normal
+    }
+    fn exits(&self, cx: &mut SpContext, p: &BExpr) -> Exits {
+        match &self.kind {
+            CommandKind::Assignment(Target::Array(arr, idx), e) => {
+                // NOTE: The array before the assignment is the array after it
+                // with the element at the old value `k` of the index replaced
+                // by its old value `v`. The assignment only succeeds when `k`
+                // is within bounds, which is checked first such that the rest
+                // is always defined.
+                let (k, v) = (cx.fresh(), cx.fresh());
+                let (k_expr, v_expr): (AExpr, AExpr) = (
+                    AExprKind::Reference(k.clone()).into(),
+                    AExprKind::Reference(v.clone()).into(),
+                );
+                let old = Store::new(arr, &k_expr, &v_expr);
+                let element: AExpr =
+                    AExprKind::Reference(Target::Array(arr.clone(), Box::new(k_expr.clone())))
+                        .into();
 
-                BExpr::Quantified(
-                    Quantifier::Exists,
-                    y.unit(),
-                    Box::new(BExpr::logic(
-                        p.subst_var(x, &y_expr),
+                let in_bounds = BExpr::logic(
+                    BExpr::rel(AExprKind::Number(0).into(), RelOp::Le, k_expr.clone()),
+                    LogicOp::Land,
+                    BExpr::rel(
+                        k_expr.clone(),
+                        RelOp::Lt,
+                        AExprKind::Function(Function::Length(arr.clone())).into(),
+                    ),
+                );
+                let post = BExpr::logic(
+                    in_bounds,
+                    LogicOp::And,
+                    BExpr::logic(
+                        BExpr::logic(
+                            old.bexpr(p),
+                            LogicOp::Land,
+                            old.bexpr(&BExpr::rel(k_expr.clone(), RelOp::Eq, (**idx).clone())),
+                        ),
                         LogicOp::Land,
-                        BExpr::rel(
-                            AExpr::Reference(x.clone()),
-                            RelOp::Eq,
-                            e.subst_var(x, &y_expr),
+                        guarded(
+                            old.aexpr(e)
+                                .into_iter()
+                                .map(|(c, e)| (c, BExpr::rel(element.clone(), RelOp::Eq, e)))
+                                .collect(),
                         ),
-                    )),
+                    ),
+                );
+                Exits::normal(
+                    BExprKind::Quantified(
+                        Quantifier::Exists,
+                        k.unit(),
+                        Box::new(
+                            BExprKind::Quantified(Quantifier::Exists, v.unit(), Box::new(post))
+                                .into(),
+                        ),
+                    )
+                    .into(),
                 )
             }
-            Command::Skip => p.clone(),
-            Command::If(guards) => guards_sp(guards, p),
-            Command::Loop(guards) => guards
-                .iter()
-                .map(|gc| BExpr::Not(gc.0.clone().into()))
-                .reduce(|a, b| BExpr::logic(a, LogicOp::Land, b))
-                .unwrap(),
-            Command::EnrichedLoop(i, guards) => {
-                let done = guards
-                    .iter()
-                    .map(|gc| BExpr::Not(gc.0.clone().into()))
-                    .reduce(|a, b| BExpr::logic(a, LogicOp::Land, b))
-                    .unwrap();
-                BExpr::logic(i.clone(), LogicOp::Land, done)
+            CommandKind::Assignment(x, e) => {
+                let y = cx.fresh();
+                let y_expr: AExpr = AExprKind::Reference(y.clone()).into();
+
+                Exits::normal(
+                    BExprKind::Quantified(
+                        Quantifier::Exists,
+                        y.unit(),
+                        Box::new(BExpr::logic(
+                            p.subst_var(x, &y_expr),
+                            LogicOp::Land,
+                            BExpr::rel(
+                                AExprKind::Reference(x.clone()).into(),
+                                RelOp::Eq,
+                                e.subst_var(x, &y_expr),
+                            ),
+                        )),
+                    )
+                    .into(),
+                )
+            }
+            CommandKind::Skip => Exits::normal(p.clone()),
+            CommandKind::If(guards) => guards_exits(cx, guards, p),
+            // NOTE: Without an invariant, the loop is treated as if it was
+            // annotated with `true`
+            CommandKind::Loop(guards) => {
+                let frame = loop_frame(&cx.frame, guards);
+                loop_exits(
+                    cx,
+                    &BExprKind::Bool(true).into(),
+                    guards,
+                    framed(done(guards), &frame),
+                )
+            }
+            CommandKind::EnrichedLoop(i, _, guards) => {
+                let frame = loop_frame(&cx.frame, guards);
+                loop_exits(
+                    cx,
+                    i,
+                    guards,
+                    BExpr::logic(framed(i.clone(), &frame), LogicOp::Land, done(guards)),
+                )
             }
             // TODO: Does this even make sense? It should never be called anyway
-            Command::Annotated(_, _, q) => q.clone(),
-            Command::Break => todo!(),
-            Command::Continue => todo!(),
+            CommandKind::Annotated(p, c, q) => Exits {
+                normal: q.clone(),
+                ..c.exits(cx, p)
+            },
+            // NOTE: The assertion summarizes everything before it, just like
+            // an invariant summarizes the iterations before it
+            CommandKind::Assertion(r, c) => c.exits(cx, r),
+            CommandKind::Break => Exits {
+                normal: BExprKind::Bool(false).into(),
+                breaks: vec![p.clone()],
+                continues: vec![],
+            },
+            CommandKind::Continue => Exits {
+                normal: BExprKind::Bool(false).into(),
+                breaks: vec![],
+                continues: vec![p.clone()],
+            },
         }
     }
     pub fn vc(&self, r: &BExpr) -> Vec<BExpr> {
-        match self {
-            Command::Assignment(_, _) => vec![],
-            Command::Skip => vec![],
-            Command::If(guards) => guards_vc(guards, r),
+        without_spans(self.located_vc_in(&mut SpContext::default(), r))
+    }
+    fn located_vc_in(&self, cx: &mut SpContext, r: &BExpr) -> LocatedVcs {
+        match &self.kind {
+            CommandKind::Assignment(_, _) => vec![],
+            CommandKind::Skip => vec![],
+            CommandKind::If(guards) => guards_vc(cx, guards, r),
             // TODO: Could we make something more useful/obvious here?
-            Command::Loop(_) => vec![],
-            Command::EnrichedLoop(i, guards) => {
+            CommandKind::Loop(_) => vec![],
+            CommandKind::EnrichedLoop(i, variant, guards) => {
+                let body = guards_exits(cx, guards, i);
+
                 let mut conditions = vec![
-                    BExpr::logic(r.clone(), LogicOp::Implies, i.clone()),
-                    BExpr::logic(guards_sp(guards, i), LogicOp::Implies, i.clone()),
+                    LocatedVc::partial(
+                        BExpr::logic(r.clone(), LogicOp::Implies, i.clone()),
+                        self.span,
+                    ),
+                    LocatedVc::partial(
+                        BExpr::logic(body.normal, LogicOp::Implies, i.clone()),
+                        self.span,
+                    ),
                 ];
+                // NOTE: Jumping back to the head of the loop must also
+                // reestablish the invariant
+                conditions.extend(body.continues.into_iter().map(|c| {
+                    LocatedVc::partial(BExpr::logic(c, LogicOp::Implies, i.clone()), self.span)
+                }));
+
+                conditions.extend(guards_vc(cx, guards, i));
+
+                if let Some(v) = variant {
+                    let x = variant_name(i, v, guards);
+                    let before: AExpr = AExprKind::Reference(Target::Variable(x.clone())).into();
+                    let initial = BExpr::rel(v.clone(), RelOp::Eq, before.clone());
+                    for gc in guards {
+                        conditions.push(LocatedVc::termination(bounded_below(i, gc, v), self.span));
+                        let outer = std::mem::replace(&mut cx.frame, vec![initial.clone()]);
+                        let body =
+                            gc.exits(cx, &BExpr::logic(i.clone(), LogicOp::Land, initial.clone()));
+                        cx.frame = outer;
+                        let decreased = BExpr::rel(v.clone(), RelOp::Lt, before.clone());
+                        conditions.extend(std::iter::once(body.normal).chain(body.continues).map(
+                            |post| {
+                                LocatedVc::termination(
+                                    BExprKind::Quantified(
+                                        Quantifier::Forall,
+                                        Target::Variable(x.clone()),
+                                        Box::new(BExpr::logic(
+                                            post,
+                                            LogicOp::Implies,
+                                            decreased.clone(),
+                                        )),
+                                    )
+                                    .into(),
+                                    self.span,
+                                )
+                            },
+                        ));
+                    }
+                }
+
+                conditions
+            }
+            CommandKind::Annotated(p, c, q) => {
+                let mut conditions = vec![LocatedVc::partial(
+                    BExpr::logic(c.exits(cx, p).normal, LogicOp::Implies, q.clone()),
+                    self.span,
+                )];
 
-                conditions.extend_from_slice(&guards_vc(guards, i));
+                conditions.extend(c.located_vc_in(cx, p));
 
                 conditions
             }
-            Command::Annotated(p, c, q) => {
-                let mut conditions = vec![BExpr::logic(c.sp(p), LogicOp::Implies, q.clone())];
+            CommandKind::Assertion(a, c) => {
+                let mut conditions = vec![LocatedVc::partial(
+                    BExpr::logic(r.clone(), LogicOp::Implies, a.clone()),
+                    self.span,
+                )];
 
-                conditions.extend_from_slice(&c.vc(p));
+                conditions.extend(c.located_vc_in(cx, a));
 
                 conditions
             }
-            Command::Break => todo!(),
-            Command::Continue => todo!(),
+            CommandKind::Break => vec![],
+            CommandKind::Continue => vec![],
         }
     }
 }
-fn guards_sp(guards: &[Guard], p: &BExpr) -> BExpr {
+fn done(guards: &[Guard]) -> BExpr {
+    guards
+        .iter()
+        .map(|gc| BExprKind::Not(gc.0.clone().into()).into())
+        .reduce(|a, b| BExpr::logic(a, LogicOp::Land, b))
+        .unwrap()
+}
+/// The loop is left either when all guards are false, or when a `break` is
+/// reached in one of the bodies. `break` and `continue` inside the loop never
+/// escape it.
+fn loop_exits(cx: &mut SpContext, i: &BExpr, guards: &[Guard], done: BExpr) -> Exits {
+    let frame = loop_frame(&cx.frame, guards);
+    let outer = std::mem::replace(&mut cx.frame, frame);
+    let body = guards_exits(cx, guards, i);
+    cx.frame = outer;
+    Exits::normal(
+        std::iter::once(done)
+            .chain(body.breaks)
+            .reduce(|a, b| BExpr::logic(a, LogicOp::Lor, b))
+            .unwrap(),
+    )
+}
+fn guards_exits(cx: &mut SpContext, guards: &[Guard], p: &BExpr) -> Exits {
+    guards
+        .iter()
+        .map(|gc| gc.exits(cx, p))
+        .reduce(|mut a, b| {
+            a.normal = BExpr::logic(a.normal, LogicOp::Lor, b.normal);
+            a.breaks.extend(b.breaks);
+            a.continues.extend(b.continues);
+            a
+        })
+        .unwrap()
+}
+fn guards_vc(cx: &mut SpContext, guards: &[Guard], r: &BExpr) -> LocatedVcs {
     guards
         .iter()
-        .map(|gc| gc.sp(p))
-        .reduce(|a, b| BExpr::logic(a, LogicOp::Lor, b))
+        .flat_map(|gc| gc.located_vc_in(cx, r))
+        .collect()
+}
+fn without_spans(vcs: LocatedVcs) -> Vec<BExpr> {
+    vcs.into_iter().map(|vc| vc.condition).collect()
+}
+
+/// A variable which does not occur free in a loop, its annotations or those
+/// of the commands within it, and which differs from the names given to the
+/// variants of nested loops. It names the value of the variant at the start
+/// of an iteration.
+fn variant_name(i: &BExpr, v: &AExpr, guards: &[Guard]) -> Variable {
+    let used: HashSet<_> = i
+        .fv()
+        .into_iter()
+        .chain(v.fv())
+        .chain(annotated_fv(guards))
+        .collect();
+    (0..)
+        .map(|n| match n {
+            0 => Variable("_variant".to_string()),
+            n => Variable(format!("_variant{n}")),
+        })
+        .find(|x| !used.contains(&Target::Variable(x.clone())))
         .unwrap()
 }
-fn guards_vc(guards: &[Guard], r: &BExpr) -> Vec<BExpr> {
-    guards.iter().flat_map(|gc| gc.vc(r)).collect()
+
+/// The free variables of the guards and their bodies, including those of
+/// annotations and the names of the variants of nested loops
+fn annotated_fv(guards: &[Guard]) -> HashSet<Target> {
+    guards
+        .iter()
+        .flat_map(|Guard(b, body)| b.fv().into_iter().chain(body.0.iter().flat_map(command_fv)))
+        .collect()
+}
+
+fn command_fv(c: &Command) -> HashSet<Target> {
+    match &c.kind {
+        CommandKind::If(guards) | CommandKind::Loop(guards) => annotated_fv(guards),
+        CommandKind::EnrichedLoop(i, v, guards) => {
+            let mut fv = annotated_fv(guards);
+            fv.extend(i.fv());
+            if let Some(v) = v {
+                fv.extend(v.fv());
+                fv.insert(Target::Variable(variant_name(i, v, guards)));
+            }
+            fv
+        }
+        CommandKind::Annotated(p, inner, q) => p
+            .fv()
+            .into_iter()
+            .chain(q.fv())
+            .chain(inner.0.iter().flat_map(command_fv))
+            .collect(),
+        CommandKind::Assertion(p, inner) => p.fv().union(&command_fv(inner)).cloned().collect(),
+        _ => c.fv(),
+    }
+}
+
+/// The variables and arrays assigned by the command, including those assigned
+/// within its bodies
+fn command_assigned(c: &Command) -> HashSet<Target> {
+    match &c.kind {
+        CommandKind::Assignment(x, _) => [x.clone().unit()].into_iter().collect(),
+        CommandKind::If(guards)
+        | CommandKind::Loop(guards)
+        | CommandKind::EnrichedLoop(_, _, guards) => guards_assigned(guards),
+        CommandKind::Annotated(_, inner, _) => inner.0.iter().flat_map(command_assigned).collect(),
+        CommandKind::Assertion(_, inner) => command_assigned(inner),
+        CommandKind::Skip | CommandKind::Break | CommandKind::Continue => HashSet::default(),
+    }
+}
+fn guards_assigned(guards: &[Guard]) -> HashSet<Target> {
+    guards
+        .iter()
+        .flat_map(|Guard(_, body)| body.0.iter().flat_map(command_assigned))
+        .collect()
+}
+
+/// The facts of `frame` which do not mention any of the `assigned` targets
+fn unassigned(frame: &[BExpr], assigned: &HashSet<Target>) -> Vec<BExpr> {
+    frame
+        .iter()
+        .filter(|f| f.fv().is_disjoint(assigned))
+        .cloned()
+        .collect()
+}
+
+/// The facts of `frame` which hold throughout a loop with the given guards
+fn loop_frame(frame: &[BExpr], guards: &[Guard]) -> Vec<BExpr> {
+    unassigned(frame, &guards_assigned(guards))
+}
+
+/// Conjoins `p` with the facts of the frame. When deriving the termination
+/// conditions of a loop, the frame holds the value of the variant at the
+/// start of the body. Nested loops which do not assign its variables keep it,
+/// which is needed as their invariants cannot mention the initial value.
+fn framed(p: BExpr, frame: &[BExpr]) -> BExpr {
+    frame
+        .iter()
+        .fold(p, |p, f| BExpr::logic(p, LogicOp::Land, f.clone()))
+}
+
+/// The variant of a loop must be non-negative whenever the body is entered
+fn bounded_below(i: &BExpr, gc: &Guard, v: &AExpr) -> BExpr {
+    BExpr::logic(
+        BExpr::logic(i.clone(), LogicOp::Land, gc.0.clone()),
+        LogicOp::Implies,
+        BExpr::rel(v.clone(), RelOp::Ge, AExprKind::Number(0).into()),
+    )
 }
 
 impl Guard {
     pub fn sp(&self, p: &BExpr) -> BExpr {
+        self.exits(&mut SpContext::default(), p).normal
+    }
+    fn exits(&self, cx: &mut SpContext, p: &BExpr) -> Exits {
         self.1
-            .sp(&BExpr::logic(self.0.clone(), LogicOp::Land, p.clone()))
+            .exits(cx, &BExpr::logic(self.0.clone(), LogicOp::Land, p.clone()))
     }
     pub fn vc(&self, r: &BExpr) -> Vec<BExpr> {
+        without_spans(self.located_vc_in(&mut SpContext::default(), r))
+    }
+    fn located_vc_in(&self, cx: &mut SpContext, r: &BExpr) -> LocatedVcs {
         self.1
-            .vc(&BExpr::logic(self.0.clone(), LogicOp::Land, r.clone()))
+            .located_vc_in(cx, &BExpr::logic(self.0.clone(), LogicOp::Land, r.clone()))
+    }
+}
+
+/// The postconditions a command must establish, depending on how control
+/// leaves it. This is the backward counterpart of [`Exits`]: `breaks` and
+/// `continues` are the postconditions of the innermost enclosing loop.
+#[derive(Debug, Clone)]
+struct Posts {
+    normal: BExpr,
+    breaks: BExpr,
+    continues: BExpr,
+    /// Facts which hold before the command, as none of their variables have
+    /// been assigned since. See [`framed`].
+    frame: Vec<BExpr>,
+}
+
+impl Posts {
+    /// The postconditions outside of any loop, where `break` and `continue`
+    /// cannot occur
+    fn normal(normal: BExpr) -> Posts {
+        Posts {
+            normal,
+            breaks: BExprKind::Bool(true).into(),
+            continues: BExprKind::Bool(true).into(),
+            frame: vec![],
+        }
+    }
+}
+
+impl Commands {
+    /// The weakest (liberal) precondition, guaranteeing that `q` holds if the
+    /// commands terminate. Loops are summarized by their invariant, and
+    /// annotated blocks by their precondition.
+    pub fn wp(&self, q: &BExpr) -> BExpr {
+        self.wp_in(&Posts::normal(q.clone()), &mut vec![])
+    }
+    /// The verification conditions derived backwards from the postcondition
+    /// `q`, using [`Commands::wp`]
+    pub fn wp_vc(&self, q: &BExpr) -> Vec<BExpr> {
+        without_spans(self.located_wp_vc(q))
+    }
+    /// The backward verification conditions paired with the span of the
+    /// command which gave rise to them
+    pub fn located_wp_vc(&self, q: &BExpr) -> LocatedVcs {
+        let mut conditions = vec![];
+        self.wp_in(&Posts::normal(q.clone()), &mut conditions);
+        conditions
+    }
+    fn wp_in(&self, posts: &Posts, conditions: &mut LocatedVcs) -> BExpr {
+        // NOTE: The frame before each command only keeps the facts whose
+        // variables are not assigned by the commands preceding it
+        let mut assigned = HashSet::new();
+        let frames = self
+            .0
+            .iter()
+            .map(|c| {
+                let frame = unassigned(&posts.frame, &assigned);
+                assigned.extend(command_assigned(c));
+                frame
+            })
+            .collect_vec();
+        // NOTE: The conditions of each command are collected separately, such
+        // that they are reported in the order of the program
+        let mut per_command = vec![];
+        let mut q = posts.normal.clone();
+        for (c, frame) in self.0.iter().zip(frames).rev() {
+            let mut located = vec![];
+            q = c.wp_in(
+                &Posts {
+                    normal: q,
+                    frame,
+                    ..posts.clone()
+                },
+                &mut located,
+            );
+            per_command.push(located);
+        }
+        conditions.extend(per_command.into_iter().rev().flatten());
+        q
+    }
+}
+
+impl Command {
+    pub fn wp(&self, q: &BExpr) -> BExpr {
+        self.wp_in(&Posts::normal(q.clone()), &mut vec![])
+    }
+    pub fn wp_vc(&self, q: &BExpr) -> Vec<BExpr> {
+        let mut conditions = vec![];
+        self.wp_in(&Posts::normal(q.clone()), &mut conditions);
+        without_spans(conditions)
+    }
+    fn wp_in(&self, posts: &Posts, conditions: &mut LocatedVcs) -> BExpr {
+        match &self.kind {
+            CommandKind::Assignment(Target::Array(arr, idx), e) => {
+                posts.normal.subst_store(arr, idx, e)
+            }
+            CommandKind::Assignment(x, e) => posts.normal.subst_var(x, e),
+            CommandKind::Skip => posts.normal.clone(),
+            CommandKind::If(guards) => guards
+                .iter()
+                .map(|gc| gc.wp_in(posts, conditions))
+                .reduce(|a, b| BExpr::logic(a, LogicOp::Land, b))
+                .unwrap(),
+            // NOTE: Without an invariant, the loop is treated as if it was
+            // annotated with `true`
+            CommandKind::Loop(guards) => loop_wp(
+                &BExprKind::Bool(true).into(),
+                guards,
+                posts,
+                self.span,
+                conditions,
+            ),
+            CommandKind::EnrichedLoop(i, variant, guards) => {
+                let pre = loop_wp(i, guards, posts, self.span, conditions);
+                if let Some(v) = variant {
+                    conditions.extend(termination_wp_vc(i, v, guards, self.span));
+                }
+                pre
+            }
+            CommandKind::Annotated(p, c, q) => {
+                let mut inner = vec![];
+                let pre = c.wp_in(&Posts::normal(q.clone()), &mut inner);
+                conditions.push(LocatedVc::partial(
+                    BExpr::logic(p.clone(), LogicOp::Implies, pre),
+                    self.span,
+                ));
+                conditions.extend(inner);
+                p.clone()
+            }
+            CommandKind::Assertion(a, c) => {
+                let mut inner = vec![];
+                let pre = c.wp_in(posts, &mut inner);
+                conditions.push(LocatedVc::partial(
+                    BExpr::logic(a.clone(), LogicOp::Implies, pre),
+                    self.span,
+                ));
+                conditions.extend(inner);
+                a.clone()
+            }
+            CommandKind::Break => posts.breaks.clone(),
+            CommandKind::Continue => posts.continues.clone(),
+        }
     }
 }
 
+/// The weakest precondition of a loop with invariant `i` is the invariant
+/// itself, together with the facts of the [frame](framed) which the loop does
+/// not assign. Every body must reestablish the invariant, or establish the
+/// postcondition of the loop when it reaches a `break`, and leaving the loop
+/// must establish the postcondition.
+fn loop_wp(
+    i: &BExpr,
+    guards: &[Guard],
+    posts: &Posts,
+    span: Option<SourceSpan>,
+    conditions: &mut LocatedVcs,
+) -> BExpr {
+    let frame = loop_frame(&posts.frame, guards);
+    let body_posts = Posts {
+        normal: i.clone(),
+        breaks: posts.normal.clone(),
+        continues: i.clone(),
+        frame: frame.clone(),
+    };
+    for gc in guards {
+        let mut inner = vec![];
+        let pre = gc.1.wp_in(&body_posts, &mut inner);
+        conditions.push(LocatedVc::partial(
+            BExpr::logic(
+                BExpr::logic(i.clone(), LogicOp::Land, gc.0.clone()),
+                LogicOp::Implies,
+                pre,
+            ),
+            span,
+        ));
+        conditions.extend(inner);
+    }
+    conditions.push(LocatedVc::partial(
+        BExpr::logic(
+            BExpr::logic(framed(i.clone(), &frame), LogicOp::Land, done(guards)),
+            LogicOp::Implies,
+            posts.normal.clone(),
+        ),
+        span,
+    ));
+    framed(i.clone(), &frame)
+}
+
+/// The conditions for a loop with invariant `i` to terminate: the variant `v`
+/// is non-negative whenever a body is entered, and every body which does not
+/// `break` out of the loop decreases it.
+fn termination_wp_vc(
+    i: &BExpr,
+    v: &AExpr,
+    guards: &[Guard],
+    span: Option<SourceSpan>,
+) -> LocatedVcs {
+    let x = variant_name(i, v, guards);
+    let before: AExpr = AExprKind::Reference(Target::Variable(x.clone())).into();
+    let decreased = BExpr::rel(v.clone(), RelOp::Lt, before.clone());
+    let initial = BExpr::rel(v.clone(), RelOp::Eq, before.clone());
+    let body_posts = Posts {
+        normal: decreased.clone(),
+        breaks: BExprKind::Bool(true).into(),
+        continues: decreased,
+        frame: vec![initial.clone()],
+    };
+    let forall = |b: BExpr| -> BExpr {
+        BExprKind::Quantified(Quantifier::Forall, Target::Variable(x.clone()), Box::new(b)).into()
+    };
+
+    let mut conditions = vec![];
+    for gc in guards {
+        conditions.push(LocatedVc::termination(bounded_below(i, gc, v), span));
+
+        let mut inner = vec![];
+        let pre = gc.1.wp_in(&body_posts, &mut inner);
+        conditions.push(LocatedVc::termination(
+            forall(BExpr::logic(
+                BExpr::logic(
+                    BExpr::logic(i.clone(), LogicOp::Land, gc.0.clone()),
+                    LogicOp::Land,
+                    initial.clone(),
+                ),
+                LogicOp::Implies,
+                pre,
+            )),
+            span,
+        ));
+        // NOTE: The conditions of nested loops which do not mention the
+        // initial value of the variant were already produced for partial
+        // correctness, and so were those for the termination of the nested
+        // loops themselves
+        conditions.extend(
+            inner
+                .into_iter()
+                .filter(|vc| {
+                    vc.kind == VcKind::PartialCorrectness
+                        && vc.condition.fv().contains(&Target::Variable(x.clone()))
+                })
+                .map(|vc| LocatedVc::termination(forall(vc.condition), vc.span)),
+        );
+    }
+    conditions
+}
+
+impl Guard {
+    pub fn wp(&self, q: &BExpr) -> BExpr {
+        self.wp_in(&Posts::normal(q.clone()), &mut vec![])
+    }
+    fn wp_in(&self, posts: &Posts, conditions: &mut LocatedVcs) -> BExpr {
+        BExpr::logic(
+            self.0.clone(),
+            LogicOp::Implies,
+            self.1.wp_in(posts, conditions),
+        )
+    }
+}
+
+/// A verification condition which does not hold, reported against the command
+/// in the source which gave rise to it. Attach the program with
+/// [`miette::Report::with_source_code`] to render it.
+#[derive(Debug, Clone, PartialEq, Eq, Error, Diagnostic, Serialize, Deserialize)]
+#[error("The verification condition {condition} does not hold")]
+pub struct VcDiagnostic {
+    pub condition: String,
+    #[label("The condition is required by this command")]
+    pub span: Option<SourceSpan>,
+    /// A memory in which the condition does not hold
+    #[help]
+    pub counterexample: Option<String>,
+}
+
 impl BExpr {
     pub fn subst_var<T>(&self, t: &Target<T>, x: &AExpr) -> BExpr {
-        match self {
-            BExpr::Bool(b) => BExpr::Bool(*b),
-            BExpr::Rel(l, op, r) => BExpr::Rel(l.subst_var(t, x), *op, r.subst_var(t, x)),
-            BExpr::Logic(l, op, r) => BExpr::logic(l.subst_var(t, x), *op, r.subst_var(t, x)),
-            BExpr::Not(e) => BExpr::Not(Box::new(e.subst_var(t, x))),
-            BExpr::Quantified(q, v, e) => {
+        match &self.kind {
+            BExprKind::Bool(b) => BExprKind::Bool(*b).into(),
+            BExprKind::Rel(l, op, r) => {
+                BExprKind::Rel(l.subst_var(t, x), *op, r.subst_var(t, x)).into()
+            }
+            BExprKind::Logic(l, op, r) => BExpr::logic(l.subst_var(t, x), *op, r.subst_var(t, x)),
+            BExprKind::Not(e) => BExprKind::Not(Box::new(e.subst_var(t, x))).into(),
+            BExprKind::Quantified(q, v, e) => {
                 if v.same_name(t) {
                     self.clone()
                 } else {
-                    BExpr::Quantified(*q, v.clone(), Box::new(e.subst_var(t, x)))
+                    BExprKind::Quantified(*q, v.clone(), Box::new(e.subst_var(t, x))).into()
                 }
             }
         }
@@ -147,68 +800,355 @@ impl BExpr {
     pub fn simplify(&self) -> BExpr {
         match self
             .semantics(&Default::default())
-            .map(BExpr::Bool)
+            .map(|b| BExprKind::Bool(b).into())
             .unwrap_or_else(|_| self.clone())
+            .kind
         {
-            BExpr::Bool(b) => BExpr::Bool(b),
-            BExpr::Rel(l, op, r) => BExpr::Rel(l.simplify(), op, r.simplify()),
-            BExpr::Logic(l, op, r) => {
+            BExprKind::Bool(b) => BExprKind::Bool(b).into(),
+            BExprKind::Rel(l, op, r) => BExprKind::Rel(l.simplify(), op, r.simplify()).into(),
+            BExprKind::Logic(l, op, r) => {
                 let l = l.simplify();
                 let r = r.simplify();
 
-                match (l, op, r) {
-                    (BExpr::Bool(true), LogicOp::And, x) | (x, LogicOp::And, BExpr::Bool(true)) => {
-                        x
-                    }
-                    (BExpr::Bool(false), LogicOp::And, _)
-                    | (_, LogicOp::And, BExpr::Bool(false)) => BExpr::Bool(false),
-                    (BExpr::Bool(false), LogicOp::Or, x) | (x, LogicOp::Or, BExpr::Bool(false)) => {
-                        x
-                    }
-                    (BExpr::Bool(true), LogicOp::Or, _) | (_, LogicOp::Or, BExpr::Bool(true)) => {
-                        BExpr::Bool(true)
-                    }
-                    (l, op, r) => BExpr::logic(l, op, r),
+                match (&l.kind, op, &r.kind) {
+                    (BExprKind::Bool(true), LogicOp::And, _) => r,
+                    (_, LogicOp::And, BExprKind::Bool(true)) => l,
+                    (BExprKind::Bool(false), LogicOp::And, _)
+                    | (_, LogicOp::And, BExprKind::Bool(false)) => BExprKind::Bool(false).into(),
+                    (BExprKind::Bool(false), LogicOp::Or, _) => r,
+                    (_, LogicOp::Or, BExprKind::Bool(false)) => l,
+                    (BExprKind::Bool(true), LogicOp::Or, _)
+                    | (_, LogicOp::Or, BExprKind::Bool(true)) => BExprKind::Bool(true).into(),
+                    _ => BExpr::logic(l, op, r),
                 }
             }
-            BExpr::Not(x) => {
+            BExprKind::Not(x) => {
                 let x = x.simplify();
-                match x {
-                    BExpr::Bool(b) => BExpr::Bool(!b),
-                    x => BExpr::Not(Box::new(x)),
+                match x.kind {
+                    BExprKind::Bool(b) => BExprKind::Bool(!b).into(),
+                    _ => BExprKind::Not(Box::new(x)).into(),
                 }
             }
-            BExpr::Quantified(_, _, _) => todo!(),
+            BExprKind::Quantified(q, x, b) => quantify(q, &x, b.simplify()),
+        }
+    }
+
+    /// The predicate with `arr` replaced by the array where the element at
+    /// `idx` is `val`, as required by the weakest precondition of
+    /// `arr[idx] := val`
+    pub fn subst_store(&self, arr: &Array, idx: &AExpr, val: &AExpr) -> BExpr {
+        Store::new(arr, idx, val).bexpr(self)
+    }
+
+    /// Whether any quantifier in the predicate binds one of `targets`, such
+    /// that substituting an expression mentioning them would capture it
+    fn binds_any(&self, targets: &HashSet<Target>) -> bool {
+        match &self.kind {
+            BExprKind::Bool(_) | BExprKind::Rel(_, _, _) => false,
+            BExprKind::Logic(l, _, r) => l.binds_any(targets) || r.binds_any(targets),
+            BExprKind::Not(b) => b.binds_any(targets),
+            BExprKind::Quantified(_, x, b) => targets.contains(x) || b.binds_any(targets),
+        }
+    }
+}
+
+/// Builds `q x :: b` from an already simplified body `b`, dropping the binder
+/// if `x` is unused, eliminating it if `b` fixes its value, and otherwise
+/// pushing it as far inward as possible
+fn quantify(q: Quantifier, x: &Target, b: BExpr) -> BExpr {
+    if !b.fv().contains(x) {
+        return b;
+    }
+
+    if let Target::Variable(v) = x {
+        let defining = match (q, &b.kind) {
+            (Quantifier::Exists, _) => one_point(v, &b),
+            (Quantifier::Forall, BExprKind::Logic(l, LogicOp::Implies, r)) => {
+                one_point(v, l).map(|(e, l)| (e, implies(l, (**r).clone())))
+            }
+            _ => None,
+        };
+        if let Some((e, rest)) = defining {
+            if !rest.binds_any(&e.fv()) {
+                return rest.subst_var(x, &e).simplify();
+            }
+        }
+    }
+
+    let mentions = |p: &BExpr| p.fv().contains(x);
+    match (q, b.kind) {
+        (Quantifier::Exists, BExprKind::Logic(l, op @ (LogicOp::Or | LogicOp::Lor), r))
+        | (Quantifier::Forall, BExprKind::Logic(l, op @ (LogicOp::And | LogicOp::Land), r)) => {
+            BExpr::logic(quantify(q, x, *l), op, quantify(q, x, *r))
+        }
+        (Quantifier::Exists, BExprKind::Logic(l, op @ (LogicOp::And | LogicOp::Land), r))
+        | (Quantifier::Forall, BExprKind::Logic(l, op @ (LogicOp::Or | LogicOp::Lor), r))
+            if !mentions(&l) || !mentions(&r) =>
+        {
+            if mentions(&l) {
+                BExpr::logic(quantify(q, x, *l), op, *r)
+            } else {
+                BExpr::logic(*l, op, quantify(q, x, *r))
+            }
+        }
+        (Quantifier::Forall, BExprKind::Logic(l, LogicOp::Implies, r)) if !mentions(&l) => {
+            BExpr::logic(*l, LogicOp::Implies, quantify(q, x, *r))
+        }
+        (q, b) => BExprKind::Quantified(q, x.clone(), Box::new(b.into())).into(),
+    }
+}
+
+/// Finds a conjunct `x = e` in `p`, where `e` does not mention `x`, and
+/// returns `e` together with `p` without that conjunct
+fn one_point(x: &Variable, p: &BExpr) -> Option<(AExpr, BExpr)> {
+    let target = Target::Variable(x.clone());
+    match &p.kind {
+        BExprKind::Rel(l, RelOp::Eq, r) => {
+            let is_x =
+                |e: &AExpr| matches!(&e.kind, AExprKind::Reference(Target::Variable(y)) if y == x);
+            let e = if is_x(l) {
+                r
+            } else if is_x(r) {
+                l
+            } else {
+                return None;
+            };
+            if e.fv().contains(&target) {
+                return None;
+            }
+            Some((e.clone(), BExprKind::Bool(true).into()))
+        }
+        BExprKind::Logic(l, op @ (LogicOp::And | LogicOp::Land), r) => {
+            if let Some((e, l)) = one_point(x, l) {
+                Some((e, conjoin(l, *op, (**r).clone())))
+            } else {
+                let (e, r) = one_point(x, r)?;
+                Some((e, conjoin((**l).clone(), *op, r)))
+            }
         }
+        _ => None,
+    }
+}
+
+fn conjoin(l: BExpr, op: LogicOp, r: BExpr) -> BExpr {
+    match (&l.kind, &r.kind) {
+        (BExprKind::Bool(true), _) => r,
+        (_, BExprKind::Bool(true)) => l,
+        _ => BExpr::logic(l, op, r),
     }
 }
 
+fn implies(l: BExpr, r: BExpr) -> BExpr {
+    match l.kind {
+        BExprKind::Bool(true) => r,
+        _ => BExpr::logic(l, LogicOp::Implies, r),
+    }
+}
+
+/// The array `arr` with the element at `idx` replaced by `val`. A read
+/// `arr[j]` of it is `val` if `j = idx` and `arr[j]` otherwise, so rather than
+/// extending the syntax with stores, substituting one for `arr` splits every
+/// expression reading `arr` into cases. Cases which are decided by the
+/// syntax of the indices, such as `j` being `idx`, are not split.
+struct Store<'a> {
+    arr: &'a Array,
+    idx: &'a AExpr,
+    val: &'a AExpr,
+}
+
+/// Values paired with the conjunction of conditions under which they apply.
+/// The conditions of the cases are mutually exclusive and exhaustive.
+type Cases<T> = Vec<(Vec<BExpr>, T)>;
+
+impl<'a> Store<'a> {
+    fn new(arr: &'a Array, idx: &'a AExpr, val: &'a AExpr) -> Self {
+        Store { arr, idx, val }
+    }
+
+    fn bexpr(&self, b: &BExpr) -> BExpr {
+        match &b.kind {
+            BExprKind::Bool(b) => BExprKind::Bool(*b).into(),
+            BExprKind::Rel(l, op, r) => guarded(
+                self.aexpr(l)
+                    .into_iter()
+                    .cartesian_product(self.aexpr(r))
+                    .map(|((lc, l), (rc, r))| (concat(lc, rc), BExpr::rel(l, *op, r)))
+                    .collect(),
+            ),
+            BExprKind::Logic(l, op, r) => BExpr::logic(self.bexpr(l), *op, self.bexpr(r)),
+            BExprKind::Not(b) => BExprKind::Not(Box::new(self.bexpr(b))).into(),
+            BExprKind::Quantified(q, x, b) => {
+                BExprKind::Quantified(*q, x.clone(), Box::new(self.bexpr(b))).into()
+            }
+        }
+    }
+
+    fn aexpr(&self, e: &AExpr) -> Cases<AExpr> {
+        match &e.kind {
+            AExprKind::Number(_) | AExprKind::Reference(Target::Variable(_)) => {
+                vec![(vec![], e.clone())]
+            }
+            AExprKind::Reference(Target::Array(arr, j)) => {
+                let read = |j: AExpr| -> AExpr {
+                    AExprKind::Reference(Target::Array(arr.clone(), Box::new(j))).into()
+                };
+                let mut cases = vec![];
+                for (c, j) in self.aexpr(j) {
+                    if arr != self.arr {
+                        cases.push((c, read(j)));
+                    } else if &j == self.idx {
+                        cases.push((c, self.val.clone()));
+                    } else if matches!((&j.kind, &self.idx.kind), (AExprKind::Number(a), AExprKind::Number(b)) if a != b)
+                    {
+                        cases.push((c, read(j)));
+                    } else {
+                        let hit = BExpr::rel(j.clone(), RelOp::Eq, self.idx.clone());
+                        let miss = BExpr::rel(j.clone(), RelOp::Ne, self.idx.clone());
+                        cases.push((concat(c.clone(), vec![hit]), self.val.clone()));
+                        cases.push((concat(c, vec![miss]), read(j)));
+                    }
+                }
+                cases
+            }
+            AExprKind::Binary(l, op, r) => self.binary(l, r, |l, r| AExpr::binary(l, *op, r)),
+            AExprKind::Minus(e) => self.unary(e, |e| AExprKind::Minus(Box::new(e)).into()),
+            AExprKind::Function(f) => match f {
+                Function::Division(a, b) => self.binary(a, b, |a, b| {
+                    AExprKind::Function(Function::Division(Box::new(a), Box::new(b))).into()
+                }),
+                Function::Min(a, b) => self.binary(a, b, |a, b| {
+                    AExprKind::Function(Function::Min(Box::new(a), Box::new(b))).into()
+                }),
+                Function::Max(a, b) => self.binary(a, b, |a, b| {
+                    AExprKind::Function(Function::Max(Box::new(a), Box::new(b))).into()
+                }),
+                Function::Fac(n) => self.unary(n, |n| {
+                    AExprKind::Function(Function::Fac(Box::new(n))).into()
+                }),
+                Function::Fib(n) => self.unary(n, |n| {
+                    AExprKind::Function(Function::Fib(Box::new(n))).into()
+                }),
+                // NOTE: The store has one element less equal to the old value
+                // at `idx`, and one more equal to `val`
+                Function::Count(arr, x) if arr == self.arr => {
+                    let old: AExpr = AExprKind::Reference(Target::Array(
+                        arr.clone(),
+                        Box::new(self.idx.clone()),
+                    ))
+                    .into();
+                    let mut cases = vec![];
+                    for (c, x) in self.aexpr(x) {
+                        let count: AExpr =
+                            AExprKind::Function(Function::Count(arr.clone(), Box::new(x.clone())))
+                                .into();
+                        for (removed, added) in
+                            [(true, true), (true, false), (false, true), (false, false)]
+                        {
+                            let is = |e: &AExpr, yes: bool| {
+                                let op = if yes { RelOp::Eq } else { RelOp::Ne };
+                                BExpr::rel(e.clone(), op, x.clone())
+                            };
+                            let count = match (removed, added) {
+                                (true, false) => AExpr::binary(
+                                    count.clone(),
+                                    AOp::Minus,
+                                    AExprKind::Number(1).into(),
+                                ),
+                                (false, true) => AExpr::binary(
+                                    count.clone(),
+                                    AOp::Plus,
+                                    AExprKind::Number(1).into(),
+                                ),
+                                _ => count.clone(),
+                            };
+                            cases.push((
+                                concat(c.clone(), vec![is(&old, removed), is(self.val, added)]),
+                                count,
+                            ));
+                        }
+                    }
+                    cases
+                }
+                Function::Count(arr, x) => self.unary(x, |x| {
+                    AExprKind::Function(Function::Count(arr.clone(), Box::new(x))).into()
+                }),
+                Function::LogicalCount(arr, x) => self.unary(x, |x| {
+                    AExprKind::Function(Function::LogicalCount(arr.clone(), Box::new(x))).into()
+                }),
+                Function::Length(_) | Function::LogicalLength(_) => vec![(vec![], e.clone())],
+            },
+        }
+    }
+
+    fn unary(&self, e: &AExpr, f: impl Fn(AExpr) -> AExpr) -> Cases<AExpr> {
+        self.aexpr(e).into_iter().map(|(c, e)| (c, f(e))).collect()
+    }
+
+    fn binary(&self, l: &AExpr, r: &AExpr, f: impl Fn(AExpr, AExpr) -> AExpr) -> Cases<AExpr> {
+        self.aexpr(l)
+            .into_iter()
+            .cartesian_product(self.aexpr(r))
+            .map(|((lc, l), (rc, r))| (concat(lc, rc), f(l, r)))
+            .collect()
+    }
+}
+
+fn concat(mut a: Vec<BExpr>, b: Vec<BExpr>) -> Vec<BExpr> {
+    a.extend(b);
+    a
+}
+
+/// The conjunction of `c ==> p` for every case, or just `p` if there is only
+/// a single unconditional case
+fn guarded(cases: Cases<BExpr>) -> BExpr {
+    cases
+        .into_iter()
+        .map(|(c, p)| {
+            match c
+                .into_iter()
+                .reduce(|a, b| BExpr::logic(a, LogicOp::Land, b))
+            {
+                Some(c) => BExpr::logic(c, LogicOp::Implies, p),
+                None => p,
+            }
+        })
+        .reduce(|a, b| BExpr::logic(a, LogicOp::Land, b))
+        .unwrap_or(BExprKind::Bool(true).into())
+}
+
 impl AExpr {
     fn subst_var<T>(&self, t: &Target<T>, x: &AExpr) -> AExpr {
-        match self {
-            AExpr::Number(n) => AExpr::Number(*n),
-            AExpr::Reference(v) if v.same_name(t) => x.clone(),
-            AExpr::Reference(v) => AExpr::Reference(v.clone()),
-            AExpr::Binary(l, op, r) => AExpr::binary(l.subst_var(t, x), *op, r.subst_var(t, x)),
-            AExpr::Minus(e) => AExpr::Minus(Box::new(e.subst_var(t, x))),
-            AExpr::Function(f) => AExpr::Function(f.subst_var(t, x)),
+        match &self.kind {
+            AExprKind::Number(n) => AExprKind::Number(*n).into(),
+            AExprKind::Reference(v @ Target::Variable(_)) if v.same_name(t) => x.clone(),
+            AExprKind::Reference(Target::Variable(v)) => {
+                AExprKind::Reference(Target::Variable(v.clone())).into()
+            }
+            AExprKind::Reference(Target::Array(arr, idx)) => {
+                AExprKind::Reference(Target::Array(arr.clone(), Box::new(idx.subst_var(t, x))))
+                    .into()
+            }
+            AExprKind::Binary(l, op, r) => AExpr::binary(l.subst_var(t, x), *op, r.subst_var(t, x)),
+            AExprKind::Minus(e) => AExprKind::Minus(Box::new(e.subst_var(t, x))).into(),
+            AExprKind::Function(f) => AExprKind::Function(f.subst_var(t, x)).into(),
         }
     }
 
     pub fn simplify(&self) -> AExpr {
         match self
             .semantics(&Default::default())
-            .map(AExpr::Number)
+            .map(|n| AExprKind::Number(n).into())
             .unwrap_or_else(|_| self.clone())
+            .kind
         {
-            AExpr::Number(n) => AExpr::Number(n),
-            AExpr::Reference(v) => AExpr::Reference(v.simplify()),
-            AExpr::Binary(l, op, r) => AExpr::binary(l.simplify(), op, r.simplify()),
-            AExpr::Minus(e) => match &*e {
-                AExpr::Minus(inner) => inner.simplify(),
-                _ => AExpr::Minus(Box::new(e.simplify())),
+            AExprKind::Number(n) => AExprKind::Number(n).into(),
+            AExprKind::Reference(v) => AExprKind::Reference(v.simplify()).into(),
+            AExprKind::Binary(l, op, r) => AExpr::binary(l.simplify(), op, r.simplify()),
+            AExprKind::Minus(e) => match &e.kind {
+                AExprKind::Minus(inner) => inner.simplify(),
+                _ => AExprKind::Minus(Box::new(e.simplify())).into(),
             },
-            AExpr::Function(_) => self.clone(),
+            AExprKind::Function(_) => self.clone(),
         }
     }
 }
@@ -247,3 +1187,220 @@ impl Target<Box<AExpr>> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::{variant_name, VcKind};
+    use crate::{
+        ast::{Array, CommandKind, Variable},
+        interpreter::InterpreterMemory,
+        parse::{parse_commands, parse_predicate},
+    };
+
+    #[test]
+    fn quantifiers_are_simplified() -> miette::Result<()> {
+        for (p, expected) in [
+            ("exists _f :: _f = x + 1 & _f > 0", "x + 1 > 0"),
+            ("exists _f :: y > 0", "y > 0"),
+            ("forall _i :: _i = 3 ==> _i < n", "3 < n"),
+            (
+                "exists _f :: _f > 0 & y = 2",
+                "(exists _f :: _f > 0) & y = 2",
+            ),
+            (
+                "forall _i :: (_i > 0 && _i < n) || n = 0",
+                "((forall _i :: _i > 0) && (forall _i :: _i < n)) || n = 0",
+            ),
+            (
+                "exists _g :: _g = _h & (exists _h :: _g < _h)",
+                "exists _g :: _g = _h & (exists _h :: _g < _h)",
+            ),
+        ] {
+            assert_eq!(
+                parse_predicate(p)?.simplify(),
+                parse_predicate(expected)?,
+                "{p}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn backward_conditions_of_a_loop_hold() -> miette::Result<()> {
+        let cmds = parse_commands(
+            "{ n >= 0 }
+            i := 0;
+            do { i <= n } i < n -> i := i + 1 od
+            { i = n }",
+        )?;
+        let vcs = cmds.wp_vc(&crate::ast::BExprKind::Bool(true).into());
+        // NOTE: One for the block, one for the body and one for leaving the
+        // loop
+        assert_eq!(vcs.len(), 3);
+
+        for i in -3..=3 {
+            for n in -3..=3 {
+                let mut m = InterpreterMemory::default();
+                m.variables.insert(Variable("i".to_string()), i);
+                m.variables.insert(Variable("n".to_string()), n);
+                for vc in &vcs {
+                    assert_eq!(vc.semantics(&m), Ok(true), "{vc} with i = {i}, n = {n}");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn array_assignments_only_change_one_element() -> miette::Result<()> {
+        let cmds = parse_commands("A[i] := A[j] + 1")?;
+        let q = parse_predicate("A[0] > A[1] & count(A, 2) = 1")?;
+        let wp = cmds.wp(&q);
+        let sp = cmds.sp(&parse_predicate("A[0] = A[1]")?).simplify();
+
+        for i in 0..2 {
+            for j in 0..2 {
+                for a in [[0, 0], [1, 2], [2, 1]] {
+                    let mut m = InterpreterMemory::default();
+                    m.variables.insert(Variable("i".to_string()), i);
+                    m.variables.insert(Variable("j".to_string()), j);
+                    let mut after = m.clone();
+                    let mut arr = a.to_vec();
+                    m.arrays.insert(Array("A".to_string()), arr.clone());
+                    arr[i as usize] = arr[j as usize] + 1;
+                    after.arrays.insert(Array("A".to_string()), arr);
+
+                    assert_eq!(wp.semantics(&m), q.semantics(&after), "{wp} with {m:?}");
+                    if a[0] == a[1] {
+                        assert_eq!(
+                            sp.bounded_semantics(&after, &(-3..=3), &mut 10_000),
+                            Ok(true),
+                            "{sp} with {after:?}"
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn variants_give_termination_conditions() -> miette::Result<()> {
+        let holds = |vc: &crate::ast::BExpr| {
+            (-3..=3).cartesian_product(-3..=3).all(|(i, n)| {
+                let mut m = InterpreterMemory::default();
+                m.variables.insert(Variable("i".to_string()), i);
+                m.variables.insert(Variable("n".to_string()), n);
+                vc.bounded_semantics(&m, &(-8..=8), &mut 10_000) == Ok(true)
+            })
+        };
+
+        for (variant, terminates) in [("n - i", true), ("i", false)] {
+            let cmds = parse_commands(&format!(
+                "{{ n >= 0 }}
+                i := 0;
+                do {{ 0 <= i & i <= n }} {{ {variant} }} i < n -> i := i + 1 od
+                {{ i = n }}"
+            ))?;
+            assert_eq!(parse_commands(&cmds.to_string())?, cmds);
+
+            for vcs in [
+                cmds.located_vc(&crate::ast::BExprKind::Bool(true).into()),
+                cmds.located_wp_vc(&crate::ast::BExprKind::Bool(true).into()),
+            ] {
+                let (termination, partial): (Vec<_>, Vec<_>) =
+                    vcs.iter().partition(|vc| vc.kind == VcKind::Termination);
+                assert_eq!(termination.len(), 2);
+                assert!(partial.iter().all(|vc| holds(&vc.condition)));
+                assert_eq!(
+                    termination.iter().all(|vc| holds(&vc.condition)),
+                    terminates,
+                    "{}",
+                    termination.iter().map(|vc| &vc.condition).format(", ")
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn nested_loops_keep_the_initial_variant() -> miette::Result<()> {
+        let holds = |vc: &crate::ast::BExpr| {
+            (0..3)
+                .map(|_| -3..=3)
+                .multi_cartesian_product()
+                .all(|values| {
+                    let mut m = InterpreterMemory::default();
+                    for (x, v) in ["i", "j", "n"].into_iter().zip(values) {
+                        m.variables.insert(Variable(x.to_string()), v);
+                    }
+                    vc.bounded_semantics(&m, &(-8..=8), &mut 100_000) == Ok(true)
+                })
+        };
+
+        let cmds = parse_commands(
+            "{ n >= 0 }
+            i := 0;
+            do { 0 <= i & i <= n } { n - i } i < n ->
+                j := 0;
+                do { 0 <= j & j <= i & i < n } { i - j } j < i -> j := j + 1 od;
+                i := i + 1
+            od
+            { i = n }",
+        )?;
+
+        for vcs in [
+            cmds.located_vc(&crate::ast::BExprKind::Bool(true).into()),
+            cmds.located_wp_vc(&crate::ast::BExprKind::Bool(true).into()),
+        ] {
+            assert!(
+                vcs.iter().any(|vc| vc.kind == VcKind::Termination),
+                "{}",
+                vcs.iter().map(|vc| &vc.condition).format(", ")
+            );
+            for vc in &vcs {
+                assert!(holds(&vc.condition), "{:?}: {}", vc.kind, vc.condition);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn variant_names_avoid_nested_loops() -> miette::Result<()> {
+        let cmds = parse_commands(
+            "{ true }
+            do { true } { n - i } i < n ->
+                j := 0;
+                do { true } { i - j } j < i -> j := j + 1 od;
+                i := i + 1
+            od
+            { true }",
+        )?;
+        let CommandKind::Annotated(_, cmds, _) = &cmds.0[0].kind else {
+            panic!("`{cmds}` should be annotated");
+        };
+        let CommandKind::EnrichedLoop(i, Some(v), guards) = &cmds.0[0].kind else {
+            panic!("`{}` should be a loop with a variant", cmds.0[0]);
+        };
+        let CommandKind::EnrichedLoop(inner_i, Some(inner_v), inner_guards) =
+            &guards[0].1 .0[1].kind
+        else {
+            panic!("`{}` should be a loop with a variant", guards[0].1);
+        };
+
+        let inner = variant_name(inner_i, inner_v, inner_guards);
+        assert_eq!(inner, Variable("_variant".to_string()));
+        assert_eq!(
+            variant_name(i, v, guards),
+            Variable("_variant1".to_string())
+        );
+
+        Ok(())
+    }
+}
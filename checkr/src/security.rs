@@ -1,12 +1,13 @@
 use std::{collections::HashSet, fmt::Display};
 
 use itertools::{chain, Itertools};
+use miette::Diagnostic;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 use crate::{
-    ast::{Command, Commands, Guard, Target},
-    gcl,
-    parse::ParseError,
+    ast::{Command, CommandKind, Commands, Guard, Target},
+    parse::{self, SourceSpan},
     sign::Memory,
 };
 
@@ -43,19 +44,30 @@ where
     }
 }
 
+/// A flow together with the span of the command which caused it
+type LocatedFlow = (Flow<Target>, Option<SourceSpan>);
+
 impl Commands {
     pub fn flows(&self) -> HashSet<Flow<Target>> {
         self.sec(&Default::default())
+            .into_iter()
+            .map(|(flow, _)| flow)
+            .collect()
+    }
+    /// The flows of the program paired with the span of the command causing
+    /// them. A flow caused by multiple commands is included once for each.
+    pub fn located_flows(&self) -> Vec<(Flow<Target>, Option<SourceSpan>)> {
+        self.sec(&Default::default()).into_iter().sorted().collect()
     }
-    fn sec(&self, implicit: &HashSet<Target>) -> HashSet<Flow<Target>> {
+    fn sec(&self, implicit: &HashSet<Target>) -> HashSet<LocatedFlow> {
         self.0.iter().flat_map(|c| c.sec(implicit)).collect()
     }
 }
 
 impl Command {
-    fn sec(&self, implicit: &HashSet<Target>) -> HashSet<Flow<Target>> {
-        match self {
-            Command::Assignment(t, a) => chain!(
+    fn sec(&self, implicit: &HashSet<Target>) -> HashSet<LocatedFlow> {
+        match &self.kind {
+            CommandKind::Assignment(t, a) => chain!(
                 implicit.iter().cloned(),
                 match t {
                     Target::Variable(_) => Default::default(),
@@ -63,13 +75,16 @@ impl Command {
                 },
                 a.fv()
             )
-            .map(|i| Flow {
-                from: i,
-                into: t.clone().unit(),
+            .map(|i| {
+                let flow = Flow {
+                    from: i,
+                    into: t.clone().unit(),
+                };
+                (flow, self.span)
             })
             .collect(),
-            Command::Skip => HashSet::default(),
-            Command::If(c) | Command::Loop(c) | Command::EnrichedLoop(_, c) => {
+            CommandKind::Skip => HashSet::default(),
+            CommandKind::If(c) | CommandKind::Loop(c) | CommandKind::EnrichedLoop(_, _, c) => {
                 c.iter()
                     .fold(
                         (implicit.clone(), HashSet::default()),
@@ -84,21 +99,36 @@ impl Command {
                     )
                     .1
             }
-            Command::Annotated(_, c, _) => c.sec(implicit),
-            Command::Break => HashSet::default(),
-            Command::Continue => HashSet::default(),
+            CommandKind::Annotated(_, c, _) => c.sec(implicit),
+            CommandKind::Assertion(_, c) => c.sec(implicit),
+            CommandKind::Break => HashSet::default(),
+            CommandKind::Continue => HashSet::default(),
         }
     }
 }
 
 impl Guard {
-    fn sec2(&self, implicit: &HashSet<Target>) -> (HashSet<Target>, HashSet<Flow<Target>>) {
+    fn sec2(&self, implicit: &HashSet<Target>) -> (HashSet<Target>, HashSet<LocatedFlow>) {
         let implicit = implicit.iter().cloned().chain(self.0.fv()).collect();
         let flows = self.1.sec(&implicit);
         (implicit, flows)
     }
 }
 
+/// A flow violating the security lattice, reported against the command in the
+/// source which caused it. Attach the program with
+/// [`miette::Report::with_source_code`] to render it.
+#[derive(
+    Debug, Error, Diagnostic, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[error("Illegal flow {flow}")]
+#[diagnostic(help("The security lattice does not allow information to flow from {} to {}", flow.from, flow.into))]
+pub struct FlowDiagnostic {
+    pub flow: Flow<Target>,
+    #[label("This command causes the flow {flow}")]
+    pub span: Option<SourceSpan>,
+}
+
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct SecurityClass(pub String);
 
@@ -152,9 +182,7 @@ impl SecurityLattice {
         SecurityLattice { allowed }
     }
     pub fn parse(src: &str) -> color_eyre::Result<SecurityLattice> {
-        let flows = gcl::SecurityLatticeParser::new()
-            .parse(src)
-            .map_err(|e| ParseError::new(src, e))?;
+        let flows = parse::parse_security_lattice(src)?;
 
         Ok(Self::new(&flows))
     }
@@ -190,6 +218,9 @@ pub struct SecurityAnalysisOutput {
     pub actual: Vec<Flow<Target>>,
     pub allowed: Vec<Flow<Target>>,
     pub violations: Vec<Flow<Target>>,
+    /// The violations reported against each command causing them
+    #[serde(default)]
+    pub diagnostics: Vec<FlowDiagnostic>,
 }
 
 impl SecurityAnalysisOutput {
@@ -202,16 +233,69 @@ impl SecurityAnalysisOutput {
         let actual = cmds.flows();
         let violations = actual
             .iter()
-            .cloned()
             .filter(|flow| !allowed.contains(flow))
+            .cloned()
             .sorted()
             .dedup()
             .collect();
+        let diagnostics = cmds
+            .located_flows()
+            .into_iter()
+            .filter(|(flow, _)| !allowed.contains(flow))
+            .map(|(flow, span)| FlowDiagnostic { flow, span })
+            .collect();
 
         Self {
             actual: actual.into_iter().sorted().collect(),
             allowed,
             violations,
+            diagnostics,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        parse::parse_commands,
+        security::{Flow, SecurityClass, SecurityLattice},
+        sign::Memory,
+    };
+
+    use super::SecurityAnalysisOutput;
+
+    #[test]
+    fn illegal_flows_point_to_their_command() -> miette::Result<()> {
+        let src = "y := x;\nif x > 0 -> z := 1 fi";
+        let cmds = parse_commands(src)?;
+        let class = |c: &str| SecurityClass(c.to_string());
+        let classification = Memory::from_targets(
+            cmds.fv(),
+            |x| class(if x.0 == "x" { "Private" } else { "Public" }),
+            |_| class("Public"),
+        );
+        let lattice = SecurityLattice::new(&[Flow {
+            from: class("Public"),
+            into: class("Private"),
+        }]);
+
+        let output = SecurityAnalysisOutput::run(&classification, &lattice, &cmds);
+        let reported = output
+            .diagnostics
+            .iter()
+            .map(|d| {
+                let span = d.span.unwrap();
+                (
+                    d.flow.from.name(),
+                    d.flow.into.name(),
+                    &src[span.offset()..span.end()],
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(reported, [("x", "y", "y := x"), ("x", "z", "z := 1")]);
+
+        Ok(())
+    }
+}
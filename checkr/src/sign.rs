@@ -7,8 +7,8 @@ use itertools::{chain, Either, Itertools};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    analysis::{Direction, MonotoneFramework},
-    ast::{AExpr, Array, BExpr, Int, Target, Variable},
+    analysis::{Direction, Lattice, MonotoneFramework},
+    ast::{AExpr, AExprKind, Array, BExpr, BExprKind, Int, Target, Variable},
     interpreter::InterpreterError,
     pg::{Action, Edge, ProgramGraph},
 };
@@ -69,7 +69,7 @@ impl Default for Signs {
 
 impl std::fmt::Display for Signs {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{{{}}}", self.iter().format(", "))
+        write!(f, "{{{}}}", self.signs().format(", "))
     }
 }
 
@@ -89,7 +89,7 @@ fn signs_as_json() {
     use std::collections::BTreeSet;
     assert_eq!(
         serde_json::to_string(&Signs::ALL).unwrap(),
-        serde_json::to_string(&Signs::ALL.iter().collect::<BTreeSet<_>>()).unwrap()
+        serde_json::to_string(&Signs::ALL.signs().collect::<BTreeSet<_>>()).unwrap()
     );
 }
 
@@ -137,7 +137,7 @@ impl Default for Bools {
 
 impl std::fmt::Display for Bools {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{{{}}}", self.iter().format(", "))
+        write!(f, "{{{}}}", self.bools().format(", "))
     }
 }
 
@@ -157,7 +157,7 @@ fn bools_as_json() {
     use std::collections::BTreeSet;
     assert_eq!(
         serde_json::to_string(&Bools::ALL).unwrap(),
-        serde_json::to_string(&Bools::ALL.iter().collect::<BTreeSet<_>>()).unwrap()
+        serde_json::to_string(&Bools::ALL.bools().collect::<BTreeSet<_>>()).unwrap()
     );
 }
 
@@ -172,7 +172,7 @@ impl From<bool> for Bools {
 
 impl Bools {
     pub fn bools(self) -> impl Iterator<Item = bool> + Clone {
-        [true, false]
+        [false, true]
             .into_iter()
             .filter(move |&s| self.contains(s.into()))
     }
@@ -280,7 +280,7 @@ impl<T, A> Memory<T, A> {
         Self { variables, arrays }
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = MemoryRef<T, A>> + Clone {
+    pub fn iter(&self) -> impl Iterator<Item = MemoryRef<'_, T, A>> + Clone {
         chain!(
             self.variables
                 .iter()
@@ -306,10 +306,39 @@ impl<T, A> Memory<T, A> {
     }
 }
 
+impl<T, A> Lattice for Memory<T, A>
+where
+    T: Lattice,
+    A: Lattice,
+{
+    fn bottom() -> Self {
+        Memory {
+            variables: Lattice::bottom(),
+            arrays: Lattice::bottom(),
+        }
+    }
+
+    fn lub_extend(&mut self, other: &Self) {
+        self.variables.lub_extend(&other.variables);
+        self.arrays.lub_extend(&other.arrays);
+    }
+
+    fn lub(&self, other: &Self) -> Self {
+        Memory {
+            variables: self.variables.lub(&other.variables),
+            arrays: self.arrays.lub(&other.arrays),
+        }
+    }
+
+    fn contains(&self, other: &Self) -> bool {
+        self.variables.contains(&other.variables) && self.arrays.contains(&other.arrays)
+    }
+}
+
 impl MonotoneFramework for SignAnalysis {
     type Domain = HashSet<SignMemory>;
 
-    fn semantic(&self, _pg: &ProgramGraph, e: &Edge, prev: &Self::Domain) -> Self::Domain {
+    fn semantic(&self, pg: &ProgramGraph, e: &Edge, prev: &Self::Domain) -> Self::Domain {
         match e.action() {
             Action::Assignment(Target::Variable(var), x) => prev
                 .iter()
@@ -355,6 +384,7 @@ impl MonotoneFramework for SignAnalysis {
                 .filter(|mem| b.semantics_sign(mem).contains(Bools::TRUE))
                 .cloned()
                 .collect(),
+            Action::Block(_) => self.transfer(pg, e, prev),
         }
     }
 
@@ -367,15 +397,15 @@ impl MonotoneFramework for SignAnalysis {
     }
 }
 
-fn cartesian_flat_map<'a, L: 'a, R: 'a, T: Clone, Q>(
+fn cartesian_flat_map<'a, L, R, T: Clone, Q>(
     l: L,
     r: R,
     f: impl Fn(T, Option<T>) -> Q + 'a,
 ) -> impl Iterator<Item = Q> + 'a
 where
-    L: IntoIterator<Item = T> + Clone,
+    L: IntoIterator<Item = T> + Clone + 'a,
     L::IntoIter: Clone,
-    R: IntoIterator<Item = T> + Clone,
+    R: IntoIterator<Item = T> + Clone + 'a,
     R::IntoIter: Clone,
 {
     if r.clone().into_iter().next().is_none() {
@@ -391,9 +421,9 @@ where
 
 impl BExpr {
     fn semantics_sign(&self, mem: &SignMemory) -> Bools {
-        match self {
-            BExpr::Bool(b) => [*b].into_iter().collect(),
-            BExpr::Rel(l, op, r) => {
+        match &self.kind {
+            BExprKind::Bool(b) => [*b].into_iter().collect(),
+            BExprKind::Rel(l, op, r) => {
                 let l = l.semantics_sign(mem);
                 let r = r.semantics_sign(mem);
                 cartesian_flat_map(
@@ -404,7 +434,7 @@ impl BExpr {
                 .flatten()
                 .collect()
             }
-            BExpr::Logic(l, op, r) => {
+            BExprKind::Logic(l, op, r) => {
                 let l = l.semantics_sign(mem);
                 let r = r.semantics_sign(mem);
                 cartesian_flat_map(l.bools(), r.bools(), |l, r| {
@@ -413,8 +443,8 @@ impl BExpr {
                 .flatten()
                 .collect()
             }
-            BExpr::Not(b) => b.semantics_sign(mem).map(|i| !i),
-            BExpr::Quantified(_, _, _) => {
+            BExprKind::Not(b) => b.semantics_sign(mem).map(|i| !i),
+            BExprKind::Quantified(_, _, _) => {
                 todo!("tried to compute signs of a quantified expression")
             }
         }
@@ -443,15 +473,15 @@ impl std::ops::Neg for Sign {
 
 impl AExpr {
     fn semantics_sign(&self, mem: &SignMemory) -> Signs {
-        match self {
-            AExpr::Number(n) => [sign_of(*n)].into_iter().collect(),
-            AExpr::Reference(Target::Variable(x)) => [mem
+        match &self.kind {
+            AExprKind::Number(n) => [sign_of(*n)].into_iter().collect(),
+            AExprKind::Reference(Target::Variable(x)) => [mem
                 .get_var(x)
                 .copied()
                 .unwrap_or_else(|| panic!("could not get sign of '{x}'"))]
             .into_iter()
             .collect(),
-            AExpr::Binary(l, op, r) => cartesian_flat_map(
+            AExprKind::Binary(l, op, r) => cartesian_flat_map(
                 l.semantics_sign(mem)
                     .signs()
                     .flat_map(|x| x.representative()),
@@ -477,7 +507,7 @@ impl AExpr {
             })
             .map(sign_of)
             .collect(),
-            AExpr::Reference(Target::Array(arr, idx)) => {
+            AExprKind::Reference(Target::Array(arr, idx)) => {
                 let idx_signs = idx.semantics_sign(mem);
                 if idx_signs.intersects(Signs::ZERO | Signs::POSITIVE) {
                     if let Some(arr) = mem.arrays.get(arr) {
@@ -489,8 +519,8 @@ impl AExpr {
                     Default::default()
                 }
             }
-            AExpr::Minus(n) => n.semantics_sign(mem).map(|x| -x),
-            AExpr::Function(_) => todo!("sign of a function"),
+            AExprKind::Minus(n) => n.semantics_sign(mem).map(|x| -x),
+            AExprKind::Function(_) => todo!("sign of a function"),
         }
     }
 }
@@ -0,0 +1,406 @@
+//! Export of predicates to SMT-LIB 2 and validity checking with an external
+//! solver, such as [z3](https://github.com/Z3Prover/z3) or
+//! [cvc5](https://cvc5.github.io/).
+//!
+//! Variables are integer constants and arrays are constants in the theory of
+//! arrays from integers to integers. The functions `fac` and `fib`, powers
+//! with a non-constant exponent, and the length and count of arrays are
+//! uninterpreted. A condition found to be valid is therefore valid for every
+//! interpretation of them, while a counterexample may rely on an
+//! interpretation which does not agree with GCL. Likewise, integers are
+//! unbounded in SMT-LIB, so overflows are not taken into account.
+
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::ast::{
+    AExpr, AExprKind, AOp, BExpr, BExprKind, Function, LogicOp, Quantifier, RelOp, Target,
+};
+
+/// Declarations of the functions used by the translation, which are shared by
+/// every script. Division in GCL truncates towards zero, unlike `div`.
+const PREAMBLE: &str = "\
+(set-option :produce-models true)
+(set-logic ALL)
+(define-fun gcl_div ((a Int) (b Int)) Int
+  (ite (= (>= a 0) (> b 0)) (div (abs a) (abs b)) (- (div (abs a) (abs b)))))
+(declare-fun gcl_pow (Int Int) Int)
+(declare-fun gcl_fac (Int) Int)
+(declare-fun gcl_fib (Int) Int)
+(declare-fun gcl_length ((Array Int Int)) Int)
+(declare-fun gcl_count ((Array Int Int) Int) Int)
+";
+
+fn symbol<T>(t: &Target<T>) -> String {
+    format!("|{}|", t.name())
+}
+
+fn sort<T>(t: &Target<T>) -> &'static str {
+    match t {
+        Target::Variable(_) => "Int",
+        Target::Array(_, _) => "(Array Int Int)",
+    }
+}
+
+impl AExpr {
+    /// The expression as an SMT-LIB term
+    pub fn to_smtlib(&self) -> String {
+        match &self.kind {
+            AExprKind::Number(n) if *n < 0 => format!("(- {})", n.unsigned_abs()),
+            AExprKind::Number(n) => n.to_string(),
+            AExprKind::Reference(Target::Variable(x)) => format!("|{x}|"),
+            AExprKind::Reference(Target::Array(a, idx)) => {
+                format!("(select |{a}| {})", idx.to_smtlib())
+            }
+            AExprKind::Binary(l, AOp::Pow, r) => match &r.kind {
+                AExprKind::Number(0) => "1".to_string(),
+                AExprKind::Number(n @ 1..=64) => {
+                    let l = l.to_smtlib();
+                    if *n == 1 {
+                        l
+                    } else {
+                        format!("(* {})", std::iter::repeat_n(l, *n as usize).format(" "))
+                    }
+                }
+                _ => format!("(gcl_pow {} {})", l.to_smtlib(), r.to_smtlib()),
+            },
+            AExprKind::Binary(l, op, r) => {
+                let op = match op {
+                    AOp::Plus => "+",
+                    AOp::Minus => "-",
+                    AOp::Times => "*",
+                    AOp::Divide => "gcl_div",
+                    AOp::Pow => unreachable!("powers are translated above"),
+                };
+                format!("({op} {} {})", l.to_smtlib(), r.to_smtlib())
+            }
+            AExprKind::Minus(x) => format!("(- {})", x.to_smtlib()),
+            AExprKind::Function(f) => f.to_smtlib(),
+        }
+    }
+}
+
+impl Function {
+    /// The function application as an SMT-LIB term
+    pub fn to_smtlib(&self) -> String {
+        match self {
+            Function::Division(a, b) => format!("(gcl_div {} {})", a.to_smtlib(), b.to_smtlib()),
+            Function::Min(a, b) => {
+                let (a, b) = (a.to_smtlib(), b.to_smtlib());
+                format!("(ite (<= {a} {b}) {a} {b})")
+            }
+            Function::Max(a, b) => {
+                let (a, b) = (a.to_smtlib(), b.to_smtlib());
+                format!("(ite (>= {a} {b}) {a} {b})")
+            }
+            Function::Count(a, x) | Function::LogicalCount(a, x) => {
+                format!("(gcl_count |{a}| {})", x.to_smtlib())
+            }
+            Function::Length(a) | Function::LogicalLength(a) => format!("(gcl_length |{a}|)"),
+            Function::Fac(x) => format!("(gcl_fac {})", x.to_smtlib()),
+            Function::Fib(x) => format!("(gcl_fib {})", x.to_smtlib()),
+        }
+    }
+}
+
+impl BExpr {
+    /// The predicate as an SMT-LIB formula
+    pub fn to_smtlib(&self) -> String {
+        match &self.kind {
+            BExprKind::Bool(b) => b.to_string(),
+            BExprKind::Rel(l, RelOp::Ne, r) => {
+                format!("(not (= {} {}))", l.to_smtlib(), r.to_smtlib())
+            }
+            BExprKind::Rel(l, op, r) => {
+                let op = match op {
+                    RelOp::Eq => "=",
+                    RelOp::Ne => unreachable!("disequalities are translated above"),
+                    RelOp::Gt => ">",
+                    RelOp::Ge => ">=",
+                    RelOp::Lt => "<",
+                    RelOp::Le => "<=",
+                };
+                format!("({op} {} {})", l.to_smtlib(), r.to_smtlib())
+            }
+            BExprKind::Logic(l, op, r) => {
+                let op = match op {
+                    LogicOp::And | LogicOp::Land => "and",
+                    LogicOp::Or | LogicOp::Lor => "or",
+                    LogicOp::Implies => "=>",
+                };
+                format!("({op} {} {})", l.to_smtlib(), r.to_smtlib())
+            }
+            BExprKind::Not(b) => format!("(not {})", b.to_smtlib()),
+            BExprKind::Quantified(q, x, b) => {
+                let q = match q {
+                    Quantifier::Exists => "exists",
+                    Quantifier::Forall => "forall",
+                };
+                format!("({q} (({} {})) {})", symbol(x), sort(x), b.to_smtlib())
+            }
+        }
+    }
+
+    /// A complete SMT-LIB script which is satisfiable exactly when the
+    /// predicate is not valid, in which case the model is a counterexample
+    pub fn validity_script(&self) -> String {
+        let declarations = self
+            .fv()
+            .into_iter()
+            .sorted()
+            .map(|t| format!("(declare-const {} {})\n", symbol(&t), sort(&t)))
+            .format("");
+        format!(
+            "{PREAMBLE}{declarations}(assert (not {}))\n(check-sat)\n(get-model)\n",
+            self.to_smtlib()
+        )
+    }
+}
+
+/// The solvers which can be used to check verification conditions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "Case")]
+pub enum Solver {
+    Z3,
+    Cvc5,
+}
+
+impl std::fmt::Display for Solver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Solver::Z3 => write!(f, "z3"),
+            Solver::Cvc5 => write!(f, "cvc5"),
+        }
+    }
+}
+
+/// The outcome of checking a single verification condition
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "Case")]
+pub enum VcVerdict {
+    /// The condition holds in every memory
+    Valid,
+    /// The condition does not hold in the memory given by the model
+    Invalid { model: String },
+    /// The solver gave up, for example because of a timeout
+    Unknown { reason: String },
+}
+
+#[derive(Debug, Error)]
+pub enum SmtError {
+    #[error("failed to run the solver `{program}`: {source}")]
+    Spawn {
+        program: String,
+        source: std::io::Error,
+    },
+    #[error("failed to communicate with the solver: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("the solver reported an error: {message}")]
+    Solver { message: String },
+    #[error("unexpected response from the solver: {response:?}")]
+    UnexpectedResponse { response: String },
+}
+
+/// A locally installed solver, run once for every verification condition with
+/// the script given over stdin
+#[derive(Debug, Clone)]
+pub struct SmtSolver {
+    solver: Solver,
+    program: PathBuf,
+    timeout: Option<Duration>,
+}
+
+impl SmtSolver {
+    /// Uses the binary of the solver found on the `PATH`
+    pub fn new(solver: Solver) -> SmtSolver {
+        SmtSolver {
+            solver,
+            program: solver.to_string().into(),
+            timeout: None,
+        }
+    }
+    pub fn program(self, program: impl Into<PathBuf>) -> Self {
+        Self {
+            program: program.into(),
+            ..self
+        }
+    }
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    fn args(&self) -> Vec<String> {
+        match self.solver {
+            Solver::Z3 => ["-in".to_string(), "-smt2".to_string()]
+                .into_iter()
+                .chain(self.timeout.map(|t| format!("-T:{}", t.as_secs().max(1))))
+                .collect(),
+            Solver::Cvc5 => ["--lang".to_string(), "smt2".to_string()]
+                .into_iter()
+                .chain(self.timeout.map(|t| format!("--tlimit={}", t.as_millis())))
+                .collect(),
+        }
+    }
+
+    /// Checks whether `vc` holds in every memory
+    pub fn check_validity(&self, vc: &BExpr) -> Result<VcVerdict, SmtError> {
+        let mut child = Command::new(&self.program)
+            .args(self.args())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|source| SmtError::Spawn {
+                program: self.program.display().to_string(),
+                source,
+            })?;
+        child
+            .stdin
+            .take()
+            .expect("stdin of the solver is piped")
+            .write_all(vc.validity_script().as_bytes())?;
+        let output = child.wait_with_output()?;
+        let verdict = parse_response(&String::from_utf8_lossy(&output.stdout));
+        match verdict {
+            // NOTE: There is no model to get when the condition is valid, which
+            // makes the solver exit with an error
+            Ok(VcVerdict::Valid) | Err(SmtError::Solver { .. }) => verdict,
+            _ if output.status.success() => verdict,
+            _ => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                Err(SmtError::Solver {
+                    message: if stderr.trim().is_empty() {
+                        format!("the solver exited with {}", output.status)
+                    } else {
+                        stderr.trim().to_string()
+                    },
+                })
+            }
+        }
+    }
+
+    /// Checks every verification condition, stopping at the first error
+    pub fn check_all<'a>(
+        &self,
+        vcs: impl IntoIterator<Item = &'a BExpr>,
+    ) -> Result<Vec<VcVerdict>, SmtError> {
+        vcs.into_iter().map(|vc| self.check_validity(vc)).collect()
+    }
+}
+
+/// Interprets the output of a [validity script](BExpr::validity_script). The
+/// first line is the answer to `(check-sat)`, and when it is `sat` the rest is
+/// the model.
+fn parse_response(response: &str) -> Result<VcVerdict, SmtError> {
+    let response = response.trim();
+    let (first, rest) = response.split_once('\n').unwrap_or((response, ""));
+    match first.trim() {
+        "unsat" => Ok(VcVerdict::Valid),
+        "sat" => Ok(VcVerdict::Invalid {
+            model: rest.trim().to_string(),
+        }),
+        reason @ ("unknown" | "timeout") => Ok(VcVerdict::Unknown {
+            reason: reason.to_string(),
+        }),
+        line if line.starts_with("(error") => Err(SmtError::Solver {
+            message: line.to_string(),
+        }),
+        _ => Err(SmtError::UnexpectedResponse {
+            response: response.to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse::parse_predicate;
+
+    use super::{parse_response, SmtError, SmtSolver, Solver, VcVerdict};
+
+    #[test]
+    fn validity_script_declares_and_negates() -> miette::Result<()> {
+        let vc = parse_predicate("forall _i :: (0 <= _i & _i < n) ==> A[n] <= _i * fac(n) / -2")?;
+        let script = vc.validity_script();
+
+        assert!(script.contains("(declare-const |n| Int)\n"));
+        assert!(script.contains("(declare-const |A| (Array Int Int))\n"));
+        assert!(!script.contains("(declare-const |_i|"));
+        assert!(script.contains(
+            "(assert (not (forall ((|_i| Int)) (=> (and (<= 0 |_i|) (< |_i| |n|)) \
+             (<= (select |A| |n|) (gcl_div (* |_i| (gcl_fac |n|)) (- 2)))))))"
+        ));
+        assert!(script.ends_with("(check-sat)\n(get-model)\n"));
+
+        assert_eq!(
+            parse_response("unsat\n(error \"no model\")").unwrap(),
+            VcVerdict::Valid
+        );
+        assert_eq!(
+            parse_response("sat\n(\n  (define-fun |n| () Int 1)\n)\n").unwrap(),
+            VcVerdict::Invalid {
+                model: "(\n  (define-fun |n| () Int 1)\n)".to_string()
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn z3_checks_validity() -> miette::Result<()> {
+        if std::process::Command::new("z3")
+            .arg("-version")
+            .output()
+            .is_err()
+        {
+            eprintln!("skipping, as z3 is not installed");
+            return Ok(());
+        }
+        let z3 = SmtSolver::new(Solver::Z3);
+
+        let valid = parse_predicate("x > 0 ==> x + 1 > 1")?;
+        assert_eq!(z3.check_validity(&valid).unwrap(), VcVerdict::Valid);
+
+        let invalid = parse_predicate("x > 0 ==> x > 1")?;
+        match z3.check_validity(&invalid).unwrap() {
+            VcVerdict::Invalid { model } => assert!(model.contains("|x|"), "{model}"),
+            verdict => panic!("expected a counterexample, but got {verdict:?}"),
+        }
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failing_solvers_are_reported() -> miette::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let program = std::env::temp_dir().join(format!("failing-solver-{}", std::process::id()));
+        std::fs::write(
+            &program,
+            "#!/bin/sh\ncat > /dev/null\necho sat\necho 'out of memory' >&2\nexit 3\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let solver = SmtSolver::new(Solver::Z3).program(&program);
+        let result = solver.check_validity(&parse_predicate("x > 0")?);
+        std::fs::remove_file(&program).unwrap();
+        match result {
+            Err(SmtError::Solver { message }) => assert_eq!(message, "out of memory"),
+            result => panic!("expected the solver to fail, but got {result:?}"),
+        }
+
+        Ok(())
+    }
+}
@@ -2,8 +2,9 @@ use axum::Json;
 use checkr::{
     env::{graph::GraphEnvInput, Analysis, Environment, GraphEnv, Markdown},
     miette,
-    pg::Determinism,
+    pg::{Determinism, GraphFormat},
 };
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use tracing::error;
 use typeshare::typeshare;
@@ -21,6 +22,10 @@ pub async fn dot(Json((deterministic, src)): Json<(bool, String)>) -> Json<Strin
     let Ok(cmds) = checkr::parse::parse_commands(&src) else {
         return "Parse error".to_string().into()
     };
+    let errors = checkr::check::check(&cmds);
+    if !errors.is_empty() {
+        return format!("Invalid program: {}", errors.iter().format("; ")).into();
+    }
     GraphEnv
         .run(
             &cmds,
@@ -30,10 +35,11 @@ pub async fn dot(Json((deterministic, src)): Json<(bool, String)>) -> Json<Strin
                 } else {
                     Determinism::NonDeterministic
                 },
+                format: GraphFormat::Dot,
             },
         )
         .expect("the input was just given, so it should work")
-        .dot
+        .graph
         .into()
 }
 
@@ -63,6 +69,10 @@ pub async fn generate_input_for(
             return None.into();
         }
     };
+    if let Some(err) = checkr::check::check(&cmds).into_iter().next() {
+        error!("Invalid program: {:?}", miette::Error::new(err));
+        return None.into();
+    }
     use rand::SeedableRng;
     let mut rng = rand::rngs::SmallRng::from_entropy();
     let json = analysis.gen_input(&cmds, &mut rng);
@@ -90,6 +100,10 @@ pub async fn run_analysis(Json((src, input)): Json<(String, Input)>) -> Json<Opt
             return None.into();
         }
     };
+    if let Some(err) = checkr::check::check(&cmds).into_iter().next() {
+        error!("Invalid program: {:?}", miette::Error::new(err));
+        return None.into();
+    }
     let json = if let Ok(json) = input.analysis.run(&cmds, input_json) {
         json
     } else {
@@ -13,6 +13,9 @@ pub enum ValidationResult {
     CorrectNonTerminated {
         iterations: u32,
     },
+    ProbablyCorrect {
+        reason: String,
+    },
     Mismatch {
         reason: String,
     },
@@ -37,6 +40,7 @@ impl From<checkr::env::ValidationResult> for ValidationResult {
             VR::CorrectNonTerminated { iterations } => ValidationResult::CorrectNonTerminated {
                 iterations: iterations as _,
             },
+            VR::ProbablyCorrect { reason } => ValidationResult::ProbablyCorrect { reason },
             VR::Mismatch { reason } => ValidationResult::Mismatch { reason },
             VR::TimeOut => ValidationResult::TimeOut,
         }
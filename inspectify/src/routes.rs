@@ -11,13 +11,14 @@ use axum::{
     Json, Router,
 };
 use checkr::{
+    ast::Commands,
     env::{graph::GraphEnvInput, Analysis, EnvError, GraphEnv, Markdown},
-    pg::Determinism,
+    miette::{self, GraphicalReportHandler, GraphicalTheme},
+    pg::{Determinism, GraphFormat},
 };
 use futures::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
 use tower_http::cors::CorsLayer;
-use tracing::error;
 
 use crate::{core, ApplicationState, CompilationStatus, ValidationResult};
 
@@ -138,12 +139,13 @@ pub async fn graph(
                     true => Determinism::Deterministic,
                     false => Determinism::NonDeterministic,
                 },
+                format: GraphFormat::Dot,
             },
         )
         .await
     {
         Ok(output) => Json(GraphResponse::Graph {
-            dot: output.parsed.dot,
+            dot: output.parsed.graph,
         }),
         Err(err) => Json(GraphResponse::Error {
             error: format!("{err:#?}"),
@@ -182,16 +184,17 @@ pub async fn analyze(
         .await
     {
         Ok(exec_output) => {
-            let cmds = match checkr::parse::parse_commands(&cmds) {
+            // NOTE: The output cannot be validated against a program which is
+            // not valid itself, so the problem is reported in its place
+            let cmds = match parse_and_check(&cmds) {
                 Ok(cmds) => cmds,
-                Err(err) => {
-                    error!("Parse error: {:?}", checkr::miette::Error::new(err));
+                Err(diagnostic) => {
                     return Json(AnalysisResponse {
-                        stdout: "".to_string(),
-                        stderr: "".to_string(),
+                        stdout: String::from_utf8(exec_output.output.stdout).unwrap(),
+                        stderr: String::from_utf8(exec_output.output.stderr).unwrap(),
                         parsed_markdown: None,
-                        took: Duration::ZERO,
-                        validation_result: None,
+                        took: exec_output.took,
+                        validation_result: Some(ValidationResult::Mismatch { reason: diagnostic }),
                     });
                 }
             };
@@ -228,6 +231,9 @@ pub async fn analyze(
                                         error: err.to_string(),
                                     }
                                 }
+                                EnvError::InvalidProgram { .. } => ValidationResult::Mismatch {
+                                    reason: err.to_string(),
+                                },
                             }),
                         });
                     }
@@ -285,3 +291,23 @@ pub async fn analyze(
 
     Json(output)
 }
+
+/// Parses and checks the program, rendering the first problem found as a
+/// diagnostic pointing into the source
+fn parse_and_check(src: &str) -> Result<Commands, String> {
+    let render = |report: miette::Report| {
+        let mut rendered = String::new();
+        GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor())
+            .render_report(&mut rendered, report.as_ref())
+            .expect("rendering into a string does not fail");
+        rendered
+    };
+
+    let cmds = checkr::parse::parse_commands(src).map_err(|err| render(err.into()))?;
+    if let Some(err) = checkr::check::check(&cmds).into_iter().next() {
+        return Err(render(
+            miette::Report::new(err).with_source_code(src.to_string()),
+        ));
+    }
+    Ok(cmds)
+}
@@ -543,7 +543,8 @@ const computeIndicatorState = ({
 
   if (
     response.validation_result.type == "CorrectTerminated" ||
-    response.validation_result.type == "CorrectNonTerminated"
+    response.validation_result.type == "CorrectNonTerminated" ||
+    response.validation_result.type == "ProbablyCorrect"
   )
     return IndicatorState.Correct;
 
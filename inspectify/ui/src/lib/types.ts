@@ -46,7 +46,14 @@ export enum Analysis {
 	Interpreter = "Interpreter",
 	ProgramVerification = "ProgramVerification",
 	Sign = "Sign",
+	Interval = "Interval",
+	LiveVariables = "LiveVariables",
+	ReachingDefinitions = "ReachingDefinitions",
+	AvailableExpressions = "AvailableExpressions",
+	VeryBusyExpressions = "VeryBusyExpressions",
+	Worklist = "Worklist",
 	Security = "Security",
+	ProofOutline = "ProofOutline",
 }
 
 export type CompilerState = 
@@ -61,6 +68,9 @@ export type ValidationResult =
 	| { type: "CorrectTerminated", content?: undefined }
 	| { type: "CorrectNonTerminated", content: {
 	iterations: number;
+}}
+	| { type: "ProbablyCorrect", content: {
+	reason: string;
 }}
 	| { type: "Mismatch", content: {
 	reason: string;